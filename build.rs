@@ -0,0 +1,77 @@
+use std::{env, fs, path::Path, process::Command};
+
+/// Dependencies whose versions are reported by `hoc version --verbose`.
+const REPORTED_DEPENDENCIES: [&str; 3] = ["ssh2", "crossterm", "tinytemplate"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    watch_git_head();
+
+    let git_commit = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    println!("cargo:rustc-env=HOC_GIT_COMMIT={git_commit}");
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    println!("cargo:rustc-env=HOC_RUSTC_VERSION={rustc_version}");
+
+    let target = env::var("TARGET").unwrap_or_else(|_| "unknown".to_owned());
+    println!("cargo:rustc-env=HOC_TARGET={target}");
+
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let dependencies = REPORTED_DEPENDENCIES
+        .iter()
+        .map(|name| format!("{name} {}", locked_version(&lock, name)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!("cargo:rustc-env=HOC_DEPENDENCIES={dependencies}");
+}
+
+/// Reruns the build script when a commit is checked out or made. Outside of a git repository,
+/// nothing is watched, since a missing path would make the script rerun on every build.
+fn watch_git_head() {
+    let git_dir = Path::new(".git");
+    let Ok(head) = fs::read_to_string(git_dir.join("HEAD")) else {
+        return;
+    };
+    println!("cargo:rerun-if-changed=.git/HEAD");
+
+    // HEAD points to a branch, whose ref changes with new commits, unless it is detached.
+    if let Some(reference) = head.trim().strip_prefix("ref: ") {
+        if git_dir.join(reference).exists() {
+            println!("cargo:rerun-if-changed=.git/{reference}");
+        }
+        if git_dir.join("packed-refs").exists() {
+            println!("cargo:rerun-if-changed=.git/packed-refs");
+        }
+    }
+}
+
+fn command_output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|stdout| stdout.trim().to_owned())
+        .filter(|stdout| !stdout.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// Finds the version of the package in the lock file, without pulling in a TOML parser.
+fn locked_version(lock: &str, name: &str) -> String {
+    let name_line = format!("name = \"{name}\"");
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == name_line {
+            if let Some(version) = lines
+                .next()
+                .and_then(|line| line.strip_prefix("version = \""))
+                .and_then(|version| version.strip_suffix('"'))
+            {
+                return version.to_owned();
+            }
+        }
+    }
+    "unknown".to_owned()
+}
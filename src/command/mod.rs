@@ -1,6 +1,7 @@
-use std::net::IpAddr;
+use std::{net::IpAddr, path::PathBuf, time::Duration};
 
 use clap::{CommandFactory, Parser};
+use log_facade::Level;
 
 use crate::{cidr::Cidr, prelude::*};
 pub use run::*;
@@ -52,6 +53,10 @@ commands_summary! {
             default = "5m0s",
             help = "Time to wait for any individual Kubernetes operation",
         }
+        hocfile {
+            default = "hocfile.yaml",
+            help = "Path to the hocfile describing the application",
+        }
     }
 }
 
@@ -86,6 +91,17 @@ pub enum Command {
     Node(NodeCommand),
 
     Deploy(DeployCommand),
+
+    History(HistoryCommand),
+
+    Logs(LogsCommand),
+
+    Export(ExportCommand),
+
+    Import(ImportCommand),
+
+    #[clap(subcommand)]
+    Context(ContextCommand),
 }
 
 /// Debug functions
@@ -176,6 +192,86 @@ pub struct DeployCommand {
         default_value = default::deploy::timeout(),
     )]
     timeout: String,
+
+    #[clap(
+        help = help::deploy::hocfile(),
+        long,
+        default_value = default::deploy::hocfile(),
+    )]
+    hocfile: PathBuf,
+
+    /// Print the steps of the deployment without running them
+    #[clap(long)]
+    list_steps: bool,
+}
+
+/// List previous command runs
+#[derive(Parser)]
+#[clap(name = "history")]
+pub struct HistoryCommand {
+    /// Only list runs of this command, e.g. `node deploy`
+    #[clap(long)]
+    command: Option<String>,
+}
+
+/// Print the log file of the most recent run
+#[derive(Parser)]
+#[clap(name = "logs")]
+pub struct LogsCommand {
+    /// Keep printing lines as they are written to the log file
+    #[clap(short, long)]
+    follow: bool,
+
+    /// Only print lines logged within this duration, e.g. `30s`, `15m` or `1h30m`
+    #[clap(long, value_parser = logs::parse_duration)]
+    since: Option<Duration>,
+
+    /// Only print lines at this level or more severe
+    #[clap(long)]
+    level: Option<Level>,
+}
+
+/// Export the context to an archive, to be imported on another machine
+#[derive(Parser)]
+#[clap(name = "export")]
+pub struct ExportCommand {
+    /// Path of the archive to write
+    path: PathBuf,
+
+    /// Mask the values of the keys given by `--redact`
+    #[clap(long)]
+    redacted: bool,
+
+    /// Leave out the keys under the global prefix `$`, along with their files
+    #[clap(long)]
+    exclude_global: bool,
+}
+
+/// Import a context from an archive created by the `export` command
+#[derive(Parser)]
+#[clap(name = "import")]
+pub struct ImportCommand {
+    /// Path of the archive to read
+    path: PathBuf,
+}
+
+/// Inspect the context
+#[derive(clap::Subcommand)]
+pub enum ContextCommand {
+    List(ContextListCommand),
+}
+
+/// List the values of the keys matching a template
+#[derive(Parser)]
+#[clap(name = "context-list")]
+pub struct ContextListCommand {
+    /// Key template to match, where `*` matches within a key component and `**` across them
+    #[clap(default_value = "**")]
+    template: String,
+
+    /// Format of the output, defaulting to `table` on a terminal and `yaml` otherwise
+    #[clap(long, value_enum)]
+    output_format: Option<context::list::OutputFormat>,
 }
 
 /// Manage an SD card
@@ -187,7 +283,11 @@ pub enum SdCardCommand {
 /// Prepare an SD card for a node to be deployed
 #[derive(Parser)]
 #[clap(name = "sd-card-prepare")]
-pub struct SdCardPrepareCommand {}
+pub struct SdCardPrepareCommand {
+    /// Show the changes to each boot configuration file and confirm them before writing
+    #[clap(long)]
+    diff: bool,
+}
 
 /// Manage a node
 #[derive(clap::Subcommand)]
@@ -202,6 +302,10 @@ pub enum NodeCommand {
 pub struct NodeDeployCommand {
     #[clap(help = help::node_deploy::node_name())]
     node_name: String,
+
+    /// Run every section again, including those completed by a previous run
+    #[clap(long)]
+    fresh: bool,
 }
 
 /// Upgrades a node to use the latest features
@@ -213,6 +317,35 @@ pub struct NodeUpgradeCommand {
 }
 
 impl Command {
+    /// The name of the command as it is invoked, or `None` if runs of it should not be recorded.
+    pub fn history_name(&self) -> Option<&'static str> {
+        use Command::*;
+
+        let name = match self {
+            Version(_) | History(_) | Logs(_) | Export(_) | Import(_) | Context(_) => return None,
+            Upgrade(_) => "upgrade",
+            Init(_) => "init",
+            SdCard(SdCardCommand::Prepare(_)) => "sd-card prepare",
+            Node(NodeCommand::Deploy(_)) => "node deploy",
+            Node(NodeCommand::Upgrade(_)) => "node upgrade",
+            Deploy(_) => "deploy",
+            #[cfg(debug_assertions)]
+            Debug(_) => return None,
+        };
+
+        Some(name)
+    }
+
+    /// Whether the command only reads the context, in which case it does not need to lock it.
+    pub fn is_read_only(&self) -> bool {
+        use Command::*;
+
+        matches!(
+            self,
+            Version(_) | History(_) | Logs(_) | Export(_) | Context(_)
+        )
+    }
+
     #[throws(anyhow::Error)]
     pub fn run(self) {
         use Command::*;
@@ -261,10 +394,12 @@ impl Command {
             }
 
             SdCard(sd_card_command) => match sd_card_command {
-                SdCardCommand::Prepare(_prepare_command) => {
+                SdCardCommand::Prepare(prepare_command) => {
                     cmd_diagnostics!(SdCardPrepareCommand);
 
-                    sd_card::prepare::run()?;
+                    arg_diagnostics!(diff, prepare_command.diff);
+
+                    sd_card::prepare::run(prepare_command.diff)?;
                 }
             },
 
@@ -273,8 +408,9 @@ impl Command {
                     cmd_diagnostics!(NodeDeployCommand);
 
                     arg_diagnostics!(node_name, deploy_command.node_name);
+                    arg_diagnostics!(fresh, deploy_command.fresh);
 
-                    node::deploy::run(deploy_command.node_name)?;
+                    node::deploy::run(deploy_command.node_name, deploy_command.fresh)?;
                 }
                 NodeCommand::Upgrade(upgrade_command) => {
                     cmd_diagnostics!(NodeUpgradeCommand);
@@ -289,10 +425,64 @@ impl Command {
                 cmd_diagnostics!(DeployCommand);
 
                 arg_diagnostics!(timeout, deploy_command.timeout);
+                arg_diagnostics!(hocfile, deploy_command.hocfile.to_string_lossy());
+                arg_diagnostics!(list_steps, deploy_command.list_steps);
+
+                deploy::run(
+                    deploy_command.timeout,
+                    deploy_command.hocfile,
+                    deploy_command.list_steps,
+                )?;
+            }
+
+            History(history_command) => {
+                cmd_diagnostics!(HistoryCommand);
 
-                deploy::run(deploy_command.timeout)?;
+                arg_diagnostics!(history_command.command);
+
+                history::run(history_command.command);
+            }
+
+            Logs(logs_command) => {
+                cmd_diagnostics!(LogsCommand);
+
+                arg_diagnostics!(follow, logs_command.follow);
+
+                logs::run(logs_command.follow, logs_command.since, logs_command.level)?;
+            }
+
+            Export(export_command) => {
+                cmd_diagnostics!(ExportCommand);
+
+                arg_diagnostics!(path, export_command.path.to_string_lossy());
+                arg_diagnostics!(redacted, export_command.redacted);
+                arg_diagnostics!(exclude_global, export_command.exclude_global);
+
+                export::run(
+                    export_command.path,
+                    export_command.redacted,
+                    export_command.exclude_global,
+                )?;
             }
 
+            Import(import_command) => {
+                cmd_diagnostics!(ImportCommand);
+
+                arg_diagnostics!(path, import_command.path.to_string_lossy());
+
+                import::run(import_command.path)?;
+            }
+
+            Context(context_command) => match context_command {
+                ContextCommand::List(list_command) => {
+                    cmd_diagnostics!(ContextListCommand);
+
+                    arg_diagnostics!(template, list_command.template);
+
+                    context::list::run(list_command.template, list_command.output_format)?;
+                }
+            },
+
             #[cfg(debug_assertions)]
             Debug(debug_command) => match debug_command {
                 DebugCommand::Progress(_progress_command) => {
@@ -0,0 +1,203 @@
+use std::io;
+
+use anyhow::Error;
+use crossterm::tty::IsTty;
+
+use crate::{
+    context::{
+        key::{self, Key, KeyOwned},
+        kv::{self, Item, REDACTED_VALUE},
+        Context,
+    },
+    log,
+    prelude::*,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The matched item as YAML
+    Yaml,
+    /// The matched item as JSON
+    Json,
+    /// A row for each matched key and its value
+    Table,
+}
+
+impl OutputFormat {
+    /// Tables when printing to a terminal, or YAML otherwise.
+    fn detect() -> Self {
+        if io::stdout().is_tty() {
+            Self::Table
+        } else {
+            Self::Yaml
+        }
+    }
+}
+
+#[throws(Error)]
+pub fn run(template: String, output_format: Option<OutputFormat>) {
+    let template = Key::new(&template);
+    let kv = Context::get_or_init().kv();
+
+    // Printed directly, since the output is the point of the command, also when quiet.
+    match output_format.unwrap_or_else(OutputFormat::detect) {
+        OutputFormat::Table => {
+            let rows = table_rows(kv.get_keyed(template)?, kv::is_redacted)?;
+            for line in log::format_table(&["KEY", "VALUE"], &rows) {
+                println!("{line}");
+            }
+        }
+        format => {
+            let mut item = kv.get_item(template)?;
+            redact_item(
+                &mut item,
+                key::get_known_prefix_for_template(template),
+                &kv::is_redacted,
+            );
+            println!("{}", format_item(&item, format)?);
+        }
+    }
+}
+
+/// Masks the values of redacted keys in `item`, which is found at `key`.
+fn redact_item(item: &mut Item, key: &Key, is_redacted: &impl Fn(&Key) -> bool) {
+    let child_key = |component: &str| {
+        if key.as_str().is_empty() {
+            KeyOwned::from(component)
+        } else {
+            key.join(component)
+        }
+    };
+
+    match item {
+        Item::Value(_) => {
+            if is_redacted(key) {
+                *item = Item::from(REDACTED_VALUE);
+            }
+        }
+        Item::Array(array) => {
+            for (index, elem) in array.iter_mut().enumerate() {
+                redact_item(elem, &child_key(&index.to_string()), is_redacted);
+            }
+        }
+        Item::Map(map) => {
+            for (component, elem) in map.iter_mut() {
+                redact_item(elem, &child_key(component), is_redacted);
+            }
+        }
+    }
+}
+
+#[throws(Error)]
+fn format_item(item: &Item, output_format: OutputFormat) -> String {
+    match output_format {
+        OutputFormat::Yaml => serde_yaml::to_string(item)?.trim_end().to_owned(),
+        OutputFormat::Json => serde_json::to_string_pretty(item)?,
+        OutputFormat::Table => unreachable!("tables are formatted by row"),
+    }
+}
+
+#[throws(Error)]
+fn table_rows(
+    keyed: Vec<(KeyOwned, Item)>,
+    is_redacted: impl Fn(&Key) -> bool,
+) -> Vec<Vec<String>> {
+    keyed
+        .into_iter()
+        .map(|(key, item)| {
+            let value = if is_redacted(&key) {
+                REDACTED_VALUE.to_owned()
+            } else {
+                item.to_output_string()?
+            };
+            Ok(vec![key.as_str().to_owned(), value])
+        })
+        .collect::<Result<_, Error>>()?
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn matched() -> Item {
+        Item::Map(IndexMap::from([(
+            "betsy".to_owned(),
+            Item::Map(IndexMap::from([
+                ("address".to_owned(), Item::from("10.0.0.1")),
+                ("port".to_owned(), Item::from(22u16)),
+            ])),
+        )]))
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn yaml_output() {
+        let output = format_item(&matched(), OutputFormat::Yaml)?;
+        assert_eq!(serde_yaml::from_str::<Item>(&output)?, matched());
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn json_output() {
+        let output = format_item(&matched(), OutputFormat::Json)?;
+        let json: serde_json::Value = serde_json::from_str(&output)?;
+        assert_eq!(json["betsy"]["address"], "10.0.0.1");
+        assert_eq!(json["betsy"]["port"], 22);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn table_output() {
+        let rows = table_rows(
+            vec![
+                (
+                    KeyOwned::from("nodes/betsy/address"),
+                    Item::from("10.0.0.1"),
+                ),
+                (KeyOwned::from("nodes/betsy/port"), Item::from(22u16)),
+            ],
+            |_| false,
+        )?;
+        assert_eq!(
+            rows,
+            [
+                ["nodes/betsy/address", "10.0.0.1"],
+                ["nodes/betsy/port", "22"],
+            ]
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn redacted_values_are_masked() {
+        let is_redacted = |key: &Key| key.as_str().ends_with("/address");
+
+        let mut item = matched();
+        redact_item(&mut item, Key::new("nodes"), &is_redacted);
+        for format in [OutputFormat::Yaml, OutputFormat::Json] {
+            let output = format_item(&item, format)?;
+            assert!(output.contains(REDACTED_VALUE));
+            assert!(!output.contains("10.0.0.1"));
+        }
+
+        let rows = table_rows(
+            vec![
+                (
+                    KeyOwned::from("nodes/betsy/address"),
+                    Item::from("10.0.0.1"),
+                ),
+                (KeyOwned::from("nodes/betsy/port"), Item::from(22u16)),
+            ],
+            is_redacted,
+        )?;
+        assert_eq!(
+            rows,
+            [
+                ["nodes/betsy/address", REDACTED_VALUE],
+                ["nodes/betsy/port", "22"],
+            ]
+        );
+    }
+}
@@ -1,35 +1,131 @@
 use std::time::Duration;
-use std::{fmt::Write, fs::File};
+use std::{
+    fmt::Write,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-use anyhow::Error;
+use indexmap::IndexMap;
 use serde::Deserialize;
 use serde::Serialize;
+use thiserror::Error;
 use tinytemplate::TinyTemplate;
 
-use crate::prelude::*;
+use crate::{context, log, prelude::*, process};
+
+/// The steps of the deployment, in the order they are run.
+const STEPS: [Step; 3] = [
+    Step {
+        name: "Deploy application",
+        description: |hocfile, timeout| {
+            format!(
+                "Install or upgrade the Helm release {} (timeout {timeout})",
+                hocfile.meta.name,
+            )
+        },
+        run: deploy_application,
+    },
+    Step {
+        name: "Wait on pods",
+        description: |hocfile, _| {
+            format!(
+                "Wait until the pods of {} are running and ready",
+                hocfile.meta.name,
+            )
+        },
+        run: |hocfile, _| wait_on_pods(hocfile),
+    },
+    Step {
+        name: "Test deployment",
+        description: |hocfile, timeout| {
+            format!(
+                "Run the Helm tests of {} (timeout {timeout})",
+                hocfile.meta.name,
+            )
+        },
+        run: test_deployment,
+    },
+];
+
+struct Step {
+    name: &'static str,
+    description: fn(&Hocfile, &str) -> String,
+    run: fn(&Hocfile, &str) -> Result<(), Error>,
+}
 
 #[throws(Error)]
-pub fn run(timeout: String) {
-    let file = open_hocfile()?;
+pub fn run(timeout: String, hocfile_path: PathBuf, list_steps: bool) {
+    let file = open_hocfile(&hocfile_path)?;
     let hocfile = parse_hocfile(file)?;
-    deploy_application(&hocfile, &timeout)?;
-    wait_on_pods(&hocfile)?;
-    test_deployment(&hocfile, &timeout)?;
+
+    run_steps(&STEPS, &hocfile, &timeout, list_steps)?;
+}
+
+/// Runs the steps in order, or only lists them if `list_steps` is set.
+#[throws(Error)]
+fn run_steps(steps: &[Step], hocfile: &Hocfile, timeout: &str, list_steps: bool) {
+    if list_steps {
+        log::table(
+            &["STEP", "NAME", "DESCRIPTION"],
+            &step_rows(steps, hocfile, timeout),
+        );
+        return;
+    }
+
+    for step in steps {
+        (step.run)(hocfile, timeout)?;
+    }
     report(&hocfile.meta.name);
 }
 
+fn step_rows(steps: &[Step], hocfile: &Hocfile, timeout: &str) -> Vec<Vec<String>> {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            vec![
+                (i + 1).to_string(),
+                step.name.to_owned(),
+                (step.description)(hocfile, timeout),
+            ]
+        })
+        .collect()
+}
+
 #[throws(Error)]
-fn open_hocfile() -> File {
+fn open_hocfile(path: &Path) -> File {
     progress!("Opening hocfile");
 
-    File::open("hocfile.yaml").context("hocfile not found in current directory")?
+    File::open(path).map_err(|err| Error::HocfileNotFound(path.to_owned(), err))?
 }
 
 #[throws(Error)]
 fn parse_hocfile(file: File) -> Hocfile {
     progress!("Parsing hocfile");
 
-    serde_yaml::from_reader(file)?
+    let hocfile: Hocfile = serde_yaml::from_reader(file)?;
+    validate_hocfile(&hocfile)?;
+    hocfile
+}
+
+/// Checks that the required fields are non-empty, since empty values would otherwise flow into
+/// the Helm commands and fail in confusing ways.
+#[throws(Error)]
+fn validate_hocfile(hocfile: &Hocfile) {
+    let fields = [
+        ("meta.name", &hocfile.meta.name),
+        ("meta.version", &hocfile.meta.version),
+        ("image.name", &hocfile.image.name),
+        ("service.domain", &hocfile.service.domain),
+    ];
+
+    for (field, value) in fields {
+        if value.trim().is_empty() {
+            throw!(Error::EmptyField(field));
+        }
+    }
 }
 
 #[throws(Error)]
@@ -63,19 +159,23 @@ fn deploy_application(hocfile: &Hocfile, timeout: &str) {
     };
 
     let chart = tt.render("chart", &context)?;
+    let env = render_env(hocfile)?;
 
     let registry: String = kv!("registry/prefix").get()?.convert()?;
 
     shell.run(process!("tee /helm/hoc-service/Chart.yaml" < ("{chart}")))?;
-    shell.run(process!(
-        "helm upgrade {name} /helm/hoc-service/ --install --atomic --timeout {timeout} \
-            --set image.repository={registry}/{image_name} \
-            --set ingress.host={host} \
-            --set service.port={port}",
-        host = hocfile.service.domain,
-        image_name = hocfile.image.name,
-        name = hocfile.meta.name,
-        port = hocfile.service.internal_port,
+    shell.run(with_env(
+        process!(
+            "helm upgrade {name} /helm/hoc-service/ --install --atomic --timeout {timeout} \
+                --set image.repository={registry}/{image_name} \
+                --set ingress.host={host} \
+                --set service.port={port}",
+            host = hocfile.service.domain,
+            image_name = hocfile.image.name,
+            name = hocfile.meta.name,
+            port = hocfile.service.internal_port,
+        ),
+        &env,
     ))?;
 
     shell.exit()?;
@@ -85,6 +185,7 @@ fn deploy_application(hocfile: &Hocfile, timeout: &str) {
 fn wait_on_pods(hocfile: &Hocfile) {
     progress!("Waiting on pods to be ready");
 
+    const ATTEMPTS: usize = 30;
     const JSON_PATH: &str = "'\
         {range .items[*]}\
             {.status.phase}{\"=\"}\
@@ -93,7 +194,7 @@ fn wait_on_pods(hocfile: &Hocfile) {
             {end}{\",\"}\
         {end}'";
 
-    for _ in 0..30 {
+    for _ in 0..ATTEMPTS {
         let output = process!(
             "kubectl get pods \
                 -l=app.kubernetes.io/managed-by!=Helm,\
@@ -113,33 +214,106 @@ fn wait_on_pods(hocfile: &Hocfile) {
         });
 
         if all_ready {
-            break;
+            return;
         };
 
         spin_sleep::sleep(Duration::from_secs(10));
     }
+
+    throw!(Error::PodsNotReady(ATTEMPTS));
 }
 
 #[throws(Error)]
 fn test_deployment(hocfile: &Hocfile, timeout: &str) {
     progress!("Testing deployment");
 
-    process!(
-        "helm test {name} --timeout {timeout}",
-        name = hocfile.meta.name,
+    let env = render_env(hocfile)?;
+    with_env(
+        process!(
+            "helm test {name} --timeout {timeout}",
+            name = hocfile.meta.name,
+        ),
+        &env,
     )
     .run()?;
 }
 
+/// Renders the environment variables of the hocfile, which may refer to the application name and
+/// version.
+#[throws(Error)]
+fn render_env(hocfile: &Hocfile) -> Vec<(String, String)> {
+    let mut tt = TinyTemplate::new();
+    for (name, value) in &hocfile.env {
+        tt.add_template(name, value)?;
+    }
+
+    let context = ChartContext {
+        name: &hocfile.meta.name,
+        app_version: &hocfile.meta.version,
+    };
+
+    let mut env = Vec::with_capacity(hocfile.env.len());
+    for name in hocfile.env.keys() {
+        env.push((name.clone(), tt.render(name, &context)?));
+    }
+    env
+}
+
+/// Sets the environment variables on the process, overriding any inherited ones.
+fn with_env(process: process::ProcessBuilder, env: &[(String, String)]) -> process::ProcessBuilder {
+    env.iter().fold(process, |process, (name, value)| {
+        process.env_var(name.clone(), Some(value.clone()))
+    })
+}
+
 fn report(application_name: &str) {
     info!("{application_name} has been successfully deployed");
 }
 
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Hocfile not found at {0:?}: {1}")]
+    HocfileNotFound(PathBuf, #[source] io::Error),
+
+    #[error("Invalid hocfile: {0}")]
+    Hocfile(#[from] serde_yaml::Error),
+
+    #[error("Invalid hocfile: {0} must not be empty")]
+    EmptyField(&'static str),
+
+    #[error("Failed to render chart: {0}")]
+    Template(#[from] tinytemplate::error::Error),
+
+    #[error("Pods were not ready after {0} attempts")]
+    PodsNotReady(usize),
+
+    #[error(transparent)]
+    Process(#[from] process::Error),
+
+    #[error(transparent)]
+    Context(#[from] context::Error),
+}
+
+impl Error {
+    /// The exit code for `hoc` to exit with, so scripts can tell failures apart.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::HocfileNotFound(..) | Self::Hocfile(_) | Self::EmptyField(_) => ExitCode::from(2),
+            Self::Template(_) => ExitCode::from(3),
+            Self::PodsNotReady(_) => ExitCode::from(4),
+            Self::Process(_) => ExitCode::from(5),
+            Self::Context(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Hocfile {
     meta: Meta,
     image: Image,
     service: Service,
+    #[serde(default)]
+    env: IndexMap<String, String>,
 }
 
 #[derive(Deserialize)]
@@ -165,3 +339,133 @@ struct ChartContext<'a> {
     name: &'a str,
     app_version: &'a str,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn custom_hocfile_path() {
+        let test_dir = util::TempDir::new()?;
+        let hocfile_path = test_dir.path().join("hocfile.yaml");
+        fs::write(
+            &hocfile_path,
+            "meta:\n  name: custom\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: custom.local\n  internalPort: 8080\n",
+        )?;
+
+        let hocfile = parse_hocfile(open_hocfile(&hocfile_path)?)?;
+
+        assert_eq!(hocfile.meta.name, "custom");
+        assert_eq!(hocfile.image.name, "custom-image");
+        assert_eq!(hocfile.service.internal_port, 8080);
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn hocfile_errors() {
+        let test_dir = util::TempDir::new()?;
+        let hocfile_path = test_dir.path().join("hocfile.yaml");
+
+        let res = open_hocfile(&hocfile_path);
+        assert!(matches!(res, Err(Error::HocfileNotFound(path, _)) if path == hocfile_path));
+
+        fs::write(&hocfile_path, "meta:\n  name: missing-fields\n")?;
+        let res = parse_hocfile(open_hocfile(&hocfile_path)?);
+        assert!(matches!(res, Err(Error::Hocfile(_))));
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn empty_hocfile_fields() {
+        let hocfile: Hocfile = serde_yaml::from_str(
+            "meta:\n  name: \"\"\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: custom.local\n  internalPort: 8080\n",
+        )?;
+        assert!(matches!(
+            validate_hocfile(&hocfile),
+            Err(Error::EmptyField("meta.name"))
+        ));
+
+        let hocfile: Hocfile = serde_yaml::from_str(
+            "meta:\n  name: custom\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: \"  \"\n  internalPort: 8080\n",
+        )?;
+        assert!(matches!(
+            validate_hocfile(&hocfile),
+            Err(Error::EmptyField("service.domain"))
+        ));
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn list_steps_in_order() {
+        let hocfile: Hocfile = serde_yaml::from_str(
+            "meta:\n  name: custom\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: custom.local\n  internalPort: 8080\n",
+        )?;
+
+        let steps = step_rows(&STEPS, &hocfile, "5m");
+        assert_eq!(steps.len(), 3);
+        for (i, step) in steps.iter().enumerate() {
+            assert_eq!(step[0], (i + 1).to_string());
+        }
+        assert_eq!(steps[0][1], "Deploy application");
+        assert!(steps[2][2].contains("custom"));
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn listing_steps_runs_nothing() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+        let hocfile: Hocfile = serde_yaml::from_str(
+            "meta:\n  name: custom\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: custom.local\n  internalPort: 8080\n",
+        )?;
+        let steps = [Step {
+            name: "Count",
+            description: |_, _| String::new(),
+            run: |_, _| {
+                RUNS.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        }];
+
+        run_steps(&steps, &hocfile, "5m", true)?;
+        assert_eq!(RUNS.load(Ordering::SeqCst), 0);
+
+        run_steps(&steps, &hocfile, "5m", false)?;
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn env_is_visible_to_script() {
+        let hocfile: Hocfile = serde_yaml::from_str(
+            "meta:\n  name: custom\n  version: 1.0.0\n\
+             image:\n  name: custom-image\n\
+             service:\n  domain: custom.local\n  internalPort: 8080\n\
+             env:\n  GREETING: hello from {name} {app_version}\n",
+        )?;
+
+        let env = render_env(&hocfile)?;
+        let output = with_env(process!("echo \"$GREETING\""), &env)
+            .local_mode()
+            .run()?;
+
+        assert_eq!(output.stdout.trim_end(), "hello from custom 1.0.0");
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Error;
+use tar::{Builder, Header};
+
+use crate::{
+    context::{
+        key::Key,
+        kv::{self, GLOBAL_PREFIX, REDACTED_VALUE},
+        Context,
+    },
+    prelude::*,
+};
+
+pub(super) const CONTEXT_ENTRY: &str = "context.yaml";
+pub(super) const FILES_PREFIX: &str = "files/";
+const ALIASES_ENTRY: &str = "$aliases";
+
+#[throws(Error)]
+pub fn run(archive_path: PathBuf, redacted: bool, exclude_global: bool) {
+    progress!("Exporting context");
+
+    Context::get_or_init().persist()?;
+    write_archive(
+        &crate::local_context_file_path(),
+        &archive_path,
+        redacted,
+        exclude_global,
+    )?;
+
+    info!("Context exported to {archive_path:?}");
+}
+
+/// Bundles the context file together with all files it refers to in a tar archive. If `redacted`
+/// is set, the values of redacted keys are masked. If `exclude_global` is set, the keys under the
+/// global prefix are left out, along with their files.
+#[throws(Error)]
+pub(super) fn write_archive(
+    context_path: &Path,
+    archive_path: &Path,
+    redacted: bool,
+    exclude_global: bool,
+) {
+    let mut contents = fs::read_to_string(context_path)?;
+    let mut context: serde_yaml::Value = serde_yaml::from_str(&contents)?;
+
+    if redacted {
+        redact_context(&mut context, kv::is_redacted);
+    }
+    if exclude_global {
+        exclude_global_keys(&mut context);
+    }
+    if redacted || exclude_global {
+        contents = serde_yaml::to_string(&context)?;
+    }
+
+    let mut archive = Builder::new(File::create(archive_path)?);
+
+    debug!("Adding context file");
+    let mut header = Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    archive.append_data(&mut header, CONTEXT_ENTRY, contents.as_bytes())?;
+
+    let files = context
+        .get("files")
+        .and_then(serde_yaml::Value::as_mapping)
+        .into_iter()
+        .flatten();
+    for (key, path) in files {
+        let (Some(key), Some(path)) = (key.as_str(), path.as_str()) else {
+            continue;
+        };
+
+        debug!("Adding file for key {key:?}");
+        archive.append_file(format!("{FILES_PREFIX}{key}"), &mut File::open(path)?)?;
+    }
+
+    archive.finish()?;
+}
+
+/// Removes the keys under the global prefix, along with their files and aliases.
+fn exclude_global_keys(context: &mut serde_yaml::Value) {
+    let is_global = |key: &serde_yaml::Value| {
+        key.as_str()
+            .map_or(false, |key| key.starts_with(GLOBAL_PREFIX))
+    };
+
+    if let Some(kv) = context
+        .get_mut("kv")
+        .and_then(serde_yaml::Value::as_mapping_mut)
+    {
+        // The aliases are stored next to the keys, under a name which has the global prefix.
+        if let Some(aliases) = kv
+            .get_mut(ALIASES_ENTRY)
+            .and_then(serde_yaml::Value::as_mapping_mut)
+        {
+            aliases.retain(|key, target| !is_global(key) && !is_global(target));
+        }
+        kv.retain(|key, _| key.as_str() == Some(ALIASES_ENTRY) || !is_global(key));
+    }
+
+    if let Some(files) = context
+        .get_mut("files")
+        .and_then(serde_yaml::Value::as_mapping_mut)
+    {
+        files.retain(|key, _| !is_global(key));
+    }
+}
+
+fn redact_context(context: &mut serde_yaml::Value, is_redacted: impl Fn(&Key) -> bool) {
+    let values = context
+        .get_mut("kv")
+        .and_then(serde_yaml::Value::as_mapping_mut)
+        .into_iter()
+        .flatten();
+    for (key, value) in values {
+        if key.as_str().map_or(false, |key| is_redacted(Key::new(key))) {
+            *value = REDACTED_VALUE.into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[throws(Error)]
+    fn redacted_values_are_masked() {
+        let mut context: serde_yaml::Value = serde_yaml::from_str(
+            "kv:\n  admin/username: hoc\n  admin/passwords/remote: hunter2\nfiles: {}\n",
+        )?;
+
+        redact_context(&mut context, |key| {
+            key.as_str().starts_with("admin/passwords/")
+        });
+
+        assert_eq!(context["kv"]["admin/username"].as_str(), Some("hoc"));
+        assert_eq!(
+            context["kv"]["admin/passwords/remote"].as_str(),
+            Some(REDACTED_VALUE)
+        );
+    }
+}
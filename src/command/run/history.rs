@@ -0,0 +1,24 @@
+use crate::{context::Context, log, prelude::*};
+
+pub fn run(command: Option<String>) {
+    let history = Context::get_or_init().history();
+
+    let rows: Vec<_> = history
+        .runs(command.as_deref())
+        .map(|run| {
+            vec![
+                run.command.clone(),
+                run.state.to_string(),
+                run.started.clone(),
+                run.finished.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    if rows.is_empty() {
+        info!("No runs recorded");
+        return;
+    }
+
+    log::table(&["COMMAND", "STATE", "STARTED", "FINISHED"], &rows);
+}
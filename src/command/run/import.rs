@@ -0,0 +1,254 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    os::unix::fs::OpenOptionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Error;
+use tar::Archive;
+
+use super::export::{CONTEXT_ENTRY, FILES_PREFIX};
+use crate::{context::Context, prelude::*, util::Opt};
+
+#[throws(Error)]
+pub fn run(archive_path: PathBuf) {
+    progress!("Importing context");
+
+    let context_path = crate::local_context_file_path();
+    if context_path.try_exists()? {
+        warn!("A context already exists at {context_path:?}");
+
+        let opt = select!("Do you want to replace it?")
+            .with_options([Opt::Yes, Opt::No])
+            .get()?;
+        if opt == Opt::No {
+            bail!("Import aborted");
+        }
+    }
+
+    read_archive(&archive_path, &context_path, &crate::local_files_dir())?;
+    Context::get_or_init().load()?;
+
+    info!("Context imported from {archive_path:?}");
+}
+
+/// Unpacks an archive written by [`super::export::write_archive`], relocating the files to
+/// `files_dir`.
+#[throws(Error)]
+fn read_archive(archive_path: &Path, context_path: &Path, files_dir: &Path) {
+    let mut archive = Archive::new(File::open(archive_path)?);
+    let mut entries = archive.entries()?;
+
+    // The context file is written first, so the file keys are known when the files are unpacked.
+    let Some(entry) = entries.next() else {
+        bail!("The archive is empty");
+    };
+    let entry = entry?;
+    ensure!(
+        entry.path()? == Path::new(CONTEXT_ENTRY),
+        "The archive does not start with {CONTEXT_ENTRY}"
+    );
+    let mut context: serde_yaml::Value = serde_yaml::from_reader(entry)?;
+
+    let files = context
+        .get_mut("files")
+        .and_then(serde_yaml::Value::as_mapping_mut);
+
+    // Keys come from the archive, so they must not be able to escape the files directory.
+    for key in files.iter().flat_map(|files| files.keys()) {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        if !Path::new(key)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            bail!("Invalid file key in archive: {key:?}");
+        }
+    }
+
+    let mut extracted = HashSet::new();
+    if let Some(files) = files {
+        for entry in entries {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.into_owned();
+            let Some(key) = entry_path
+                .to_str()
+                .and_then(|path| path.strip_prefix(FILES_PREFIX))
+            else {
+                continue;
+            };
+            let Some(path) = files.get_mut(key) else {
+                continue;
+            };
+
+            debug!("Extracting file for key {key:?}");
+            let local_path = files_dir.join(key);
+            if let Some(parent) = local_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = File::options()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(entry.header().mode()?)
+                .open(&local_path)?;
+            io::copy(&mut entry, &mut file)?;
+
+            *path = serde_yaml::Value::String(local_path.to_string_lossy().into_owned());
+            extracted.insert(key.to_owned());
+        }
+
+        for key in files.keys().filter_map(serde_yaml::Value::as_str) {
+            ensure!(
+                extracted.contains(key),
+                "Missing file for key {key:?} in archive"
+            );
+        }
+    }
+
+    // Cached files are not exported, and will be recreated when needed.
+    if let Some(cache) = context.get_mut("cache") {
+        *cache = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+    }
+
+    serde_yaml::to_writer(File::create(context_path)?, &context)?;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::run::export, util};
+
+    #[test]
+    #[throws(Error)]
+    fn export_import_round_trip() {
+        let test_dir = util::TempDir::new()?;
+        let source_dir = test_dir.path().join("source");
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(source_dir.join("files/admin"))?;
+        fs::create_dir_all(&target_dir)?;
+
+        let secret_path = source_dir.join("files/admin/secret");
+        fs::write(&secret_path, "hunter2")?;
+        fs::write(
+            source_dir.join("context.yaml"),
+            format!(
+                "kv:\n  admin/username: hoc\nfiles:\n  admin/secret: {}\ncache: {{}}\n",
+                secret_path.display(),
+            ),
+        )?;
+
+        let archive_path = test_dir.path().join("context.tar");
+        export::write_archive(
+            &source_dir.join("context.yaml"),
+            &archive_path,
+            false,
+            false,
+        )?;
+        read_archive(
+            &archive_path,
+            &target_dir.join("context.yaml"),
+            &target_dir.join("files"),
+        )?;
+
+        let context: serde_yaml::Value =
+            serde_yaml::from_reader(File::open(target_dir.join("context.yaml"))?)?;
+        let imported_path = target_dir.join("files/admin/secret");
+        let imported = fs::read_to_string(&imported_path)?;
+
+        assert_eq!(context["kv"]["admin/username"].as_str(), Some("hoc"));
+        assert_eq!(
+            context["files"]["admin/secret"].as_str(),
+            imported_path.to_str(),
+        );
+        assert_eq!(imported, "hunter2");
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn keys_outside_files_dir_are_rejected() {
+        let test_dir = util::TempDir::new()?;
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir)?;
+
+        let archive_path = test_dir.path().join("context.tar");
+        let mut archive = tar::Builder::new(File::create(&archive_path)?);
+        let context = b"kv: {}\nfiles:\n  ../evil: /tmp/evil\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(context.len() as u64);
+        archive.append_data(&mut header, CONTEXT_ENTRY, &context[..])?;
+
+        // The builder refuses to write parent components, so the name is set directly.
+        let name = format!("{FILES_PREFIX}../evil");
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(4);
+        header.set_cksum();
+        archive.append(&header, &b"evil"[..])?;
+        archive.finish()?;
+
+        let res = read_archive(
+            &archive_path,
+            &target_dir.join("context.yaml"),
+            &target_dir.join("files"),
+        );
+        assert!(res.is_err());
+        assert!(!target_dir.join("evil").try_exists()?);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn global_keys_are_excluded() {
+        let test_dir = util::TempDir::new()?;
+        let source_dir = test_dir.path().join("source");
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(source_dir.join("files"))?;
+        fs::create_dir_all(&target_dir)?;
+
+        let local_path = source_dir.join("files/config");
+        let global_path = source_dir.join("files/global");
+        fs::write(&local_path, "local")?;
+        fs::write(&global_path, "global")?;
+        fs::write(
+            source_dir.join("context.yaml"),
+            format!(
+                "kv:\n  \
+                   $aliases:\n    current: nodes/adam\n    $current: $nodes/betsy\n  \
+                   nodes/adam: 10.0.0.1\n  \
+                   $nodes/betsy: 10.0.0.2\n\
+                 files:\n  config: {}\n  $config: {}\n\
+                 cache: {{}}\n",
+                local_path.display(),
+                global_path.display(),
+            ),
+        )?;
+
+        let archive_path = test_dir.path().join("context.tar");
+        export::write_archive(&source_dir.join("context.yaml"), &archive_path, false, true)?;
+        read_archive(
+            &archive_path,
+            &target_dir.join("context.yaml"),
+            &target_dir.join("files"),
+        )?;
+
+        let context: serde_yaml::Value =
+            serde_yaml::from_reader(File::open(target_dir.join("context.yaml"))?)?;
+        assert_eq!(context["kv"]["nodes/adam"].as_str(), Some("10.0.0.1"));
+        assert!(context["kv"].get("$nodes/betsy").is_none());
+        assert_eq!(
+            context["kv"]["$aliases"]["current"].as_str(),
+            Some("nodes/adam")
+        );
+        assert!(context["kv"]["$aliases"].get("$current").is_none());
+        assert!(context["files"].get("$config").is_none());
+        assert_eq!(
+            fs::read_to_string(target_dir.join("files/config"))?,
+            "local"
+        );
+        assert!(!target_dir.join("files/$config").try_exists()?);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use lazy_regex::regex;
+use log_facade::Level;
+
+use crate::{log, prelude::*};
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+#[throws(Error)]
+pub fn run(follow: bool, since: Option<Duration>, level: Option<Level>) {
+    let Some(path) = latest_log_file(&log::logs_dir()?, &log::log_file()?)? else {
+        info!("No logs recorded");
+        return;
+    };
+
+    let since = since
+        .map(|since| chrono::Duration::from_std(since).map(|since| Utc::now() - since))
+        .transpose()?;
+    let mut filter = LineFilter::new(since, level.unwrap_or(Level::Trace));
+
+    let reader = BufReader::new(File::open(&path)?);
+    read_lines(reader, follow, |line| {
+        if filter.matches(line) {
+            if log::use_color() {
+                println!("{line}");
+            } else {
+                println!("{}", regex!("\x1b\\[[0-9;]*m").replace_all(line, ""));
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+}
+
+/// Passes each complete line to `f`. If following, waits for more lines to be written until `f`
+/// breaks.
+#[throws(Error)]
+fn read_lines<R, F>(mut reader: R, follow: bool, mut f: F)
+where
+    R: BufRead,
+    F: FnMut(&str) -> ControlFlow<()>,
+{
+    let mut line = String::new();
+    loop {
+        if reader.read_line(&mut line)? == 0 {
+            if !follow {
+                break;
+            }
+            spin_sleep::sleep(FOLLOW_INTERVAL);
+            continue;
+        }
+
+        // Wait for the rest of the line if it is still being written.
+        if follow && !line.ends_with('\n') {
+            continue;
+        }
+
+        if f(line.trim_end_matches('\n')).is_break() {
+            break;
+        }
+        line.clear();
+    }
+}
+
+/// Parses durations like `90s`, `15m` or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {s:?}, expected e.g. `30s`, `15m` or `1h30m`");
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let n: u64 = number.parse().map_err(|_| invalid())?;
+        seconds += n * unit;
+        number.clear();
+    }
+
+    if !number.is_empty() || s.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Finds the most recent log file, other than the one of the current run.
+#[throws(Error)]
+fn latest_log_file(logs_dir: &Path, current: &Path) -> Option<PathBuf> {
+    let mut latest: Option<PathBuf> = None;
+    let mut dirs = vec![logs_dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let read_dir = match fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => throw!(err),
+        };
+
+        for entry in read_dir {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if path != current && latest.as_ref().map_or(true, |latest| &path > latest) {
+                // The directories and file names are dates and times, so they sort
+                // chronologically.
+                latest.replace(path);
+            }
+        }
+    }
+
+    latest
+}
+
+/// Selects log file lines by time and level. Lines without a header, i.e. continuations of
+/// multiline messages, follow the selection of the message they belong to.
+struct LineFilter {
+    since: Option<DateTime<Utc>>,
+    level: Level,
+    last_matched: bool,
+}
+
+impl LineFilter {
+    fn new(since: Option<DateTime<Utc>>, level: Level) -> Self {
+        Self {
+            since,
+            level,
+            last_matched: false,
+        }
+    }
+
+    fn matches(&mut self, line: &str) -> bool {
+        if let Some((timestamp, level)) = parse_header(line) {
+            self.last_matched =
+                level <= self.level && self.since.map_or(true, |since| timestamp >= since);
+        }
+        self.last_matched
+    }
+}
+
+/// Parses the timestamp and level of a line like `[2023-01-01T12:00:00.000000Z INFO  hoc] ...`,
+/// which may be wrapped in color escape codes.
+fn parse_header(line: &str) -> Option<(DateTime<Utc>, Level)> {
+    let line = match line.strip_prefix('\x1b') {
+        Some(rest) => &rest[rest.find('m')? + 1..],
+        None => line,
+    };
+
+    let mut words = line.strip_prefix('[')?.split_whitespace();
+    let timestamp = DateTime::parse_from_rfc3339(words.next()?).ok()?;
+    let level = Level::from_str(words.next()?.trim_end_matches(']')).ok()?;
+
+    Some((timestamp.with_timezone(&Utc), level))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        thread,
+    };
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::util;
+
+    const LOG_FILE: &str = "\
+\x1b[38;5;10m[2023-01-01T12:00:00.000000Z INFO    hoc::command] Starting\x1b[39m
+\x1b[38;5;12m[2023-01-01T12:00:01.000000Z DEBUG   hoc::process] Running
+ls -l\x1b[39m
+\x1b[38;5;9m[2023-01-01T12:05:00.000000Z ERROR   hoc::process] Failed\x1b[39m
+\x1b[38;5;12m[2023-01-01T12:06:00.000000Z DEBUG   hoc::process] Cleaning up\x1b[39m
+";
+
+    fn select(since: Option<DateTime<Utc>>, level: Level) -> Vec<&'static str> {
+        let mut filter = LineFilter::new(since, level);
+        LOG_FILE
+            .lines()
+            .filter(|line| filter.matches(line))
+            .collect()
+    }
+
+    #[test]
+    fn filter_by_level_and_time() {
+        let lines = select(None, Level::Info);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("Starting\x1b[39m"));
+        assert!(lines[1].ends_with("Failed\x1b[39m"));
+
+        let since = Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 1).unwrap();
+        let lines = select(Some(since), Level::Debug);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with("Running"));
+        assert_eq!(lines[1], "ls -l\x1b[39m");
+        assert!(lines[3].ends_with("Cleaning up\x1b[39m"));
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn follow_growing_log() {
+        let test_dir = util::TempDir::new()?;
+        let path = test_dir.path().join("hoc.log");
+        let mut file = File::create(&path)?;
+        writeln!(file, "first")?;
+
+        let writer = thread::spawn(move || -> io::Result<()> {
+            thread::sleep(FOLLOW_INTERVAL);
+            write!(file, "sec")?;
+            file.flush()?;
+            thread::sleep(FOLLOW_INTERVAL);
+            writeln!(file, "ond")?;
+            writeln!(file, "third")?;
+            Ok(())
+        });
+
+        let mut lines = Vec::new();
+        read_lines(BufReader::new(File::open(&path)?), true, |line| {
+            lines.push(line.to_owned());
+            if lines.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        writer.join().unwrap()?;
+
+        assert_eq!(lines, ["first", "second", "third"]);
+    }
+}
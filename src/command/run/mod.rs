@@ -1,8 +1,13 @@
 #[cfg(debug_assertions)]
 pub mod debug;
 
+pub mod context;
 pub mod deploy;
+pub mod export;
+pub mod history;
+pub mod import;
 pub mod init;
+pub mod logs;
 pub mod node;
 pub mod sd_card;
 pub mod upgrade;
@@ -1,31 +1,49 @@
-use std::{io::Write, net::IpAddr};
+use std::{io::Write, net::IpAddr, thread, time::Duration};
 
 use anyhow::Error;
 
 use crate::{
     command,
-    context::{self, kv},
+    context::{self, kv, Context},
     prelude::*,
-    process,
+    process, prompt,
     util::Opt,
 };
 
+/// Sections which are marked as completed, so they are skipped when deploying again after a failed
+/// run.
+const SECTIONS: [&str; 5] = [
+    "initialization",
+    "password",
+    "upgrade",
+    "cluster",
+    "kubeconfig",
+];
+
+/// The kubeconfig may be empty or missing until the control plane is ready.
+const KUBECONFIG_ATTEMPTS: u32 = 5;
+const KUBECONFIG_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 #[throws(Error)]
-pub fn run(node_name: String) {
+pub fn run(node_name: String, fresh: bool) {
     check_node(&node_name)?;
 
+    if fresh {
+        clear_sections(&mut NodeSections(&node_name))?;
+    }
+
     let ip_address = get_node_ip_address(&node_name)?;
     await_node_startup(&node_name, ip_address)?;
 
     process::global_settings().remote_mode(node_name.clone());
 
-    await_node_initialization()?;
-    change_password()?;
-
-    command::node::upgrade::run(node_name.clone(), true)?;
-
-    join_cluster(&node_name)?;
-    copy_kubeconfig(ip_address)?;
+    run_section(&node_name, "initialization", await_node_initialization)?;
+    run_section(&node_name, "password", change_password)?;
+    run_section(&node_name, "upgrade", || {
+        command::node::upgrade::run(node_name.clone(), true)
+    })?;
+    run_section(&node_name, "cluster", || join_cluster(&node_name))?;
+    run_section(&node_name, "kubeconfig", || copy_kubeconfig(ip_address))?;
 
     process::global_settings().container_mode();
 
@@ -33,6 +51,73 @@ pub fn run(node_name: String) {
     report(&node_name)?;
 }
 
+/// Keeps track of which sections have been completed.
+trait CompletedSections {
+    fn contains(&self, section: &str) -> bool;
+
+    fn insert(&mut self, section: &'static str) -> Result<(), Error>;
+
+    fn remove(&mut self, section: &'static str) -> Result<(), Error>;
+}
+
+/// The completed sections of a node, recorded in the kv so they are remembered between runs.
+struct NodeSections<'a>(&'a str);
+
+impl CompletedSections for NodeSections<'_> {
+    fn contains(&self, section: &str) -> bool {
+        kv!("nodes/{}/configured/{section}", self.0).exists()
+    }
+
+    #[throws(Error)]
+    fn insert(&mut self, section: &'static str) {
+        kv!("nodes/{}/configured/{section}", self.0).update_or_put(true)?;
+    }
+
+    #[throws(Error)]
+    fn remove(&mut self, section: &'static str) {
+        if self.contains(section) {
+            kv!("nodes/{}/configured/{section}", self.0).drop()?;
+        }
+    }
+}
+
+/// Runs the section, unless a previous run has already completed it.
+#[throws(Error)]
+fn run_section<F>(node_name: &str, section: &'static str, f: F)
+where
+    F: FnOnce() -> Result<(), Error>,
+{
+    run_unless_completed(&mut NodeSections(node_name), section, || {
+        let snapshot = Context::get_or_init().kv().snapshot();
+        f()?;
+        for change in snapshot.diff(&Context::get_or_init().kv()) {
+            debug!("{change}");
+        }
+        Ok(())
+    })?;
+}
+
+#[throws(Error)]
+fn run_unless_completed<F>(sections: &mut impl CompletedSections, section: &'static str, f: F)
+where
+    F: FnOnce() -> Result<(), Error>,
+{
+    if sections.contains(section) {
+        info!("Skipping {section}, since it was completed by a previous run");
+        return;
+    }
+
+    f()?;
+    sections.insert(section)?;
+}
+
+#[throws(Error)]
+fn clear_sections(sections: &mut impl CompletedSections) {
+    for section in SECTIONS {
+        sections.remove(section)?;
+    }
+}
+
 #[throws(Error)]
 fn check_node(node_name: &str) {
     progress!("Checking node");
@@ -119,7 +204,7 @@ fn await_node_startup(node_name: &str, ip_address: IpAddr) {
             .get()?;
 
         if opt == Opt::No {
-            throw!(inquire::InquireError::OperationCanceled);
+            throw!(prompt::Error::Canceled);
         }
 
         if !ping_endpoint(ip_address)? {
@@ -179,6 +264,13 @@ fn join_cluster(node_name: &str) {
 
     match files!("admin/kube/config").get() {
         Ok(kubeconfig_file) => {
+            if !kubeconfig_file.is_text()? {
+                bail!(
+                    "The kubeconfig at {:?} is not a text file, it may be corrupt",
+                    kubeconfig_file.local_path,
+                );
+            }
+
             let kubeconfig: kv::Item = serde_yaml::from_reader(kubeconfig_file)?;
             let k3s_url: String = kubeconfig
                 .take("clusters")
@@ -247,15 +339,52 @@ fn copy_kubeconfig(ip_address: IpAddr) {
 
     progress!("Copying kubeconfig");
 
-    let output = process!(sudo "cat /etc/rancher/k3s/k3s.yaml").run()?;
+    let kubeconfig = fetch_kubeconfig(
+        || Ok(process!(sudo "cat /etc/rancher/k3s/k3s.yaml").run()?.stdout),
+        thread::sleep,
+    )?;
     let mut kubeconfig_file = files!("admin/kube/config").permissions(0o600).create()?;
-    let contents = output.stdout.replace(
+    let contents = kubeconfig.replace(
         "server: https://127.0.0.1:6443",
         &format!("server: https://{ip_address}:6443"),
     );
     kubeconfig_file.write_all(contents.as_bytes())?;
 }
 
+/// Fetches the kubeconfig until it is valid YAML, retrying with a delay.
+#[throws(Error)]
+fn fetch_kubeconfig<F, S>(mut fetch: F, mut sleep: S) -> String
+where
+    F: FnMut() -> Result<String, Error>,
+    S: FnMut(Duration),
+{
+    let mut attempt = 1;
+    loop {
+        let result = fetch().and_then(|kubeconfig| {
+            ensure!(!kubeconfig.trim().is_empty(), "The kubeconfig is empty");
+            serde_yaml::from_str::<serde_yaml::Value>(&kubeconfig)
+                .context("The kubeconfig is not valid YAML")?;
+            Ok(kubeconfig)
+        });
+
+        match result {
+            Ok(kubeconfig) => break kubeconfig,
+            Err(err) if attempt < KUBECONFIG_ATTEMPTS => {
+                warn!(
+                    "{err}, retrying in {}s (attempt {}/{KUBECONFIG_ATTEMPTS})",
+                    KUBECONFIG_RETRY_DELAY.as_secs(),
+                    attempt + 1,
+                );
+                sleep(KUBECONFIG_RETRY_DELAY);
+                attempt += 1;
+            }
+            Err(err) => throw!(err.context(format!(
+                "Failed to fetch the kubeconfig after {KUBECONFIG_ATTEMPTS} attempts"
+            ))),
+        }
+    }
+}
+
 #[throws(Error)]
 fn verify_installation(node_name: &str) {
     progress!("Verifying installation");
@@ -267,3 +396,79 @@ fn report(node_name: &str) {
     kv!("nodes/{node_name}/initialized").update(true)?;
     info!("{node_name} has been successfully deployed");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    impl CompletedSections for HashSet<&'static str> {
+        fn contains(&self, section: &str) -> bool {
+            HashSet::contains(self, section)
+        }
+
+        #[throws(Error)]
+        fn insert(&mut self, section: &'static str) {
+            HashSet::insert(self, section);
+        }
+
+        #[throws(Error)]
+        fn remove(&mut self, section: &'static str) {
+            HashSet::remove(self, section);
+        }
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn fetch_kubeconfig_until_valid() {
+        let mut responses = vec!["", "clusters: [", "apiVersion: v1\nkind: Config\n"].into_iter();
+        let mut sleeps = 0;
+
+        let kubeconfig = fetch_kubeconfig(
+            || Ok(responses.next().unwrap_or_default().to_owned()),
+            |_| sleeps += 1,
+        )?;
+        assert_eq!(kubeconfig, "apiVersion: v1\nkind: Config\n");
+        assert_eq!(sleeps, 2);
+
+        let mut fetches = 0;
+        let res = fetch_kubeconfig(
+            || {
+                fetches += 1;
+                Ok(String::new())
+            },
+            |_| (),
+        );
+        assert!(res.is_err());
+        assert_eq!(fetches, KUBECONFIG_ATTEMPTS);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn completed_sections_are_skipped() {
+        let mut sections = HashSet::new();
+        let mut runs = 0;
+
+        let res = run_unless_completed(&mut sections, "password", || bail!("Failed"));
+        assert!(res.is_err());
+        assert!(sections.is_empty());
+
+        run_unless_completed(&mut sections, "password", || {
+            runs += 1;
+            Ok(())
+        })?;
+        run_unless_completed(&mut sections, "password", || {
+            runs += 1;
+            Ok(())
+        })?;
+        assert_eq!(runs, 1);
+
+        clear_sections(&mut sections)?;
+        run_unless_completed(&mut sections, "password", || {
+            runs += 1;
+            Ok(())
+        })?;
+        assert_eq!(runs, 2);
+    }
+}
@@ -1,20 +1,22 @@
 use std::{
     fmt::{self, Display, Formatter},
     fs::{self, File},
-    io::{Read, Seek, Write},
+    io::{self, BufRead, BufReader, Read, Seek, Write},
     net::IpAddr,
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use anyhow::Error;
+use flate2::read::MultiGzDecoder;
 use xz2::read::XzDecoder;
 
 use crate::{
     cidr::Cidr,
     context::{self, fs::ContextFile, kv},
+    log,
     prelude::*,
-    process,
+    process, prompt,
     util::{self, DiskInfo, DiskPartitionInfo, Opt},
 };
 
@@ -25,7 +27,7 @@ const UBUNTU_VERSION: UbuntuVersion = UbuntuVersion {
 };
 
 #[throws(Error)]
-pub fn run() {
+pub fn run(show_diff: bool) {
     process::global_settings().local_mode();
 
     let disk = choose_sd_card()?;
@@ -42,7 +44,7 @@ pub fn run() {
     let partition = mount_sd_card()?;
     let mount_dir = find_mount_dir(&disk)?;
 
-    modify_image(&mount_dir, &node_name, ip_address)?;
+    modify_image(&mount_dir, &node_name, ip_address, show_diff)?;
     unmount_partition(&partition)?;
 
     report(&node_name);
@@ -81,7 +83,7 @@ fn choose_sd_card() -> DiskInfo {
             .get()?;
 
         if opt == Opt::No {
-            throw!(inquire::InquireError::OperationCanceled);
+            throw!(prompt::Error::Canceled);
         }
     }
 }
@@ -104,9 +106,10 @@ fn get_os_image_path() -> PathBuf {
 
 #[throws(context::Error)]
 fn get_os_image(file: &mut ContextFile, retrying: bool) {
-    download_os_image(file, retrying)?;
-    validate_os_image(file)?;
-    decompress_xz_file(file)?;
+    let mut compressed_file = temp_file!()?;
+    download_os_image(&mut compressed_file, retrying)?;
+    validate_os_image(&compressed_file)?;
+    decompress_os_image(&compressed_file, file)?;
 }
 
 #[throws(Error)]
@@ -144,7 +147,10 @@ fn validate_os_image(os_image_file_path: &ContextFile) {
     .run()?;
     output.stdout = output.stdout.to_lowercase();
 
-    if !output.stdout.contains("xz compressed data") {
+    if !Compression::DESCRIPTIONS
+        .iter()
+        .any(|desc| output.stdout.contains(desc))
+    {
         error!("Unsupported file type");
 
         let opt = select!("Do you want to inspect the file?")
@@ -162,38 +168,127 @@ fn validate_os_image(os_image_file_path: &ContextFile) {
         bail!("Validation failed");
     }
 
-    info!("File is valid XZ compressed data");
+    info!("File is a valid image");
 }
 
 #[throws(Error)]
-fn decompress_xz_file(os_image_file: &mut ContextFile) {
-    let decompress_progress = progress_with_handle!("Decompressing image");
+fn decompress_os_image(compressed_file: &ContextFile, os_image_file: &mut ContextFile) {
+    let mut progress = progress_with_handle!("Decompressing image");
+
+    let total = compressed_file.file.metadata()?.len();
+    progress.set_progress(0, total);
+    let reader = ProgressReader {
+        inner: File::open(&compressed_file.local_path)?,
+        read: 0,
+        on_read: |read| progress.set_progress(read, total),
+    };
 
-    let os_image_file_ro = File::options().read(true).open(&os_image_file.local_path)?;
-    let mut decompressor = XzDecoder::new(os_image_file_ro);
-    let mut image_data = Vec::new();
-    decompressor
-        .read_to_end(&mut image_data)
-        .context("Reading image in XZ file")?;
+    // Truncate any existing data.
+    os_image_file.set_len(0)?;
 
-    decompress_progress.finish();
+    let compression = decompress(reader, os_image_file)?;
+    info!("Image compression: {compression}");
+    info!(
+        "Image size: {}",
+        log::format_bytes_grouped(os_image_file.file.metadata()?.len())
+    );
+}
 
-    progress!("Saving decompressed image to file");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Xz,
+    Gzip,
+    Zip,
+    None,
+}
 
-    // Truncate any existing data.
-    os_image_file.set_len(0)?;
+impl Compression {
+    /// The descriptions `file` gives the supported image formats.
+    const DESCRIPTIONS: [&str; 4] = [
+        "xz compressed data",
+        "gzip compressed data",
+        "zip archive data",
+        "boot sector",
+    ];
+
+    fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if magic.starts_with(b"PK\x03\x04") {
+            Self::Zip
+        } else {
+            Self::None
+        }
+    }
+}
+
+impl Display for Compression {
+    #[throws(fmt::Error)]
+    fn fmt(&self, f: &mut Formatter) {
+        match self {
+            Self::Xz => write!(f, "XZ")?,
+            Self::Gzip => write!(f, "gzip")?,
+            Self::Zip => write!(f, "ZIP")?,
+            Self::None => write!(f, "none")?,
+        }
+    }
+}
+
+/// Streams the decompressed data to the writer, detecting the compression from the magic bytes.
+/// Data of an unknown format is copied as is.
+#[throws(Error)]
+fn decompress(reader: impl Read, writer: &mut impl Write) -> Compression {
+    let mut reader = BufReader::new(reader);
+    let compression = Compression::detect(reader.fill_buf()?);
+
+    match compression {
+        Compression::Xz => {
+            io::copy(&mut XzDecoder::new(reader), writer).context("Reading image in XZ file")?
+        }
+        Compression::Gzip => io::copy(&mut MultiGzDecoder::new(reader), writer)
+            .context("Reading image in gzip file")?,
+        Compression::Zip => {
+            let mut image = zip::read::read_zipfile_from_stream(&mut reader)?
+                .context("The ZIP archive is empty")?;
+            io::copy(&mut image, writer).context("Reading image in ZIP archive")?
+        }
+        Compression::None => io::copy(&mut reader, writer)?,
+    };
+    writer.flush()?;
+
+    compression
+}
+
+/// Reports the number of bytes read so far.
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    on_read: F,
+}
 
-    // Write to file.
-    os_image_file.write_all(&image_data)?;
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    #[throws(io::Error)]
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.on_read)(self.read);
+        n
+    }
 }
 
 #[throws(Error)]
 fn flash_image(disk: &DiskInfo, os_image_path: &Path) {
-    let opt = select!("Do you want to flash target disk {:?}?", disk.description())
-        .with_options([Opt::Yes, Opt::No])
-        .get()?;
-    if opt == Opt::No {
-        throw!(inquire::InquireError::OperationCanceled);
+    let confirmed = prompt::confirm_destructive(
+        format!(
+            "Flashing target disk {:?} will erase it",
+            disk.description()
+        ),
+        &disk.id,
+    )?;
+    if !confirmed {
+        throw!(prompt::Error::Canceled);
     }
 
     progress!("Flashing image");
@@ -340,7 +435,7 @@ fn assign_ip_address(node_name: &str) -> Cidr {
 }
 
 #[throws(Error)]
-fn modify_image(mount_dir: &Path, node_name: &str, ip_address: Cidr) {
+fn modify_image(mount_dir: &Path, node_name: &str, ip_address: Cidr, show_diff: bool) {
     let opt = select!("Do you want to modify the partition mounted at {mount_dir:?}?")
         .with_options([Opt::Yes, Opt::No])
         .get()?;
@@ -369,7 +464,7 @@ fn modify_image(mount_dir: &Path, node_name: &str, ip_address: Cidr) {
     debug!("User data:\n{data}");
 
     let user_data_path = mount_dir.join("user-data");
-    fs::write(user_data_path, &data)?;
+    write_config_file(&user_data_path, &data, show_diff)?;
 
     // Deserialize and serialize to check for syntax errors.
     let gateway: IpAddr = kv!("network/gateway").get()?.convert()?;
@@ -388,14 +483,60 @@ fn modify_image(mount_dir: &Path, node_name: &str, ip_address: Cidr) {
     debug!("Network config:\n{network_config}");
 
     let network_config_path = mount_dir.join("network-config");
-    fs::write(network_config_path, network_config)?;
+    write_config_file(&network_config_path, network_config, show_diff)?;
 
     let cmdline_path = mount_dir.join("cmdline.txt");
-    process!(
-        "sed -i '' -E 's/ *cgroup_(memory|enable)=[^ ]*//g;\
-                       s/$/ cgroup_memory=1 cgroup_enable=memory/' {cmdline_path:?}"
-    )
-    .run()?;
+    let cmdline = fs::read_to_string(&cmdline_path)?;
+    write_config_file(&cmdline_path, &with_cgroup_memory(&cmdline), show_diff)?;
+}
+
+/// Enables the memory cgroup on the kernel command line, replacing any previous cgroup memory
+/// parameters.
+fn with_cgroup_memory(cmdline: &str) -> String {
+    cmdline
+        .lines()
+        .map(|line| {
+            let mut params: Vec<_> = line
+                .split(' ')
+                .filter(|param| {
+                    !param.is_empty()
+                        && !param.starts_with("cgroup_memory=")
+                        && !param.starts_with("cgroup_enable=")
+                })
+                .collect();
+            params.extend(["cgroup_memory=1", "cgroup_enable=memory"]);
+            params.join(" ") + "\n"
+        })
+        .collect()
+}
+
+#[throws(Error)]
+fn write_config_file(path: &Path, contents: &str, show_diff: bool) {
+    if show_diff {
+        let current = match fs::read_to_string(path) {
+            Ok(current) => current,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => throw!(err),
+        };
+
+        if current != contents {
+            info!(
+                "Changes to {path:?}:\n{}",
+                util::line_diff(&current, contents)
+            );
+
+            let opt = select!("Do you want to write the changes?")
+                .with_options([Opt::Yes, Opt::No])
+                .get()?;
+
+            if opt == Opt::No {
+                warn!("Skipping changes to {path:?}");
+                return;
+            }
+        }
+    }
+
+    fs::write(path, contents)?;
 }
 
 #[throws(Error)]
@@ -433,3 +574,51 @@ impl Display for UbuntuVersion {
         )?;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::GzEncoder, Compression as GzCompression};
+
+    use super::*;
+
+    #[test]
+    #[throws(Error)]
+    fn decompress_gzip_image() {
+        let image: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+        encoder.write_all(&image)?;
+        let compressed = encoder.finish()?;
+
+        let mut read = 0;
+        let reader = ProgressReader {
+            inner: &compressed[..],
+            read: 0,
+            on_read: |n| read = n,
+        };
+        let mut decompressed = Vec::new();
+        assert_eq!(decompress(reader, &mut decompressed)?, Compression::Gzip);
+        assert_eq!(decompressed, image);
+        assert_eq!(read, compressed.len() as u64);
+
+        let mut raw = Vec::new();
+        assert_eq!(decompress(&image[..], &mut raw)?, Compression::None);
+        assert_eq!(raw, image);
+    }
+
+    #[test]
+    fn cmdline_cgroup_memory() {
+        let cmdline = "console=tty1 cgroup_enable=cpuset cgroup_memory=0 rootwait\n";
+        let new_cmdline = with_cgroup_memory(cmdline);
+
+        assert_eq!(
+            new_cmdline,
+            "console=tty1 rootwait cgroup_memory=1 cgroup_enable=memory\n"
+        );
+        assert_eq!(with_cgroup_memory(&new_cmdline), new_cmdline);
+        assert_eq!(
+            util::line_diff(cmdline, &new_cmdline),
+            "-console=tty1 cgroup_enable=cpuset cgroup_memory=0 rootwait\n\
+             +console=tty1 rootwait cgroup_memory=1 cgroup_enable=memory\n"
+        );
+    }
+}
@@ -1,11 +1,45 @@
 use crossterm::style::Stylize;
+use log_facade::log_enabled;
 
 use crate::prelude::*;
 
+/// Shows the version, along with the build information when run with the global `--verbose`
+/// flag.
 pub fn run() {
-    info!(
+    for line in version_lines(log_enabled!(Level::Debug)) {
+        info!("{line}");
+    }
+}
+
+fn version_lines(verbose: bool) -> Vec<String> {
+    let mut lines = vec![format!(
         "{} is at version v{}",
         "hoc".yellow(),
         env!("CARGO_PKG_VERSION"),
-    );
+    )];
+
+    if verbose {
+        lines.extend([
+            format!("Git commit: {}", env!("HOC_GIT_COMMIT")),
+            format!("Compiler: {}", env!("HOC_RUSTC_VERSION")),
+            format!("Target: {}", env!("HOC_TARGET")),
+            format!("Dependencies: {}", env!("HOC_DEPENDENCIES")),
+        ]);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_contains_crate_version() {
+        let lines = version_lines(false);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(&format!("v{}", env!("CARGO_PKG_VERSION"))));
+
+        assert_eq!(version_lines(true).len(), 5);
+    }
 }
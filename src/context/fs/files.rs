@@ -1,5 +1,6 @@
 use std::{
     borrow::Cow,
+    collections::HashSet,
     fs::{self, File},
     io,
     os::unix::prelude::OpenOptionsExt,
@@ -16,6 +17,7 @@ use crate::{
         Error,
     },
     prelude::*,
+    prompt,
     util::Opt,
 };
 
@@ -30,9 +32,13 @@ pub struct Files {
 
 impl Files {
     pub(in crate::context) fn new() -> Self {
+        Self::in_dir(crate::local_files_dir())
+    }
+
+    pub(in crate::context) fn in_dir(files_dir: PathBuf) -> Self {
         Self {
             map: IndexMap::new(),
-            files_dir: crate::local_files_dir(),
+            files_dir,
         }
     }
 
@@ -107,11 +113,11 @@ impl Files {
                 error!("File at path {path:?} already exists");
 
                 file_options.create_new(false);
-                let opt = select!("How do you want to resolve the file path conflict?")
-                    .with_options([Opt::Skip, Opt::Overwrite])
-                    .get()?;
-
-                should_overwrite = opt == Opt::Overwrite;
+                should_overwrite = resolve_path_conflict(&key, &path, || {
+                    select!("How do you want to resolve the file path conflict?")
+                        .with_options([Opt::Skip, Opt::Overwrite])
+                        .get()
+                })?;
                 if !should_overwrite {
                     warn!("Creating file: {key:?} (skipping)");
                     let file = ContextFile::new(
@@ -163,6 +169,19 @@ impl Files {
         throw!(Error::KeyDoesNotExist(key.into_owned()));
     }
 
+    /// The local path of the file, without opening it.
+    #[throws(Error)]
+    pub fn get_path<K>(&self, key: &K) -> PathBuf
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let key = key.as_ref();
+        self.map
+            .get(key)
+            .cloned()
+            .ok_or_else(|| Error::KeyDoesNotExist(key.to_owned()))?
+    }
+
     #[throws(Error)]
     pub fn remove_file<K>(&mut self, key: &K, force: bool)
     where
@@ -187,6 +206,37 @@ impl Files {
             None => (),
         }
     }
+
+    /// Removes files in the files directory which no key refers to, returning their paths.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn compact(&self) -> Vec<PathBuf> {
+        let referenced: HashSet<_> = self.map.values().collect();
+
+        let mut removed = Vec::new();
+        let mut dirs = vec![self.files_dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let read_dir = match fs::read_dir(&dir) {
+                Ok(read_dir) => read_dir,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => throw!(err),
+            };
+
+            for entry in read_dir {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                } else if !referenced.contains(&path) {
+                    debug!("Remove orphaned file: {path:?}");
+                    fs::remove_file(&path)?;
+                    removed.push(path);
+                }
+            }
+        }
+
+        removed
+    }
 }
 
 pub mod ledger {
@@ -240,3 +290,108 @@ pub mod ledger {
         }
     }
 }
+
+/// Returns whether an existing file, not known to the context, should be overwritten. Fails with a
+/// descriptive error if the conflict can't be resolved interactively.
+#[throws(Error)]
+fn resolve_path_conflict<F>(key: &Key, path: &Path, select: F) -> bool
+where
+    F: FnOnce() -> Result<Opt, prompt::Error>,
+{
+    match select() {
+        Ok(opt) => opt == Opt::Overwrite,
+        Err(prompt::Error::SelectionRequired(_)) => throw!(Error::FileAlreadyExists {
+            key: key.to_owned(),
+            path: path.to_owned(),
+        }),
+        Err(err) => throw!(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[throws(Error)]
+    fn get_path_of_file() {
+        let files_dir = util::TempDir::new()?;
+        let mut files = Files {
+            map: IndexMap::new(),
+            files_dir: files_dir.path().to_owned(),
+        };
+
+        let (_, file) = files.create_file(Key::new("admin/kube/config"), None, |_| Ok(()))?;
+        let path = files.get_path("admin/kube/config");
+        let missing = files.get_path("admin/kube");
+
+        assert_eq!(path?, file.local_path);
+        assert!(matches!(
+            missing,
+            Err(Error::KeyDoesNotExist(key)) if key.as_str() == "admin/kube",
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn classify_text_and_binary_files() {
+        let files_dir = util::TempDir::new()?;
+        let mut files = Files {
+            map: IndexMap::new(),
+            files_dir: files_dir.path().to_owned(),
+        };
+
+        let (_, mut text) = files.create_file(Key::new("text"), None, |_| Ok(()))?;
+        text.write_contents("apiVersion: v1\nkind: Config\n".as_bytes())?;
+        let (_, mut binary) = files.create_file(Key::new("binary"), None, |_| Ok(()))?;
+        binary.write_contents(&[0x1f, 0x8b, 0x08, 0x00, 0xff])?;
+
+        let is_text = text.is_text()?;
+        let is_binary_text = binary.is_text()?;
+
+        assert!(is_text);
+        assert!(!is_binary_text);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn compact_removes_orphaned_files() {
+        let files_dir = util::TempDir::new()?;
+        let mut files = Files {
+            map: IndexMap::new(),
+            files_dir: files_dir.path().to_owned(),
+        };
+
+        let (_, kept) = files.create_file(Key::new("nodes/adam/config"), None, |_| Ok(()))?;
+        let (_, orphan) = files.create_file(Key::new("nodes/betsy/config"), None, |_| Ok(()))?;
+
+        // Forget the key without removing the file, like an interrupted run would.
+        files.map.remove(Key::new("nodes/betsy/config"));
+
+        let removed = files.compact()?;
+        let kept_exists = kept.local_path.exists();
+
+        assert_eq!(removed, [orphan.local_path]);
+        assert!(kept_exists);
+    }
+
+    #[test]
+    fn unresolved_path_conflict_names_key() {
+        let key = Key::new("nodes/betsy/config");
+        let path = Path::new("/hoc/files/nodes/betsy/config");
+        let res = resolve_path_conflict(key, path, || {
+            Err(prompt::Error::SelectionRequired("conflict".to_owned()))
+        });
+
+        let Err(Error::FileAlreadyExists {
+            key: conflict_key,
+            path: conflict_path,
+        }) = res
+        else {
+            panic!("expected a file conflict error");
+        };
+        assert_eq!(conflict_key.as_str(), "nodes/betsy/config");
+        assert_eq!(conflict_path, path);
+    }
+}
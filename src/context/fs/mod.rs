@@ -5,8 +5,9 @@ use std::{
     path::PathBuf,
 };
 
+use self::files::Files;
 use crate::{
-    context::{key::Key, Context, Error},
+    context::{key::Key, kv::Kv, Context, Error},
     ledger::Ledger,
     prelude::*,
 };
@@ -45,19 +46,38 @@ impl FileBuilder<Persisted> {
         Context::get_or_init().files().get_file(self.key)?
     }
 
+    /// The local path of the file, for handing it to other programs.
     #[throws(Error)]
-    pub fn create(self) -> ContextFile {
+    pub fn path(self) -> PathBuf {
         let context = Context::get_or_init();
+        let files = context.files();
+        self.path_in(&files, &context.kv())?
+    }
+
+    #[throws(Error)]
+    fn path_in(self, files: &Files, kv: &Kv) -> PathBuf {
+        match files.get_path(&*self.key) {
+            Err(Error::KeyDoesNotExist(key)) if kv.item_exists(&key) => {
+                throw!(Error::NotAFile(key))
+            }
+            result => result?,
+        }
+    }
+
+    #[throws(Error)]
+    pub fn create(self) -> ContextFile {
+        self.create_in(&mut Context::get_or_init().files_mut())?
+    }
+
+    #[throws(Error)]
+    fn create_in(self, files: &mut Files) -> ContextFile {
         let mut previous_path = None;
-        let (had_previous_file, file) =
-            context
-                .files_mut()
-                .create_file(&self.key, self.permissions, |path| {
-                    let temp_file = temp_file!()?;
-                    fs::rename(path, &temp_file.local_path)?;
-                    previous_path.replace(temp_file.local_path);
-                    Ok(())
-                })?;
+        let (had_previous_file, file) = files.create_file(&self.key, self.permissions, |path| {
+            let temp_file = temp_file!()?;
+            fs::rename(path, &temp_file.local_path)?;
+            previous_path.replace(temp_file.local_path);
+            Ok(())
+        })?;
 
         if !had_previous_file || previous_path.is_some() {
             Ledger::get_or_init().add(files::ledger::Create::new(
@@ -70,6 +90,22 @@ impl FileBuilder<Persisted> {
         file
     }
 
+    /// Creates the file and writes `contents` to it. Unless other permissions have been set, the
+    /// file is only accessible by the current user.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn create_with(self, contents: &[u8]) -> ContextFile {
+        self.create_with_in(&mut Context::get_or_init().files_mut(), contents)?
+    }
+
+    #[throws(Error)]
+    fn create_with_in(mut self, files: &mut Files, contents: &[u8]) -> ContextFile {
+        self.permissions.get_or_insert(0o600);
+        let mut file = self.create_in(files)?;
+        file.write_contents(contents)?;
+        file
+    }
+
     pub fn cached<F>(self, file_cacher: F) -> FileBuilder<Cached<F>>
     where
         F: Fn(&mut ContextFile, bool) -> Result<(), Error>,
@@ -178,6 +214,23 @@ impl ContextFile {
     pub fn set_len(&self, size: u64) {
         self.file.set_len(size)?
     }
+
+    /// Whether the file contains UTF-8 text, as opposed to binary or corrupt contents.
+    #[throws(io::Error)]
+    pub fn is_text(&self) -> bool {
+        let contents = fs::read(&self.local_path)?;
+        !contents.contains(&0) && std::str::from_utf8(&contents).is_ok()
+    }
+
+    /// Replaces the contents of the file, leaving the cursor at the start.
+    #[throws(Error)]
+    pub fn write_contents(&mut self, contents: &[u8]) {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        self.file.write_all(contents)?;
+        self.file.flush()?;
+        self.file.rewind()?;
+    }
 }
 
 impl Read for ContextFile {
@@ -225,3 +278,44 @@ impl Seek for ContextFile {
         self.file.seek(pos)?
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::{context::kv::PutOptions, util};
+
+    #[test]
+    #[throws(Error)]
+    fn create_file_with_contents() {
+        let files_dir = util::TempDir::new()?;
+        let mut files = Files::in_dir(files_dir.path().to_owned());
+
+        let file = FileBuilder::new(Cow::Borrowed(Key::new("config")))
+            .create_with_in(&mut files, b"key: value\n")?;
+
+        let contents = fs::read_to_string(&file.local_path)?;
+        let mode = file.file.metadata()?.permissions().mode();
+
+        assert_eq!(contents, "key: value\n");
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn get_path_of_value_is_not_a_file() {
+        let files_dir = util::TempDir::new()?;
+        let files = Files::in_dir(files_dir.path().to_owned());
+        let mut kv = Kv::new();
+        kv.put_value("nodes/adam/address", "10.0.0.1", PutOptions::default())?;
+
+        let res =
+            FileBuilder::new(Cow::Borrowed(Key::new("nodes/adam/address"))).path_in(&files, &kv);
+
+        assert!(matches!(
+            res,
+            Err(Error::NotAFile(key)) if key.as_str() == "nodes/adam/address",
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     io,
     os::unix::prelude::OpenOptionsExt,
     path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use crate::{
@@ -11,6 +12,28 @@ use crate::{
     util,
 };
 
+static KEEP_TEMP: AtomicBool = AtomicBool::new(false);
+
+/// Makes the temporary files survive cleanup, so they can be inspected after a run.
+pub fn set_keep_temp(keep_temp: bool) {
+    KEEP_TEMP.store(keep_temp, Ordering::SeqCst);
+}
+
+fn keep_temp() -> bool {
+    KEEP_TEMP.load(Ordering::SeqCst)
+}
+
+static CLEANUP_DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Makes cleanup only report the temporary files it would remove.
+pub fn set_cleanup_dry_run(dry_run: bool) {
+    CLEANUP_DRY_RUN.store(dry_run, Ordering::SeqCst);
+}
+
+fn cleanup_dry_run() -> bool {
+    CLEANUP_DRY_RUN.load(Ordering::SeqCst)
+}
+
 pub struct Temp {
     temp_dir: PathBuf,
     permissions: Option<u32>,
@@ -70,20 +93,37 @@ impl Temp {
         ContextFile::new(file, path, crate::container_temp_dir().join(random_key))
     }
 
+    /// Removes the temporary files, returning their paths.
     #[throws(Error)]
-    pub fn cleanup(&self) {
+    pub fn cleanup(&self) -> Vec<PathBuf> {
+        self.cleanup_files(keep_temp(), cleanup_dry_run())?
+    }
+
+    #[throws(Error)]
+    fn cleanup_files(&self, keep: bool, dry_run: bool) -> Vec<PathBuf> {
         let read_dir = match fs::read_dir(&self.temp_dir) {
             Ok(read_dir) => read_dir,
-            Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
             Err(err) => throw!(err),
         };
 
+        let mut paths = Vec::new();
         for entry in read_dir {
             let entry = entry?;
             if entry.file_type()?.is_file() {
-                fs::remove_file(entry.path())?;
+                let path = entry.path();
+                if keep {
+                    info!("Keeping temporary file: {path:?}");
+                } else if dry_run {
+                    info!("Would remove temporary file: {path:?}");
+                } else {
+                    fs::remove_file(&path)?;
+                }
+                paths.push(path);
             }
         }
+
+        paths
     }
 }
 
@@ -95,3 +135,58 @@ impl Default for Temp {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[throws(Error)]
+    fn keep_temp_retains_files() {
+        let temp_dir = util::TempDir::new()?;
+        let temp = Temp {
+            temp_dir: temp_dir.path().to_owned(),
+            permissions: None,
+        };
+        let file = temp.create_file()?;
+        let path = file.local_path.clone();
+        drop(file);
+
+        temp.cleanup_files(true, false)?;
+        assert!(path.exists());
+
+        temp.cleanup_files(false, false)?;
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn cleanup_follows_keep_temp() {
+        let temp_dir = util::TempDir::new()?;
+        let temp = Temp {
+            temp_dir: temp_dir.path().to_owned(),
+            permissions: None,
+        };
+        let path = temp.create_file()?.local_path;
+
+        // The flags are only read, so that tests running in parallel are not affected.
+        let retained = keep_temp() || cleanup_dry_run();
+        temp.cleanup()?;
+        assert_eq!(path.exists(), retained);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn dry_run_reports_files() {
+        let temp_dir = util::TempDir::new()?;
+        let temp = Temp {
+            temp_dir: temp_dir.path().to_owned(),
+            permissions: None,
+        };
+        let path = temp.create_file()?.local_path;
+
+        let candidates = temp.cleanup_files(false, true)?;
+        assert_eq!(candidates, [path.clone()]);
+        assert!(path.exists());
+    }
+}
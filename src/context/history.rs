@@ -0,0 +1,101 @@
+use std::fmt::{self, Display, Formatter};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct History {
+    runs: Vec<Run>,
+}
+
+impl History {
+    pub(super) fn new() -> Self {
+        Self { runs: Vec::new() }
+    }
+
+    /// Records the start of a command run, returning an id for finishing it.
+    pub fn start<C: Into<String>>(&mut self, command: C) -> usize {
+        self.runs.push(Run {
+            command: command.into(),
+            started: now(),
+            finished: None,
+            state: RunState::InProgress,
+        });
+        self.runs.len() - 1
+    }
+
+    pub fn finish(&mut self, id: usize, succeeded: bool) {
+        if let Some(run) = self.runs.get_mut(id) {
+            run.finished.replace(now());
+            run.state = if succeeded {
+                RunState::Succeeded
+            } else {
+                RunState::Failed
+            };
+        }
+    }
+
+    /// Iterates over the recorded runs, oldest first, optionally only those of a certain command.
+    pub fn runs<'a>(&'a self, command: Option<&'a str>) -> impl Iterator<Item = &'a Run> {
+        self.runs
+            .iter()
+            .filter(move |run| command.map_or(true, |command| run.command == command))
+    }
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub command: String,
+    pub started: String,
+    pub finished: Option<String>,
+    pub state: RunState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunState {
+    InProgress,
+    Succeeded,
+    Failed,
+}
+
+impl Display for RunState {
+    #[throws(fmt::Error)]
+    fn fmt(&self, f: &mut Formatter) {
+        match self {
+            Self::InProgress => write!(f, "in progress")?,
+            Self::Succeeded => write!(f, "succeeded")?,
+            Self::Failed => write!(f, "failed")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finished_runs_are_listed() {
+        let mut history = History::new();
+
+        let init = history.start("init");
+        let deploy = history.start("node deploy");
+        history.finish(init, true);
+
+        let runs: Vec<_> = history.runs(Some("init")).collect();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].command, "init");
+        assert_eq!(runs[0].state, RunState::Succeeded);
+        assert!(runs[0].finished.is_some());
+
+        history.finish(deploy, false);
+        let states: Vec<_> = history.runs(None).map(|run| run.state).collect();
+        assert_eq!(states, [RunState::Succeeded, RunState::Failed]);
+    }
+}
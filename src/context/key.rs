@@ -6,7 +6,7 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-use crate::prelude::*;
+use crate::{context::Error, prelude::*};
 
 pub fn get_known_prefix_for_template(template: &Key) -> &Key {
     let len: usize = template
@@ -205,6 +205,63 @@ impl<'a> FromIterator<KeyComponent<'a>> for KeyOwned {
     }
 }
 
+/// Builds a key one component at a time, validating each component.
+#[derive(Default)]
+pub struct KeyBuilder {
+    inner: KeyOwned,
+}
+
+#[allow(unused)]
+impl KeyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[throws(Error)]
+    pub fn push<C: AsRef<str>>(self, component: C) -> Self {
+        let component = component.as_ref();
+
+        let reason = if component.is_empty() {
+            Some("component is empty")
+        } else if component.contains('/') {
+            Some("component contains a '/'")
+        } else if component.contains('*') {
+            Some("component contains a wildcard")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            throw!(Error::InvalidKeyComponent(component.to_owned(), reason));
+        }
+
+        self.push_unchecked(component)
+    }
+
+    /// Adds a wildcard matching any single component.
+    pub fn wildcard(self) -> Self {
+        self.push_unchecked("*")
+    }
+
+    /// Adds a wildcard matching any number of nested components.
+    pub fn nested_wildcard(self) -> Self {
+        self.push_unchecked("**")
+    }
+
+    pub fn build(self) -> KeyOwned {
+        self.inner
+    }
+
+    fn push_unchecked(self, component: &str) -> Self {
+        let inner = if self.inner.as_str().is_empty() {
+            KeyOwned::from(component)
+        } else {
+            self.inner.join(component)
+        };
+        Self { inner }
+    }
+}
+
 pub struct Components<'a> {
     inner: std::str::Split<'a, char>,
 }
@@ -251,3 +308,35 @@ impl<'a> KeyComponent<'a> {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[throws(Error)]
+    fn build_keys_from_components() {
+        let node_name = "betsy";
+        assert_eq!(
+            key!("nodes", node_name, "network", "address")?.as_str(),
+            "nodes/betsy/network/address",
+        );
+        assert_eq!(
+            KeyBuilder::new().push("nodes")?.wildcard().build().as_str(),
+            "nodes/*",
+        );
+    }
+
+    #[test]
+    fn reject_invalid_components() {
+        for component in ["", "network/address", "/nodes", "*", "be**y"] {
+            assert!(
+                matches!(
+                    key!("nodes", component),
+                    Err(Error::InvalidKeyComponent(c, _)) if c == component,
+                ),
+                "{component:?} should be rejected",
+            );
+        }
+    }
+}
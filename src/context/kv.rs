@@ -1,12 +1,17 @@
 use std::{
+    collections::HashSet,
     fmt::{self, Debug, Display, Formatter},
     iter::Enumerate,
     marker::PhantomData,
-    ops::Deref,
+    mem,
+    ops::{Bound, Deref, RangeBounds},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     vec,
 };
 
 use indexmap::IndexMap;
+use once_cell::sync::OnceCell;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
@@ -19,43 +24,172 @@ use crate::{
     util::Opt,
 };
 
+/// Replaces the values of redacted keys in logs and exports.
+pub const REDACTED_VALUE: &str = "****";
+
+/// Prefix of the keys which are global to this machine, rather than part of the cluster context.
+pub const GLOBAL_PREFIX: &str = "$";
+
+static REDACTED: OnceCell<Vec<Regex>> = OnceCell::new();
+
+/// Sets the key templates whose values are hidden in logs and redacted exports.
+pub fn set_redacted<I, K>(templates: I)
+where
+    I: IntoIterator<Item = K>,
+    K: AsRef<Key>,
+{
+    REDACTED.get_or_init(|| {
+        templates
+            .into_iter()
+            .map(|template| template_regex(template.as_ref()))
+            .collect()
+    });
+}
+
+pub fn is_redacted(key: &Key) -> bool {
+    REDACTED.get().map_or(false, |regexes| {
+        regexes.iter().any(|regex| regex.is_match(key.as_str()))
+    })
+}
+
+/// Formats the value for logging, unless the key is redacted.
+fn display_value(key: &Key, value: &impl Display) -> String {
+    if is_redacted(key) {
+        REDACTED_VALUE.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+fn template_regex(template: &Key) -> Regex {
+    // Build the regex expression, replacing wildcards and escaping regex tokens
+    let mut regex_str = String::from("^");
+    regex_str += &template
+        .components()
+        .map(|comp| {
+            if comp.is_nested_wildcard() {
+                // Nested means we are able to span multiple components
+                ".*".to_owned()
+            } else {
+                // We may find '*' wildcard in the component, so we convert them to a regex
+                // expression that is bounded within the component
+                regex::escape(comp.as_str()).replace(r#"\*"#, "[^/]*")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    regex_str += "$";
+    Regex::new(&regex_str).expect("regular expression should be parsed correctly")
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Kv {
+    #[serde(
+        default,
+        rename = "$aliases",
+        skip_serializing_if = "IndexMap::is_empty"
+    )]
+    aliases: IndexMap<KeyOwned, KeyOwned>,
+
     #[serde(flatten)]
     map: IndexMap<KeyOwned, ValueType>,
+
+    #[serde(skip)]
+    watchers: Vec<Watcher>,
+
+    #[serde(skip)]
+    notifications: Vec<Notification>,
+}
+
+type WatchCallback = Arc<Mutex<dyn FnMut(&Key, &Value) + Send>>;
+
+struct Watcher {
+    regex: Regex,
+    callback: WatchCallback,
+}
+
+/// A change of a watched key, held until the store is no longer borrowed, so the callback is free
+/// to use it.
+pub struct Notification {
+    callback: WatchCallback,
+    key: KeyOwned,
+    value: Value,
+}
+
+impl Notification {
+    pub fn send(self) {
+        (self.callback.lock().expect(EXPECT_THREAD_NOT_POSIONED))(&self.key, &self.value);
+    }
 }
 
 impl Kv {
-    pub(super) fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
+            aliases: IndexMap::new(),
             map: IndexMap::new(),
+            watchers: Vec::new(),
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Follows any aliases from `key` to the key holding the value.
+    #[throws(Error)]
+    fn resolve_alias<'a>(&'a self, key: &'a Key) -> &'a Key {
+        let mut visited = HashSet::new();
+        let mut current = key;
+        while let Some(target) = self.aliases.get(current) {
+            if !visited.insert(current) {
+                throw!(Error::AliasCycle(key.to_owned()));
+            }
+            current = &**target;
         }
+        current
+    }
+
+    /// Makes `key` refer to the value of `target`. Reading or writing the alias reads or writes
+    /// the target. Returns the previous target of the alias, if any.
+    ///
+    /// Only literal keys are resolved, so wildcard templates, e.g. in [`Self::get_item`] or
+    /// [`Self::contains`], never match aliases.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn put_alias<K, T>(&mut self, key: K, target: T) -> Option<KeyOwned>
+    where
+        K: Into<KeyOwned>,
+        T: Into<KeyOwned>,
+    {
+        let key = key.into();
+        let target = target.into();
+
+        if self.map.contains_key(&*key) {
+            throw!(Error::KeyAlreadyExists(key));
+        }
+
+        debug!("Putting alias: {key:?} => {target:?}");
+        let previous = self.aliases.insert(key.clone(), target);
+
+        let resolved = self.resolve_alias(&key).map(|_| ());
+        if let Err(err) = resolved {
+            self.restore_alias(key, previous);
+            throw!(err);
+        }
+
+        previous
+    }
+
+    /// Points the alias back to `previous`, or removes it if there was no previous target.
+    pub(super) fn restore_alias(&mut self, key: KeyOwned, previous: Option<KeyOwned>) {
+        match previous {
+            Some(previous) => self.aliases.insert(key, previous),
+            None => self.aliases.remove(&*key),
+        };
     }
 
     fn get_keys<K>(&self, template: &K) -> Vec<KeyOwned>
     where
         K: AsRef<Key> + ?Sized,
     {
-        let template = template.as_ref();
-
-        // Build the regex expression, replacing wildcards and escaping regex tokens
-        let mut regex_str = String::from("^");
-        regex_str += &template
-            .components()
-            .map(|comp| {
-                if comp.is_nested_wildcard() {
-                    // Nested means we are able to span multiple components
-                    ".*".to_owned()
-                } else {
-                    // We may find '*' wildcard in the component, so we convert them to a regex
-                    // expression that is bounded within the component
-                    regex::escape(comp.as_str()).replace(r#"\*"#, "[^/]*")
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("/");
-        regex_str += "$";
-        let regex = Regex::new(&regex_str).expect("regular expression should be parsed correctly");
+        let regex = template_regex(template.as_ref());
 
         // Collect all keys that matches the regular expression
         self.map
@@ -65,6 +199,22 @@ impl Kv {
             .collect()
     }
 
+    /// Groups all keys by their components into a tree.
+    #[allow(unused)]
+    pub fn keys_tree(&self) -> KeyTree {
+        let mut tree = KeyTree::default();
+        for key in self.map.keys() {
+            let mut node = &mut tree;
+            for component in key.components() {
+                node = node
+                    .children
+                    .entry(component.as_str().to_owned())
+                    .or_default();
+            }
+        }
+        tree
+    }
+
     #[throws(Error)]
     pub fn get_item<K>(&self, template: &K) -> Item
     where
@@ -75,7 +225,7 @@ impl Kv {
         if !template.contains_wildcard() {
             return self
                 .map
-                .get(template)
+                .get(self.resolve_alias(template)?)
                 .map(|value| Item::Value(value.deref().clone()))
                 .ok_or_else(|| Error::KeyDoesNotExist(template.to_owned()))?;
         }
@@ -99,6 +249,147 @@ impl Kv {
             .ok_or_else(|| Error::KeyDoesNotExist(template.to_owned()))?
     }
 
+    /// Gets a flag, which may also be stored as a string like `"yes"`, `"1"` or `"off"`.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_bool_flag<K>(&self, key: &K) -> bool
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let key = key.as_ref();
+        match self.get_item(key)? {
+            Item::Value(Value::Bool(flag)) => flag,
+            Item::Value(Value::String(s)) => match s.to_lowercase().as_str() {
+                "true" | "yes" | "y" | "on" | "1" => true,
+                "false" | "no" | "n" | "off" | "0" | "" => false,
+                _ => throw!(Error::InvalidBoolFlag(key.to_owned(), s)),
+            },
+            item => throw!(Error::MismatchedTypes {
+                expected: TypeDescription::Bool,
+                actual: item.type_description(),
+            }),
+        }
+    }
+
+    /// Gets every value matching the template, paired with its full key.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_keyed<K>(&self, template: &K) -> Vec<(KeyOwned, Item)>
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let template = template.as_ref();
+
+        let keyed: Vec<_> = self
+            .get_keys(template)
+            .into_iter()
+            .map(|key| {
+                let value = self.map[&key].deref().clone();
+                (key, Item::Value(value))
+            })
+            .collect();
+
+        if keyed.is_empty() {
+            throw!(Error::KeyDoesNotExist(template.to_owned()));
+        }
+
+        keyed
+    }
+
+    /// Gets several items at once, with a separate result for each key.
+    #[allow(unused)]
+    pub fn get_many<I, K>(&self, templates: I) -> Vec<(KeyOwned, Result<Item, Error>)>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<Key>,
+    {
+        templates
+            .into_iter()
+            .map(|template| {
+                let template = template.as_ref();
+                (template.to_owned(), self.get_item(template))
+            })
+            .collect()
+    }
+
+    /// Gets the number of characters in a string value.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_string_len<K>(&self, key: &K) -> usize
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        self.get_string(key)?.chars().count()
+    }
+
+    /// Gets a substring of a string value, indexed by characters. Out-of-range bounds are clamped
+    /// to the string.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_string_slice<K, R>(&self, key: &K, range: R) -> String
+    where
+        K: AsRef<Key> + ?Sized,
+        R: RangeBounds<usize>,
+    {
+        let string = self.get_string(key)?;
+        let len = string.chars().count();
+
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(len);
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        }
+        .clamp(start, len);
+
+        string.chars().skip(start).take(end - start).collect()
+    }
+
+    /// Gets an array where every element is converted to `T`. A failed conversion reports the
+    /// index of the offending element.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_typed_array<T, K>(&self, key: &K) -> Vec<T>
+    where
+        T: TryFrom<Item>,
+        Error: From<<T as TryFrom<Item>>::Error>,
+        K: AsRef<Key> + ?Sized,
+    {
+        let key = key.as_ref();
+
+        let array = match self.get_item(&key.join("**"))? {
+            Item::Array(array) => array,
+            item => throw!(Error::MismatchedTypes {
+                expected: TypeDescription::Array(Vec::new()),
+                actual: item.type_description(),
+            }),
+        };
+
+        let mut typed_array = Vec::with_capacity(array.len());
+        for (index, element) in array.into_iter().enumerate() {
+            let element = T::try_from(element).map_err(|err| Error::ArrayElement {
+                key: key.to_owned(),
+                index,
+                source: Box::new(err.into()),
+            })?;
+            typed_array.push(element);
+        }
+        typed_array
+    }
+
+    #[throws(Error)]
+    fn get_string<K>(&self, key: &K) -> String
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        String::try_from(Value::try_from(self.get_item(key)?)?)?
+    }
+
     pub fn item_exists<K>(&self, key: &K) -> bool
     where
         K: AsRef<Key> + ?Sized,
@@ -108,6 +399,25 @@ impl Kv {
             .any(|k| k.as_str().starts_with(key.as_ref().as_str()))
     }
 
+    /// Whether the literal key holds a value, or whether any key matches the template if it
+    /// contains wildcards. Unlike [`Self::get_item`], no values are cloned.
+    #[allow(unused)]
+    pub fn contains<K>(&self, template: &K) -> bool
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let template = template.as_ref();
+
+        if !template.contains_wildcard() {
+            return self
+                .resolve_alias(template)
+                .map_or(false, |key| self.map.contains_key(key));
+        }
+
+        let regex = template_regex(template);
+        self.map.keys().any(|key| regex.is_match(key.as_str()))
+    }
+
     /// Puts a value in the key-value store.
     ///
     /// Returns `None` if no previous value was present, `Some(None)` if a value is already present
@@ -123,8 +433,9 @@ impl Kv {
         K: Into<KeyOwned>,
         V: Into<Value> + Clone + Display,
     {
-        let key = key.into();
-        let into_value = value.clone();
+        let key: KeyOwned = key.into();
+        let key = self.resolve_alias(&key)?.to_owned();
+        let into_value = display_value(&key, &value);
         let value = if !options.temporary {
             ValueType::Persistent(value.into())
         } else {
@@ -144,6 +455,17 @@ impl Kv {
         let mut should_overwrite = false;
         'get: {
             match self.map.get(&*key) {
+                Some(existing)
+                    if options.update
+                        && !existing
+                            .type_description()
+                            .compatible_with(&value.type_description()) =>
+                {
+                    throw!(Error::MismatchedTypes {
+                        expected: existing.type_description(),
+                        actual: value.type_description(),
+                    });
+                }
                 Some(existing) if **existing != *value => {
                     if existing.is_temporary() && value.is_persistent() {
                         error!("Key {key:?} is already set with a different value and is marked as temporary");
@@ -207,17 +529,83 @@ impl Kv {
         } else {
             debug!(
                 "Old item for key {key:?}: {}",
-                serde_json::to_string(&self.get_item(&*key)?)?
+                display_value(&key, &self.get_item(&*key)?.pretty(2))
             );
             warn!("{verb} {desc}: {key:?} => {into_value} (overwriting)");
         }
 
+        for watcher in &self.watchers {
+            if watcher.regex.is_match(key.as_str()) {
+                self.notifications.push(Notification {
+                    callback: Arc::clone(&watcher.callback),
+                    key: key.clone(),
+                    value: (*value).clone(),
+                });
+            }
+        }
+
         self.map
             .insert(key, value)
             .map(ValueType::into_inner)
             .map(Some)
     }
 
+    /// Registers a callback, called whenever a value is put or updated for a key matching the
+    /// template. Callbacks are not persisted, so they only last for the current run.
+    ///
+    /// The callbacks are not called right away, but through the notifications returned by
+    /// [`Self::take_notifications`].
+    #[allow(unused)]
+    pub fn watch<K, F>(&mut self, template: &K, callback: F)
+    where
+        K: AsRef<Key> + ?Sized,
+        F: FnMut(&Key, &Value) + Send + 'static,
+    {
+        self.watchers.push(Watcher {
+            regex: template_regex(template.as_ref()),
+            callback: Arc::new(Mutex::new(callback)),
+        });
+    }
+
+    /// Takes the changes of watched keys since the last call, to be sent once the store is no
+    /// longer borrowed.
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        mem::take(&mut self.notifications)
+    }
+
+    /// Updates the value if the key exists, or puts it otherwise. A value of a mismatched type
+    /// still results in an error.
+    ///
+    /// Returns the same as [`Self::put_value`].
+    #[throws(Error)]
+    pub fn update_or_put_value<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        temporary: bool,
+    ) -> Option<Option<Value>>
+    where
+        K: Into<KeyOwned>,
+        V: Into<Value> + Clone + Display,
+    {
+        let key = key.into();
+        let update = self.map.contains_key(self.resolve_alias(&key)?);
+        self.put_value(key, value, PutOptions { temporary, update })?
+    }
+
+    /// Gets the entry of the key, for inserting or modifying its value in place.
+    #[allow(unused)]
+    pub fn entry<K>(&mut self, key: K, temporary: bool) -> Entry
+    where
+        K: Into<KeyOwned>,
+    {
+        Entry {
+            kv: self,
+            key: key.into(),
+            temporary,
+        }
+    }
+
     #[throws(Error)]
     #[allow(unused)]
     pub fn put_array<K, V, I>(&mut self, key_prefix: K, array: I, options: PutOptions)
@@ -248,6 +636,124 @@ impl Kv {
         }
     }
 
+    /// Puts a JSON value, flattening objects and arrays into keys under the key prefix.
+    #[throws(Error)]
+    #[allow(unused)]
+    pub fn put_json_value<K>(
+        &mut self,
+        key_prefix: K,
+        value: serde_json::Value,
+        options: PutOptions,
+    ) where
+        K: Into<KeyOwned>,
+    {
+        self.put_item(key_prefix.into(), Item::try_from(value)?, options)?;
+    }
+
+    /// Appends an item to the end of the array at the key prefix. If `unique` is set, the item is
+    /// not appended if an equal item already exists in the array.
+    ///
+    /// Returns whether the item was appended.
+    #[throws(Error)]
+    #[allow(unused)]
+    pub fn append_item<K, I>(
+        &mut self,
+        key_prefix: K,
+        item: I,
+        unique: bool,
+        options: PutOptions,
+    ) -> bool
+    where
+        K: Into<KeyOwned>,
+        I: Into<Item>,
+    {
+        let key_prefix = key_prefix.into();
+        let item = item.into();
+
+        let array = match self.get_item(&key_prefix.join("**")) {
+            Ok(Item::Array(array)) => array,
+            Ok(existing) => throw!(Error::MismatchedTypes {
+                expected: TypeDescription::Array(Vec::new()),
+                actual: existing.type_description(),
+            }),
+            Err(Error::KeyDoesNotExist(_)) => Vec::new(),
+            Err(err) => throw!(err),
+        };
+
+        if unique && array.contains(&item) {
+            return false;
+        }
+
+        self.put_item(key_prefix.join(&array.len().to_string()), item, options)?;
+        true
+    }
+
+    /// Extends the array at the key prefix with every item, initializing it if it doesn't exist.
+    /// Returns the number of appended items.
+    #[throws(Error)]
+    #[allow(unused)]
+    pub fn append_items<K, I, T>(
+        &mut self,
+        key_prefix: K,
+        items: I,
+        unique: bool,
+        options: PutOptions,
+    ) -> usize
+    where
+        K: Into<KeyOwned>,
+        I: IntoIterator<Item = T>,
+        T: Into<Item>,
+    {
+        let key_prefix = key_prefix.into();
+
+        let mut array = match self.get_item(&key_prefix.join("**")) {
+            Ok(Item::Array(array)) => array,
+            Ok(existing) => throw!(Error::MismatchedTypes {
+                expected: TypeDescription::Array(Vec::new()),
+                actual: existing.type_description(),
+            }),
+            Err(Error::KeyDoesNotExist(_)) => Vec::new(),
+            Err(err) => throw!(err),
+        };
+
+        let mut appended = 0;
+        for item in items {
+            let item = item.into();
+            if unique && array.contains(&item) {
+                continue;
+            }
+
+            self.put_item(
+                key_prefix.join(&array.len().to_string()),
+                item.clone(),
+                options,
+            )?;
+            array.push(item);
+            appended += 1;
+        }
+
+        appended
+    }
+
+    #[throws(Error)]
+    fn put_item(&mut self, key: KeyOwned, item: Item, options: PutOptions) {
+        match item {
+            Item::Value(value) => {
+                self.put_value(key, value, options)?;
+            }
+            Item::Array(array) => {
+                for (index, item) in array.into_iter().enumerate() {
+                    self.put_item(key.join(&index.to_string()), item, options)?;
+                }
+            }
+            Item::Map(map) => {
+                for (map_key, item) in map {
+                    self.put_item(key.join(&map_key), item, options)?;
+                }
+            }
+        }
+    }
+
     #[throws(Error)]
     pub fn drop_item<K, D>(&mut self, template: &K, on_drop: D) -> Option<Item>
     where
@@ -312,6 +818,224 @@ impl Kv {
         self.map
             .retain(|_, value| matches!(value, ValueType::Persistent(_)));
     }
+
+    /// Captures the current values, which can later be compared against the key-value store.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            map: Arc::new(
+                self.map
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.deref().clone()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// An entry of the key-value store, which may or may not hold a value.
+pub struct Entry<'kv> {
+    kv: &'kv mut Kv,
+    key: KeyOwned,
+    temporary: bool,
+}
+
+impl Entry<'_> {
+    #[throws(Error)]
+    fn existing_value(&self) -> Option<Value> {
+        let key = self.kv.resolve_alias(&self.key)?;
+        self.kv.map.get(key).map(|value| (**value).clone())
+    }
+
+    /// Modifies the value if the key exists. The modified value is updated in the store, so it must
+    /// be of the same type as the existing value.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Value),
+    {
+        if let Some(mut value) = self.existing_value()? {
+            f(&mut value);
+            self.kv.put_value(
+                self.key.clone(),
+                value,
+                PutOptions {
+                    temporary: self.temporary,
+                    update: true,
+                },
+            )?;
+        }
+        self
+    }
+
+    /// Puts the value if the key does not exist.
+    ///
+    /// Returns the value held by the key afterwards.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn or_insert<V>(mut self, value: V) -> Value
+    where
+        V: Into<Value> + Clone + Display,
+    {
+        if let Some(existing) = self.existing_value()? {
+            return existing;
+        }
+
+        self.kv.put_value(
+            self.key,
+            value.clone(),
+            PutOptions {
+                temporary: self.temporary,
+                update: false,
+            },
+        )?;
+        value.into()
+    }
+}
+
+/// The keys of the key-value store, grouped by their components.
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyTree {
+    children: IndexMap<String, KeyTree>,
+}
+
+impl KeyTree {
+    #[allow(unused)]
+    pub fn get(&self, component: &str) -> Option<&Self> {
+        self.children.get(component)
+    }
+
+    #[allow(unused)]
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.children.keys().map(String::as_str)
+    }
+
+    #[throws(fmt::Error)]
+    fn fmt_children(&self, f: &mut Formatter, prefix: &str) {
+        for (i, (component, child)) in self.children.iter().enumerate() {
+            let is_last = i + 1 == self.children.len();
+            let (branch, indent) = if is_last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            writeln!(f, "{prefix}{branch}{component}")?;
+            child.fmt_children(f, &format!("{prefix}{indent}"))?;
+        }
+    }
+}
+
+impl Display for KeyTree {
+    #[throws(fmt::Error)]
+    fn fmt(&self, f: &mut Formatter) {
+        for (component, child) in &self.children {
+            writeln!(f, "{component}")?;
+            child.fmt_children(f, "")?;
+        }
+    }
+}
+
+/// A read-only view of the key-value store at the time it was taken. Cloning it is cheap.
+#[derive(Clone)]
+pub struct Snapshot {
+    map: Arc<IndexMap<KeyOwned, Value>>,
+}
+
+impl Snapshot {
+    /// Gets an item as it was when the snapshot was taken.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn get_item<K>(&self, template: &K) -> Item
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let template = template.as_ref();
+
+        if !template.contains_wildcard() {
+            return self
+                .map
+                .get(template)
+                .map(|value| Item::Value(value.clone()))
+                .ok_or_else(|| Error::KeyDoesNotExist(template.to_owned()))?;
+        }
+
+        let regex = template_regex(template);
+        let mut item_builder = ItemBuilder::new(template);
+
+        for (key, value) in self.map.iter() {
+            if regex.is_match(key.as_str()) {
+                item_builder.push(key.clone(), value.clone());
+            }
+        }
+
+        item_builder
+            .build()
+            .ok_or_else(|| Error::KeyDoesNotExist(template.to_owned()))?
+    }
+
+    /// Lists the changes made in `kv` since the snapshot was taken.
+    pub fn diff(&self, kv: &Kv) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for (key, old) in self.map.iter() {
+            match kv.map.get(key) {
+                Some(new) if **new != *old => changes.push(Change::Modified {
+                    key: key.clone(),
+                    old: old.clone(),
+                    new: new.deref().clone(),
+                }),
+                Some(_) => (),
+                None => changes.push(Change::Removed {
+                    key: key.clone(),
+                    value: old.clone(),
+                }),
+            }
+        }
+
+        for (key, new) in &kv.map {
+            if !self.map.contains_key(key) {
+                changes.push(Change::Added {
+                    key: key.clone(),
+                    value: new.deref().clone(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added {
+        key: KeyOwned,
+        value: Value,
+    },
+    Removed {
+        key: KeyOwned,
+        value: Value,
+    },
+    Modified {
+        key: KeyOwned,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl Display for Change {
+    #[throws(fmt::Error)]
+    fn fmt(&self, f: &mut Formatter) {
+        match self {
+            Self::Added { key, value } => write!(f, "+ {key:?}: {}", display_value(key, value))?,
+            Self::Removed { key, value } => write!(f, "- {key:?}: {}", display_value(key, value))?,
+            Self::Modified { key, old, new } => write!(
+                f,
+                "~ {key:?}: {} => {}",
+                display_value(key, old),
+                display_value(key, new),
+            )?,
+        }
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -460,6 +1184,200 @@ impl Item {
         T::try_from(self)?
     }
 
+    /// Converts the item to an absolute path, resolving a relative path against `base`.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn into_path(self, base: &Path) -> PathBuf {
+        let path = self.convert::<String>()?;
+        if path.is_empty() {
+            throw!(Error::InvalidPath(path, "path is empty"));
+        }
+        if path.contains('\0') {
+            throw!(Error::InvalidPath(path, "path contains a nul byte"));
+        }
+
+        let path = PathBuf::from(path);
+        if path.is_absolute() {
+            path
+        } else {
+            base.join(path)
+        }
+    }
+
+    /// Formats the item for printing to scripts. Strings are printed as-is, other values in
+    /// their plain form and arrays and maps as JSON.
+    #[throws(Error)]
+    pub fn to_output_string(&self) -> String {
+        match self {
+            Self::Value(Value::String(s)) => s.clone(),
+            Self::Value(value) => value.to_string(),
+            item => serde_json::to_string(item)?,
+        }
+    }
+
+    /// Formats the item for debug output, with nested arrays and maps on separate lines indented
+    /// by `indent` spaces per level. Strings and map keys are quoted.
+    pub fn pretty(&self, indent: usize) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, indent, 0);
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, indent: usize, level: usize) {
+        let (open, close, len) = match self {
+            Self::Value(value) => {
+                output.push_str(&value.to_string());
+                return;
+            }
+            Self::Array(array) => ('[', ']', array.len()),
+            Self::Map(map) => ('{', '}', map.len()),
+        };
+
+        output.push(open);
+        if len == 0 {
+            output.push(close);
+            return;
+        }
+
+        let padding = " ".repeat(indent * (level + 1));
+        let mut write_line = |key: Option<&str>, item: &Self, is_last: bool| {
+            output.push('\n');
+            output.push_str(&padding);
+            if let Some(key) = key {
+                output.push_str(&format!("{key:?}: "));
+            }
+            item.write_pretty(output, indent, level + 1);
+            if !is_last {
+                output.push(',');
+            }
+        };
+
+        match self {
+            Self::Array(array) => {
+                for (i, item) in array.iter().enumerate() {
+                    write_line(None, item, i + 1 == len);
+                }
+            }
+            Self::Map(map) => {
+                for (i, (key, item)) in map.iter().enumerate() {
+                    write_line(Some(key), item, i + 1 == len);
+                }
+            }
+            Self::Value(_) => unreachable!(),
+        }
+
+        output.push('\n');
+        output.push_str(&" ".repeat(indent * level));
+        output.push(close);
+    }
+
+    /// Merges `other` into the item. Maps are merged recursively and arrays are concatenated,
+    /// while values in `other` replace values in the item.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn deep_merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Map(mut map), Self::Map(other_map)) => {
+                for (key, other_item) in other_map {
+                    match map.get_mut(&key) {
+                        Some(item) => {
+                            let current = mem::replace(item, Self::Array(Vec::new()));
+                            *item = current.deep_merge(other_item)?;
+                        }
+                        None => {
+                            map.insert(key, other_item);
+                        }
+                    }
+                }
+                Self::Map(map)
+            }
+            (Self::Array(mut array), Self::Array(other_array)) => {
+                array.extend(other_array);
+                Self::Array(array)
+            }
+            (Self::Value(_), other @ Self::Value(_)) => other,
+            (item, other) => throw!(Error::MismatchedTypes {
+                expected: item.type_description(),
+                actual: other.type_description(),
+            }),
+        }
+    }
+
+    /// Formats a map of values and arrays of values as `KEY=VALUE` lines, which can be sourced by
+    /// shell scripts. Strings are single-quoted and array elements are indexed as `KEY_0`,
+    /// `KEY_1`, etc.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn to_env_lines(&self) -> String {
+        let Self::Map(map) = self else {
+            throw!(Error::UnsupportedEnvItem(String::new()));
+        };
+
+        let mut lines = String::new();
+        for (name, item) in map {
+            if !is_env_name(name) || indexed_env_name(name).is_some() {
+                throw!(Error::UnsupportedEnvItem(name.clone()));
+            }
+
+            match item {
+                Self::Value(value) => lines += &format!("{name}={}\n", env_value(value)),
+                Self::Array(array) => {
+                    for (index, item) in array.iter().enumerate() {
+                        let Self::Value(value) = item else {
+                            throw!(Error::UnsupportedEnvItem(format!("{name}_{index}")));
+                        };
+                        lines += &format!("{name}_{index}={}\n", env_value(value));
+                    }
+                }
+                Self::Map(_) => throw!(Error::UnsupportedEnvItem(name.clone())),
+            }
+        }
+
+        lines
+    }
+
+    /// Parses `KEY=VALUE` lines formatted by [`Item::to_env_lines`] into a map. Empty lines and
+    /// comments are ignored.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn from_env_lines(s: &str) -> Self {
+        let mut map = IndexMap::new();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let Some((name, raw_value)) = line.split_once('=') else {
+                throw!(Error::InvalidEnvLine(line.to_owned(), "missing '='"));
+            };
+            if !is_env_name(name) {
+                throw!(Error::InvalidEnvLine(line.to_owned(), "invalid name"));
+            }
+            let value = parse_env_value(raw_value)
+                .ok_or_else(|| Error::InvalidEnvLine(line.to_owned(), "unterminated quote"))?;
+
+            match indexed_env_name(name) {
+                Some((name, index)) => {
+                    let entry = map
+                        .entry(name.to_owned())
+                        .or_insert_with(|| Self::Array(Vec::new()));
+                    match entry {
+                        Self::Array(array) if array.len() == index => array.push(value.into()),
+                        _ => throw!(Error::InvalidEnvLine(line.to_owned(), "unexpected index")),
+                    }
+                }
+                None => {
+                    if map.insert(name.to_owned(), value.into()).is_some() {
+                        throw!(Error::InvalidEnvLine(line.to_owned(), "duplicate name"));
+                    }
+                }
+            }
+        }
+
+        Self::Map(map)
+    }
+
     #[throws(as Option)]
     pub fn get<K>(&self, key: &K) -> &Self
     where
@@ -484,6 +1402,43 @@ impl Item {
         current
     }
 
+    /// Sets the item at the nested key, creating any missing intermediate maps. An array element
+    /// can be replaced, or pushed right after the last one.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn set<K>(&mut self, key: &K, item: Item)
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let key = key.as_ref();
+
+        let mut current = self;
+        let mut path = Vec::new();
+        for component in key.components() {
+            let component = component.as_str();
+            current = match current {
+                Self::Value(_) => throw!(Error::NotAMap(key.to_owned(), path.join("/"))),
+                Self::Array(array) => {
+                    let index = component
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&index| index <= array.len())
+                        .ok_or_else(|| Error::KeyDoesNotExist(key.to_owned()))?;
+                    if index == array.len() {
+                        array.push(Self::Map(IndexMap::new()));
+                    }
+                    &mut array[index]
+                }
+                Self::Map(map) => map
+                    .entry(component.to_owned())
+                    .or_insert_with(|| Self::Map(IndexMap::new())),
+            };
+            path.push(component);
+        }
+
+        *current = item;
+    }
+
     #[throws(as Option)]
     pub fn take<K>(self, key: &K) -> Self
     where
@@ -553,6 +1508,65 @@ impl Item {
     }
 }
 
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .filter(|c| c.is_ascii_alphabetic() || *c == '_')
+        .is_some()
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn indexed_env_name(name: &str) -> Option<(&str, usize)> {
+    let (name, index) = name.rsplit_once('_')?;
+    if name.is_empty() || index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, index.parse().ok()?))
+}
+
+fn env_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s.replace('\'', r"'\''")),
+        value => value.to_string(),
+    }
+}
+
+fn parse_env_value(raw: &str) -> Option<Value> {
+    if !raw.starts_with('\'') {
+        let value = if let Ok(b) = raw.parse() {
+            Value::Bool(b)
+        } else if let Ok(u) = raw.parse() {
+            Value::UnsignedInteger(u)
+        } else if let Ok(i) = raw.parse() {
+            Value::SignedInteger(i)
+        } else if let Ok(f) = raw.parse() {
+            Value::FloatingPointNumber(f)
+        } else {
+            Value::String(raw.to_owned())
+        };
+        return Some(value);
+    }
+
+    // Quoted strings are concatenated, where `'\''` is an escaped quote.
+    let mut string = String::new();
+    let mut rest = raw;
+    loop {
+        let (quoted, after) = rest.strip_prefix('\'')?.split_once('\'')?;
+        string += quoted;
+        match after.strip_prefix(r"\'") {
+            Some(after) => {
+                string.push('\'');
+                rest = after;
+            }
+            None if after.is_empty() => break,
+            None => return None,
+        }
+    }
+
+    Some(Value::String(string))
+}
+
 impl IntoIterator for Item {
     type IntoIter = IntoIter;
     type Item = <IntoIter as Iterator>::Item;
@@ -586,6 +1600,39 @@ where
     }
 }
 
+impl TryFrom<serde_json::Value> for Item {
+    type Error = Error;
+
+    #[throws(Self::Error)]
+    fn try_from(value: serde_json::Value) -> Self {
+        use serde_json::Value as Json;
+
+        match value {
+            Json::Null => throw!(Error::UnsupportedNull),
+            Json::Bool(b) => Self::from(b),
+            Json::Number(n) => Self::Value(if let Some(n) = n.as_u64() {
+                Value::UnsignedInteger(n)
+            } else if let Some(n) = n.as_i64() {
+                Value::SignedInteger(n)
+            } else {
+                Value::FloatingPointNumber(n.as_f64().expect("number should be a float"))
+            }),
+            Json::String(s) => Self::from(s),
+            Json::Array(array) => Self::Array(
+                array
+                    .into_iter()
+                    .map(Self::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Json::Object(map) => Self::Map(
+                map.into_iter()
+                    .map(|(key, value)| Ok((key, Self::try_from(value)?)))
+                    .collect::<Result<_, Error>>()?,
+            ),
+        }
+    }
+}
+
 impl<T> TryFrom<Item> for Vec<T>
 where
     T: TryFrom<Item>,
@@ -1109,6 +2156,50 @@ pub enum TypeDescription {
     Map(Vec<Self>),
 }
 
+impl TypeDescription {
+    /// Checks whether the types are compatible. Arrays and maps are compatible if their element
+    /// types can be paired up with each other, regardless of order.
+    pub fn compatible_with(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Value, other) | (other, Self::Value) => {
+                !matches!(other, Self::Array(_) | Self::Map(_))
+            }
+            (Self::Array(lhs), Self::Array(rhs)) | (Self::Map(lhs), Self::Map(rhs)) => {
+                lhs.is_empty() || rhs.is_empty() || Self::pairwise_compatible(lhs, rhs)
+            }
+            (lhs, rhs) => lhs == rhs,
+        }
+    }
+
+    fn pairwise_compatible(lhs: &[Self], rhs: &[Self]) -> bool {
+        fn find_pairing(
+            lhs: &[TypeDescription],
+            rhs: &[TypeDescription],
+            used: &mut [bool],
+        ) -> bool {
+            let Some((first, rest)) = lhs.split_first() else {
+                return true;
+            };
+
+            for (i, other) in rhs.iter().enumerate() {
+                if used[i] || !first.compatible_with(other) {
+                    continue;
+                }
+
+                used[i] = true;
+                if find_pairing(rest, rhs, used) {
+                    return true;
+                }
+                used[i] = false;
+            }
+
+            false
+        }
+
+        lhs.len() == rhs.len() && find_pairing(lhs, rhs, &mut vec![false; rhs.len()])
+    }
+}
+
 impl Display for TypeDescription {
     #[throws(fmt::Error)]
     fn fmt(&self, f: &mut Formatter) {
@@ -1281,10 +2372,13 @@ mod tests {
         }
 
         Kv {
+            aliases: IndexMap::new(),
             map: item_map
                 .into_iter()
                 .flat_map(key_item_to_key_values)
                 .collect(),
+            watchers: Vec::new(),
+            notifications: Vec::new(),
         }
     }
 
@@ -1601,6 +2695,600 @@ mod tests {
         expect!("array/*/**" => m_array());
     }
 
+    #[test]
+    fn get_many() {
+        let nested = m_nested();
+        let root = item_map! {
+            **r_root(),
+            map nested,
+        };
+
+        let kv = item_map_to_kv(root);
+
+        let mut results = kv
+            .get_many([
+                "unsigned",
+                "missing",
+                "nested/two/betsy/*/token",
+                "nested/*/missing",
+            ])
+            .into_iter();
+
+        let (key, unsigned) = results.next().unwrap();
+        assert_eq!(key.as_str(), "unsigned");
+        expect_equal!(Item::from(v_unsigned()), unsigned.unwrap());
+
+        let (key, missing) = results.next().unwrap();
+        assert_eq!(key.as_str(), "missing");
+        assert!(matches!(missing, Err(Error::KeyDoesNotExist(key)) if key.as_str() == "missing"));
+
+        let (key, tokens) = results.next().unwrap();
+        assert_eq!(key.as_str(), "nested/two/betsy/*/token");
+        let tokens: Vec<_> = tokens.unwrap().into_values().map(Item::Value).collect();
+        expect_equal!(item_array!["t1", "t2", "t3", "t4"], tokens);
+
+        let (key, missing) = results.next().unwrap();
+        assert_eq!(key.as_str(), "nested/*/missing");
+        assert!(matches!(missing, Err(Error::KeyDoesNotExist(_))));
+
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn get_keyed() {
+        let kv = item_map_to_kv(item_map! { "nested" map=> m_nested() });
+
+        let tokens: Vec<_> = kv
+            .get_keyed("nested/two/betsy/*/token")?
+            .into_iter()
+            .map(|(key, item)| (key.as_str().to_owned(), item))
+            .collect();
+        expect_equal!(
+            vec![
+                ("nested/two/betsy/alpha/token".to_owned(), Item::from("t1")),
+                ("nested/two/betsy/beta/token".to_owned(), Item::from("t2")),
+                ("nested/two/betsy/delta/token".to_owned(), Item::from("t3")),
+                ("nested/two/betsy/gamma/token".to_owned(), Item::from("t4")),
+            ],
+            tokens
+        );
+
+        assert!(matches!(
+            kv.get_keyed("nested/*/missing"),
+            Err(Error::KeyDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn item_into_path() {
+        let base = Path::new("/home/hoc");
+
+        assert_eq!(
+            Item::from("/etc/hosts").into_path(base)?,
+            Path::new("/etc/hosts")
+        );
+        assert_eq!(
+            Item::from("kube/config").into_path(base)?,
+            Path::new("/home/hoc/kube/config")
+        );
+        assert!(matches!(
+            Item::from("kube\0config").into_path(base),
+            Err(Error::InvalidPath(..))
+        ));
+        assert!(matches!(
+            Item::from(1u8).into_path(base),
+            Err(Error::MismatchedTypes { .. })
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn set_nested_item() {
+        let mut item = Item::Map(item_map! {
+            "name" => "hoc",
+        });
+
+        item.set("network/cidr", Item::from("10.0.0.0/16"))?;
+        item.set("network/cidr", Item::from("10.1.0.0/16"))?;
+        item.set("network/gateway", Item::from("10.1.0.1"))?;
+
+        expect_equal!(
+            Item::Map(item_map! {
+                "name" => "hoc",
+                "network" map=> item_map! {
+                    "cidr" => "10.1.0.0/16",
+                    "gateway" => "10.1.0.1",
+                },
+            }),
+            item
+        );
+
+        let res = item.set("name/first", Item::from("h"));
+        assert!(matches!(res, Err(Error::NotAMap(_, path)) if path == "name"));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn get_bool_flag() {
+        let kv = item_map_to_kv(item_map! {
+            "bool" => true,
+            "yes" => "Yes",
+            "zero" => "0",
+            "invalid" => "maybe",
+        });
+
+        assert!(kv.get_bool_flag("bool")?);
+        assert!(kv.get_bool_flag("yes")?);
+        assert!(!kv.get_bool_flag("zero")?);
+        assert!(matches!(
+            kv.get_bool_flag("invalid"),
+            Err(Error::InvalidBoolFlag(key, value)) if key.as_str() == "invalid" && value == "maybe"
+        ));
+    }
+
+    #[test]
+    fn keys_tree() {
+        let kv = item_map_to_kv(item_map! { "nested" map=> m_nested() });
+
+        let tree = kv.keys_tree();
+        let betsy = tree
+            .get("nested")
+            .and_then(|t| t.get("two"))
+            .and_then(|t| t.get("betsy"))
+            .unwrap();
+        assert_eq!(
+            betsy.components().collect::<Vec<_>>(),
+            ["alpha", "beta", "delta", "gamma"]
+        );
+        assert!(betsy.get("alpha").and_then(|t| t.get("token")).is_some());
+
+        assert_eq!(
+            tree.to_string(),
+            "nested\n\
+             ├── one\n\
+             └── two\n    \
+                 ├── adam\n    \
+                 └── betsy\n        \
+                     ├── alpha\n        \
+                     │   └── token\n        \
+                     ├── beta\n        \
+                     │   └── token\n        \
+                     ├── delta\n        \
+                     │   └── token\n        \
+                     └── gamma\n            \
+                         └── token\n",
+        );
+    }
+
+    #[test]
+    fn string_queries() {
+        let kv = item_map_to_kv(r_root());
+
+        assert_eq!(kv.get_string_len("string").unwrap(), 5);
+        assert_eq!(kv.get_string_slice("string", ..).unwrap(), "hello");
+        assert_eq!(kv.get_string_slice("string", 1..3).unwrap(), "el");
+        assert_eq!(kv.get_string_slice("string", ..=1).unwrap(), "he");
+        assert_eq!(kv.get_string_slice("string", 3..100).unwrap(), "lo");
+        assert_eq!(kv.get_string_slice("string", 10..).unwrap(), "");
+        assert!(matches!(
+            kv.get_string_len("boolean"),
+            Err(Error::MismatchedTypes {
+                expected: TypeDescription::String,
+                actual: TypeDescription::Bool,
+            })
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn put_json_value() {
+        let mut kv = Kv::new();
+        let options = PutOptions::default();
+
+        kv.put_json_value(
+            "app",
+            serde_json::json!({
+                "name": "hoc",
+                "replicas": 3,
+                "offset": -1,
+                "ratio": 0.5,
+                "enabled": true,
+                "ports": [80, 443],
+            }),
+            options,
+        )?;
+
+        expect_equal!(Item::from("hoc"), kv.get_item("app/name")?);
+        expect_equal!(Item::from(3u64), kv.get_item("app/replicas")?);
+        expect_equal!(Item::from(-1i64), kv.get_item("app/offset")?);
+        expect_equal!(Item::from(0.5), kv.get_item("app/ratio")?);
+        expect_equal!(Item::from(true), kv.get_item("app/enabled")?);
+        expect_equal!(Item::from(443u64), kv.get_item("app/ports/1")?);
+
+        assert!(matches!(
+            kv.put_json_value("null", serde_json::json!({ "value": null }), options),
+            Err(Error::UnsupportedNull)
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn append_many() {
+        let mut kv = Kv::new();
+        let options = PutOptions::default();
+
+        assert_eq!(kv.append_items("list", ["a", "b"], false, options)?, 2);
+        assert_eq!(kv.append_items("list", ["b", "c"], true, options)?, 1);
+        expect_equal!(
+            Item::Array(item_array!["a", "b", "c"]),
+            kv.get_item("list/**")?
+        );
+
+        kv.put_value("string", "value", options)?;
+        assert!(matches!(
+            kv.append_items("string", ["a"], false, options),
+            Err(Error::MismatchedTypes { .. })
+        ));
+    }
+
+    #[test]
+    fn append_unique() {
+        let mut kv = Kv::new();
+        let options = PutOptions::default();
+
+        assert!(kv.append_item("list", "a", false, options).unwrap());
+        assert!(kv.append_item("list", "a", false, options).unwrap());
+        assert!(!kv.append_item("list", "a", true, options).unwrap());
+        assert!(kv.append_item("list", "b", true, options).unwrap());
+        expect_equal!(
+            Item::Array(item_array!["a", "a", "b"]),
+            kv.get_item("list/**").unwrap()
+        );
+
+        let pair = || Item::Array(item_array!["x", "y"]);
+        assert!(kv.append_item("nested", pair(), true, options).unwrap());
+        assert!(!kv.append_item("nested", pair(), true, options).unwrap());
+        assert!(kv.append_item("nested", pair(), false, options).unwrap());
+        expect_equal!(
+            Item::Array(vec![pair(), pair()]),
+            kv.get_item("nested/**").unwrap()
+        );
+    }
+
+    #[test]
+    fn compatible_reordered_types() {
+        use TypeDescription::*;
+
+        assert!(String.compatible_with(&String));
+        assert!(Value.compatible_with(&Bool));
+        assert!(Map(vec![String, Bool]).compatible_with(&Map(vec![Bool, String])));
+        assert!(
+            Array(vec![UnsignedInteger, String, Bool]).compatible_with(&Array(vec![
+                Bool,
+                UnsignedInteger,
+                String
+            ]))
+        );
+        assert!(Map(vec![Value, Bool]).compatible_with(&Map(vec![Bool, String])));
+        assert!(
+            Map(vec![Map(vec![String, Bool])]).compatible_with(&Map(vec![Map(vec![Bool, String])]))
+        );
+        assert!(Array(Vec::new()).compatible_with(&Array(vec![String])));
+    }
+
+    #[test]
+    fn mismatched_types() {
+        use TypeDescription::*;
+
+        assert!(!String.compatible_with(&Bool));
+        assert!(!UnsignedInteger.compatible_with(&SignedInteger));
+        assert!(!Value.compatible_with(&Array(vec![String])));
+        assert!(!Array(vec![String]).compatible_with(&Map(vec![String])));
+        assert!(!Map(vec![String, Bool]).compatible_with(&Map(vec![String, String])));
+        assert!(!Map(vec![String, Bool]).compatible_with(&Map(vec![Bool])));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn update_with_mismatched_type() {
+        let mut kv = Kv::new();
+        kv.put_value("key", "hello", PutOptions::default())?;
+
+        let res = kv.put_value(
+            "key",
+            true,
+            PutOptions {
+                update: true,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            res,
+            Err(Error::MismatchedTypes {
+                expected: TypeDescription::String,
+                actual: TypeDescription::Bool,
+            })
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn entry_or_insert_on_absent() {
+        let mut kv = Kv::new();
+
+        let value = kv
+            .entry("counter", false)
+            .and_modify(|value| *value = Value::from(100u64))?
+            .or_insert(1u64)?;
+        assert_eq!(value, Value::from(1u64));
+        expect_equal!(Item::from(1u64), kv.get_item("counter")?);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn entry_and_modify_on_present() {
+        let mut kv = Kv::new();
+        kv.put_value("counter", 1u64, PutOptions::default())?;
+
+        let value = kv
+            .entry("counter", false)
+            .and_modify(|value| {
+                if let Value::UnsignedInteger(n) = value {
+                    *n += 1;
+                }
+            })?
+            .or_insert(100u64)?;
+        assert_eq!(value, Value::from(2u64));
+        expect_equal!(Item::from(2u64), kv.get_item("counter")?);
+
+        let res = kv
+            .entry("counter", false)
+            .and_modify(|value| *value = Value::from("two"));
+        assert!(matches!(res, Err(Error::MismatchedTypes { .. })));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn contains_keys() {
+        let mut kv = Kv::new();
+        kv.put_value("nodes/betsy/ip", "10.0.0.1", PutOptions::default())?;
+        kv.put_value("nodes/betsy/port", 22, PutOptions::default())?;
+
+        assert!(kv.contains("nodes/betsy/ip"));
+        assert!(!kv.contains("nodes/betsy/user"));
+        assert!(!kv.contains("nodes/betsy"));
+
+        assert!(kv.contains("nodes/*/port"));
+        assert!(kv.contains("nodes/**"));
+        assert!(!kv.contains("nodes/*/user"));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn update_or_put() {
+        let mut kv = Kv::new();
+
+        let previous = kv.update_or_put_value("key", "hello", false)?;
+        assert_eq!(previous, None);
+        expect_equal!(Item::from("hello"), kv.get_item("key")?);
+
+        let previous = kv.update_or_put_value("key", "world", false)?;
+        assert_eq!(previous, Some(Some(Value::from("hello"))));
+        expect_equal!(Item::from("world"), kv.get_item("key")?);
+
+        let res = kv.update_or_put_value("key", 1u64, false);
+        assert!(matches!(
+            res,
+            Err(Error::MismatchedTypes {
+                expected: TypeDescription::String,
+                actual: TypeDescription::UnsignedInteger,
+            })
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn output_strings() {
+        assert_eq!(Item::from("v1.2.3").to_output_string()?, "v1.2.3");
+        assert_eq!(Item::from(42).to_output_string()?, "42");
+        assert_eq!(Item::from(true).to_output_string()?, "true");
+        assert_eq!(
+            Item::Array(item_array!["a", "b"]).to_output_string()?,
+            r#"["a","b"]"#
+        );
+    }
+
+    #[test]
+    fn pretty_nested_array() {
+        let item = Item::Array(item_array!["a", array=> item_array![1, true], "b"]);
+
+        assert_eq!(
+            item.pretty(2),
+            "[\n  \"a\",\n  [\n    1,\n    true\n  ],\n  \"b\"\n]"
+        );
+    }
+
+    #[test]
+    fn pretty_nested_map() {
+        let item = Item::Map(item_map! {
+            "name" => "betsy",
+            "network" map=> item_map! { "port" => 22 },
+            "roles" array=> item_array!["server"],
+        });
+
+        assert_eq!(
+            item.pretty(4),
+            "{\n    \"name\": \"betsy\",\n    \"network\": {\n        \"port\": 22\n    },\n    \
+                \"roles\": [\n        \"server\"\n    ]\n}"
+        );
+    }
+
+    #[test]
+    fn pretty_empty_array() {
+        assert_eq!(Item::Array(Vec::new()).pretty(2), "[]");
+        assert_eq!(
+            Item::Map(item_map! { "list" array=> item_array![] }).pretty(2),
+            "{\n  \"list\": []\n}"
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn deep_merge_maps() {
+        let item = Item::Map(item_map! {
+            "name" => "betsy",
+            "network" map=> item_map! { "address" => "10.0.0.1", "port" => 22 },
+        });
+        let other = Item::Map(item_map! {
+            "network" map=> item_map! { "port" => 2222 },
+            "role" => "server",
+        });
+
+        expect_equal!(
+            Item::Map(item_map! {
+                "name" => "betsy",
+                "network" map=> item_map! { "address" => "10.0.0.1", "port" => 2222 },
+                "role" => "server",
+            }),
+            item.deep_merge(other)?
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn deep_merge_arrays() {
+        let item = Item::Array(item_array!["a", "b"]);
+        let other = Item::Array(item_array!["b", "c"]);
+
+        expect_equal!(
+            Item::Array(item_array!["a", "b", "b", "c"]),
+            item.deep_merge(other)?
+        );
+    }
+
+    #[test]
+    fn deep_merge_mismatched_types() {
+        let item = Item::Map(item_map! { "network" => "10.0.0.1" });
+        let other = Item::Map(item_map! {
+            "network" map=> item_map! { "address" => "10.0.0.1" },
+        });
+
+        assert!(matches!(
+            item.deep_merge(other),
+            Err(Error::MismatchedTypes { .. })
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn env_lines_round_trip() {
+        let item = Item::Map(item_map! {
+            "NAME" => "it's a node",
+            "PORT" => 8080,
+            "ENABLED" => true,
+            "ADDRESSES" array=> item_array!["10.0.0.1", "10.0.0.2"],
+        });
+
+        let lines = item.to_env_lines()?;
+        assert_eq!(
+            lines,
+            "NAME='it'\\''s a node'\n\
+             PORT=8080\n\
+             ENABLED=true\n\
+             ADDRESSES_0='10.0.0.1'\n\
+             ADDRESSES_1='10.0.0.2'\n",
+        );
+        assert_eq!(Item::from_env_lines(&lines)?, item);
+    }
+
+    #[test]
+    fn env_lines_reject_nested_items() {
+        let item = Item::Map(item_map! {
+            "NESTED" map=> item_map! { "INNER" => "value" },
+        });
+        assert!(matches!(
+            item.to_env_lines(),
+            Err(Error::UnsupportedEnvItem(name)) if name == "NESTED",
+        ));
+
+        let item = Item::Map(item_map! { "invalid-name" => "value" });
+        assert!(item.to_env_lines().is_err());
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn snapshot_reads_are_frozen() {
+        let mut kv = Kv::new();
+        kv.put_value("nodes/a/address", "10.0.0.1", PutOptions::default())?;
+        kv.put_value("nodes/b/address", "10.0.0.2", PutOptions::default())?;
+
+        let snapshot = kv.snapshot();
+        kv.put_value("nodes/c/address", "10.0.0.3", PutOptions::default())?;
+        kv.drop_item("nodes/a/address", |_, _| ())?;
+
+        expect_equal!(
+            Item::from("10.0.0.1"),
+            snapshot.get_item("nodes/a/address")?
+        );
+        expect_equal!(
+            Item::Array(item_array!["10.0.0.1", "10.0.0.2"]),
+            Item::Array(
+                snapshot
+                    .get_item("nodes/*/address")?
+                    .into_values()
+                    .map(Item::Value)
+                    .collect()
+            )
+        );
+        assert!(matches!(
+            snapshot.get_item("nodes/c/address"),
+            Err(Error::KeyDoesNotExist(_))
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn snapshot_diff() {
+        let mut kv = Kv::new();
+        kv.put_value("unchanged", "same", PutOptions::default())?;
+        kv.put_value("modified", 1, PutOptions::default())?;
+        kv.put_value("removed", true, PutOptions::default())?;
+
+        let snapshot = kv.snapshot();
+
+        kv.put_value("added", "new", PutOptions::default())?;
+        kv.put_value(
+            "modified",
+            2,
+            PutOptions {
+                update: true,
+                ..Default::default()
+            },
+        )?;
+        kv.drop_item("removed", |_, _| ())?;
+
+        expect_equal!(
+            vec![
+                Change::Modified {
+                    key: "modified".into(),
+                    old: Value::from(1),
+                    new: Value::from(2),
+                },
+                Change::Removed {
+                    key: "removed".into(),
+                    value: Value::from(true),
+                },
+                Change::Added {
+                    key: "added".into(),
+                    value: Value::from("new"),
+                },
+            ],
+            snapshot.diff(&kv)
+        );
+    }
+
     #[test]
     #[throws(Error)]
     fn put_value() {
@@ -1902,4 +3590,148 @@ mod tests {
         // Map of values at multiple nested levels with multiple wildcards
         expect!("array/*/**" => m_array());
     }
+
+    #[test]
+    #[throws(Error)]
+    fn read_through_alias() {
+        let mut kv = Kv::new();
+        kv.put_value("nodes/foo/ip", "10.0.0.2", PutOptions::default())?;
+        kv.put_alias("current/ip", "nodes/foo/ip")?;
+
+        expect_equal!(Item::from("10.0.0.2"), kv.get_item("current/ip")?);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn write_through_alias() {
+        let mut kv = Kv::new();
+        kv.put_value("nodes/foo/ip", "10.0.0.2", PutOptions::default())?;
+        kv.put_alias("current/ip", "nodes/foo/ip")?;
+
+        kv.update_or_put_value("current/ip", "10.0.0.3", false)?;
+
+        expect_equal!(Item::from("10.0.0.3"), kv.get_item("nodes/foo/ip")?);
+        assert!(!kv.map.contains_key(Key::new("current/ip")));
+        assert_eq!(
+            serde_json::to_value(&kv)?["$aliases"]["current/ip"],
+            "nodes/foo/ip",
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn alias_cycle() {
+        let mut kv = Kv::new();
+        kv.put_alias("a", "b")?;
+        kv.put_alias("b", "c")?;
+
+        assert!(matches!(kv.put_alias("c", "a"), Err(Error::AliasCycle(_))));
+        assert!(matches!(kv.get_item("a"), Err(Error::KeyDoesNotExist(_))));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn restore_alias() {
+        let mut kv = Kv::new();
+        kv.put_value("nodes/foo/ip", "10.0.0.2", PutOptions::default())?;
+        kv.put_value("nodes/bar/ip", "10.0.0.3", PutOptions::default())?;
+
+        assert_eq!(kv.put_alias("current/ip", "nodes/foo/ip")?, None);
+        let previous = kv.put_alias("current/ip", "nodes/bar/ip")?;
+        assert_eq!(previous, Some(KeyOwned::from("nodes/foo/ip")));
+
+        kv.restore_alias(KeyOwned::from("current/ip"), previous);
+        expect_equal!(Item::from("10.0.0.2"), kv.get_item("current/ip")?);
+
+        kv.restore_alias(KeyOwned::from("current/ip"), None);
+        assert!(matches!(
+            kv.get_item("current/ip"),
+            Err(Error::KeyDoesNotExist(_))
+        ));
+        assert!(!kv.contains("current/*"));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn typed_array_reports_index() {
+        let mut kv = Kv::new();
+        kv.put_value("array/0", "a", PutOptions::default())?;
+        kv.put_value("array/1", "b", PutOptions::default())?;
+
+        let array: Vec<String> = kv.get_typed_array("array")?;
+        assert_eq!(array, ["a", "b"]);
+
+        kv.put_value("array/2", 3, PutOptions::default())?;
+        let res = kv.get_typed_array::<String, _>("array");
+        assert!(matches!(
+            &res,
+            Err(Error::ArrayElement { key, index: 2, .. }) if key.as_str() == "array",
+        ));
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .starts_with("element 2 of key \"array\":"));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn snapshot_diff_of_arrays() {
+        let mut kv = Kv::new();
+        kv.put_array("nodes", ["a", "b", "c"], PutOptions::default())?;
+
+        let snapshot = kv.snapshot();
+
+        kv.update_or_put_value("nodes/1", "d", false)?;
+        kv.drop_item("nodes/2", |_, _| ())?;
+        kv.put_value("nodes/2", "e", PutOptions::default())?;
+        kv.put_value("nodes/3", "f", PutOptions::default())?;
+
+        let changes: Vec<_> = snapshot.diff(&kv).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            changes,
+            [
+                r#"~ "nodes/1": "b" => "d""#,
+                r#"~ "nodes/2": "c" => "e""#,
+                r#"+ "nodes/3": "f""#,
+            ],
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn watch_matching_keys() {
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut kv = Kv::new();
+        kv.watch("nodes/*/ip", {
+            let changes = Arc::clone(&changes);
+            move |key, value| {
+                changes
+                    .lock()
+                    .unwrap()
+                    .push((key.as_str().to_owned(), value.clone()))
+            }
+        });
+
+        kv.put_value("nodes/foo/ip", "10.0.0.2", PutOptions::default())?;
+        kv.put_value("nodes/foo/name", "foo", PutOptions::default())?;
+        kv.put_value("nodes/bar/ip", "10.0.0.3", PutOptions::default())?;
+        kv.update_or_put_value("nodes/foo/ip", "10.0.0.4", false)?;
+        kv.put_value("nodes/bar/ip", "10.0.0.3", PutOptions::default())?;
+
+        // Nothing is sent while the store is borrowed.
+        assert!(changes.lock().unwrap().is_empty());
+        for notification in kv.take_notifications() {
+            notification.send();
+        }
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [
+                ("nodes/foo/ip".to_owned(), Value::from("10.0.0.2")),
+                ("nodes/bar/ip".to_owned(), Value::from("10.0.0.3")),
+                ("nodes/foo/ip".to_owned(), Value::from("10.0.0.4")),
+            ],
+        );
+    }
 }
@@ -0,0 +1,53 @@
+use std::{fs::File, io, os::unix::io::AsRawFd, path::Path};
+
+use crate::{context::Error, prelude::*};
+
+/// An advisory lock on a file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Acquires an exclusive lock on the file at `path`, creating it if necessary. Fails
+    /// immediately if the lock is already held by someone else.
+    #[throws(Error)]
+    pub fn acquire(path: &Path) -> Self {
+        let file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        // SAFETY: The file descriptor is valid for as long as `file` is alive.
+        let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if res != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                throw!(Error::Locked);
+            }
+            throw!(err);
+        }
+
+        Self { _file: file }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[throws(Error)]
+    fn second_lock_fails_while_held() {
+        let lock_dir = util::TempDir::new()?;
+        let path = lock_dir.path().join("context.lock");
+
+        let lock = FileLock::acquire(&path)?;
+        assert!(matches!(FileLock::acquire(&path), Err(Error::Locked)));
+
+        drop(lock);
+        assert!(FileLock::acquire(&path).is_ok());
+    }
+}
@@ -5,8 +5,13 @@ use std::{
     fs::File,
     io,
     marker::PhantomData,
+    ops::{Deref, DerefMut},
     os::unix::fs::PermissionsExt,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
 };
 
 use once_cell::sync::OnceCell;
@@ -15,14 +20,18 @@ use thiserror::Error;
 
 use self::{
     fs::{cache::Cache, files::Files, temp::Temp},
+    history::History,
     key::{Key, KeyOwned},
     kv::{Item, Kv, PutOptions, Value},
+    lock::FileLock,
 };
 use crate::{ledger::Ledger, prelude::*, prompt};
 
 pub mod fs;
+pub mod history;
 pub mod key;
 pub mod kv;
+mod lock;
 mod util;
 
 #[throws(D::Error)]
@@ -42,8 +51,14 @@ pub struct Context {
     files: RwLock<Files>,
     #[serde(deserialize_with = "deserialize_rw_lock")]
     cache: RwLock<Cache>,
+    #[serde(default, deserialize_with = "deserialize_rw_lock")]
+    history: RwLock<History>,
     #[serde(skip)]
     temp: RwLock<Temp>,
+    #[serde(skip)]
+    lock: Mutex<Option<FileLock>>,
+    #[serde(skip)]
+    read_only: AtomicBool,
 }
 
 impl Context {
@@ -58,10 +73,34 @@ impl Context {
             kv: RwLock::new(Kv::new()),
             files: RwLock::new(Files::new()),
             cache: RwLock::new(Cache::new()),
+            history: RwLock::new(History::new()),
             temp: RwLock::new(Temp::new()),
+            lock: Mutex::new(None),
+            read_only: AtomicBool::new(false),
         }
     }
 
+    /// Loads the context from the given base directory, or from the default locations if none is
+    /// given.
+    ///
+    /// A read-only context is neither locked nor persisted, so it can be used alongside a running
+    /// command.
+    #[throws(anyhow::Error)]
+    pub fn init(context_dir: Option<PathBuf>, read_only: bool) {
+        crate::set_context_dir(context_dir)?;
+        let context = Self::get_or_init();
+        context.set_read_only(read_only);
+        context.load()?;
+    }
+
+    fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::SeqCst);
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
     #[throws(anyhow::Error)]
     pub fn load(&self) {
         debug!("Loading context");
@@ -105,6 +144,18 @@ impl Context {
         permissions.set_mode(0o700);
         std::fs::set_permissions(&source_dir, permissions)?;
 
+        let mut lock = self.lock.lock().expect(EXPECT_THREAD_NOT_POSIONED);
+        if lock.is_none() && !self.is_read_only() {
+            debug!("Locking context");
+            if let Some(context_dir) = context_path.parent() {
+                std::fs::create_dir_all(context_dir)?;
+            }
+            lock.replace(FileLock::acquire(
+                &context_path.with_file_name("context.lock"),
+            )?);
+        }
+        drop(lock);
+
         debug!("Opening context file");
         match File::options().read(true).write(true).open(&context_path) {
             Ok(file) => {
@@ -121,6 +172,10 @@ impl Context {
                     .cache
                     .into_inner()
                     .expect(EXPECT_THREAD_NOT_POSIONED);
+                *self.history_mut() = context
+                    .history
+                    .into_inner()
+                    .expect(EXPECT_THREAD_NOT_POSIONED);
             }
             Err(error) if error.kind() == io::ErrorKind::NotFound => {
                 debug!("No context file found");
@@ -133,8 +188,8 @@ impl Context {
         self.kv.read().expect(EXPECT_THREAD_NOT_POSIONED)
     }
 
-    pub fn kv_mut(&self) -> RwLockWriteGuard<Kv> {
-        self.kv.write().expect(EXPECT_THREAD_NOT_POSIONED)
+    pub fn kv_mut(&self) -> KvWriteGuard {
+        KvWriteGuard(Some(self.kv.write().expect(EXPECT_THREAD_NOT_POSIONED)))
     }
 
     pub fn files(&self) -> RwLockReadGuard<Files> {
@@ -153,32 +208,85 @@ impl Context {
         self.cache.write().expect(EXPECT_THREAD_NOT_POSIONED)
     }
 
+    pub fn history(&self) -> RwLockReadGuard<History> {
+        self.history.read().expect(EXPECT_THREAD_NOT_POSIONED)
+    }
+
+    pub fn history_mut(&self) -> RwLockWriteGuard<History> {
+        self.history.write().expect(EXPECT_THREAD_NOT_POSIONED)
+    }
+
     pub fn temp(&self) -> RwLockReadGuard<Temp> {
         self.temp.read().expect(EXPECT_THREAD_NOT_POSIONED)
     }
 
     #[throws(anyhow::Error)]
     pub fn persist(&self) {
+        if self.is_read_only() {
+            debug!("Not persisting read-only context");
+            return;
+        }
+
         progress!("Persisting context");
 
         debug!("Dropping temporary values");
         self.kv_mut().drop_temporary_values();
 
+        // Written to the side and moved in place, so readers never see a partial file.
+        let context_path = crate::local_context_file_path();
+        let staged_path = context_path.with_extension("yaml.tmp");
+
         debug!("Opening context file for writing");
         let file = File::options()
             .write(true)
             .truncate(true)
             .create(true)
-            .open(crate::local_context_file_path())?;
+            .open(&staged_path)?;
 
         debug!("Serializing context to file");
         serde_yaml::to_writer(file, self)?;
+        std::fs::rename(&staged_path, &context_path)?;
     }
 
     #[throws(anyhow::Error)]
     pub fn cleanup(&self) {
         debug!("Clean temporary files");
         self.temp().cleanup()?;
+
+        debug!("Unlocking context");
+        self.lock.lock().expect(EXPECT_THREAD_NOT_POSIONED).take();
+    }
+}
+
+/// Write access to the key-value store. Watchers of changed keys are called once the store is
+/// unlocked, so they can use it themselves.
+pub struct KvWriteGuard<'a>(Option<RwLockWriteGuard<'a, Kv>>);
+
+impl Deref for KvWriteGuard<'_> {
+    type Target = Kv;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("guard should be held until dropped")
+    }
+}
+
+impl DerefMut for KvWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("guard should be held until dropped")
+    }
+}
+
+impl Drop for KvWriteGuard<'_> {
+    fn drop(&mut self) {
+        let Some(mut kv) = self.0.take() else {
+            return;
+        };
+        let notifications = kv.take_notifications();
+        drop(kv);
+
+        for notification in notifications {
+            notification.send();
+        }
     }
 }
 
@@ -187,10 +295,11 @@ impl Serialize for Context {
     where
         S: serde::Serializer,
     {
-        let mut context = serializer.serialize_map(Some(3))?;
+        let mut context = serializer.serialize_map(Some(4))?;
         context.serialize_entry("kv", &*self.kv())?;
         context.serialize_entry("files", &*self.files())?;
         context.serialize_entry("cache", &*self.cache())?;
+        context.serialize_entry("history", &*self.history())?;
         context.end()
     }
 }
@@ -231,6 +340,24 @@ impl<'a> KvBuilder<'a, All> {
         Context::get_or_init().kv().item_exists(&self.key)
     }
 
+    /// Makes the key an alias of `target`.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn alias<K>(self, target: &K)
+    where
+        K: AsRef<Key> + ?Sized,
+    {
+        let previous = Context::get_or_init()
+            .kv_mut()
+            .put_alias(&*self.key, target)?;
+
+        Ledger::get_or_init().add(ledger::Alias::new(
+            self.key.into_owned(),
+            target.as_ref().to_owned(),
+            previous,
+        ));
+    }
+
     #[throws(Error)]
     pub fn update<V>(self, value: V)
     where
@@ -272,6 +399,13 @@ impl<'a, O> KvBuilder<'a, O> {
             },
         )?;
 
+        self.record_put(value, previous_value);
+    }
+
+    fn record_put<V>(self, value: V, previous_value: Option<Option<Value>>)
+    where
+        V: Into<Value> + Clone + Display + Send + 'static,
+    {
         if !self.temporary && previous_value != Some(None) {
             Ledger::get_or_init().add(ledger::Put::new(
                 self.key.into_owned(),
@@ -288,6 +422,26 @@ impl<'a, O> KvBuilder<'a, O> {
     {
         self.put_or_update(value, false)?;
     }
+
+    /// Updates the value if the key exists, or puts it otherwise.
+    ///
+    /// Returns the previous value, if it was replaced.
+    #[allow(unused)]
+    #[throws(Error)]
+    pub fn update_or_put<V>(self, value: V) -> Option<Value>
+    where
+        V: Into<Value> + Clone + Display + Send + 'static,
+    {
+        let previous_value = Context::get_or_init().kv_mut().update_or_put_value(
+            &self.key,
+            value.clone(),
+            self.temporary,
+        )?;
+
+        let replaced_value = previous_value.clone().flatten();
+        self.record_put(value, previous_value);
+        replaced_value
+    }
 }
 
 #[derive(Debug, Error)]
@@ -298,15 +452,56 @@ pub enum Error {
     #[error("Key does not exist: {0:?}")]
     KeyDoesNotExist(KeyOwned),
 
+    #[error("Invalid key component {0:?}: {1}")]
+    InvalidKeyComponent(String, &'static str),
+
+    #[error("Alias cycle detected at key {0:?}")]
+    AliasCycle(KeyOwned),
+
     #[error("Mismatched value types: expected {expected} ≠ actual {actual}")]
     MismatchedTypes {
         expected: kv::TypeDescription,
         actual: kv::TypeDescription,
     },
 
+    #[error("Invalid flag {1:?} for key {0:?}: expected e.g. `yes` or `no`")]
+    InvalidBoolFlag(KeyOwned, String),
+
+    #[error("Key {0:?} holds a value rather than a file")]
+    NotAFile(KeyOwned),
+
+    #[error("Cannot set {0:?}, since {1:?} is not a map")]
+    NotAMap(KeyOwned, String),
+
+    #[error("Null values are not supported")]
+    UnsupportedNull,
+
     #[error("{0} out of range for `{1}`")]
     OverflowingNumber(i128, &'static str),
 
+    #[error("element {index} of key {key:?}: {source}")]
+    ArrayElement {
+        key: KeyOwned,
+        index: usize,
+        #[source]
+        source: Box<Error>,
+    },
+
+    #[error("Item {0:?} cannot be formatted as an environment variable")]
+    UnsupportedEnvItem(String),
+
+    #[error("Invalid environment line {0:?}: {1}")]
+    InvalidEnvLine(String, &'static str),
+
+    #[error("Invalid path {0:?}: {1}")]
+    InvalidPath(String, &'static str),
+
+    #[error("File for key {key:?} already exists at path {path:?}")]
+    FileAlreadyExists { key: KeyOwned, path: PathBuf },
+
+    #[error("Context is locked: another hoc process is running")]
+    Locked,
+
     #[error(transparent)]
     Io(#[from] io::Error),
 
@@ -322,7 +517,7 @@ pub enum Error {
 
 impl From<Infallible> for Error {
     fn from(x: Infallible) -> Self {
-        x.into()
+        match x {}
     }
 }
 
@@ -397,6 +592,47 @@ pub mod ledger {
         }
     }
 
+    pub struct Alias {
+        key: KeyOwned,
+        target: KeyOwned,
+        previous_target: Option<KeyOwned>,
+    }
+
+    impl Alias {
+        pub fn new(key: KeyOwned, target: KeyOwned, previous_target: Option<KeyOwned>) -> Self {
+            Self {
+                key,
+                target,
+                previous_target,
+            }
+        }
+    }
+
+    impl Transaction for Alias {
+        fn description(&self) -> Cow<'static, str> {
+            "Put alias".into()
+        }
+
+        fn detail(&self) -> Cow<'static, str> {
+            let mut detail = "Key: ".to_owned();
+            detail += self.key.as_str();
+            detail += "\nTarget: ";
+            detail += self.target.as_str();
+            if let Some(previous_target) = &self.previous_target {
+                detail += "\nPrevious Target: ";
+                detail += previous_target.as_str();
+            }
+            detail.into()
+        }
+
+        #[throws(anyhow::Error)]
+        fn revert(self: Box<Self>) {
+            Context::get_or_init()
+                .kv_mut()
+                .restore_alias(self.key, self.previous_target);
+        }
+    }
+
     pub struct Drop {
         template: KeyOwned,
         item: Item,
@@ -438,3 +674,45 @@ pub mod ledger {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn custom_context_dir() {
+        let test_dir = util::TempDir::new()?;
+        let context_dir = test_dir.path();
+
+        crate::set_context_dir(Some(context_dir.to_owned()))?;
+        assert!(crate::set_context_dir(None).is_err());
+        let context = Context::new();
+        context.load()?;
+        context.persist()?;
+
+        // Readers can load the context while it is locked, and leave the file alone.
+        let reader = Context::new();
+        reader.set_read_only(true);
+        let reader_loaded = reader.load().is_ok();
+        let modified = context_dir.join("context.yaml").metadata()?.modified()?;
+        reader.persist()?;
+        let reader_modified = context_dir.join("context.yaml").metadata()?.modified()?;
+
+        assert!(context_dir.join("context.yaml").try_exists()?);
+        assert!(reader_loaded);
+        assert_eq!(modified, reader_modified);
+    }
+
+    #[test]
+    fn infallible_propagates_into_error() {
+        #[throws(Error)]
+        fn parse() -> String {
+            let s: Result<String, Infallible> = "value".parse();
+            s?
+        }
+
+        assert_eq!(parse().unwrap(), "value");
+    }
+}
@@ -39,6 +39,15 @@ impl Ledger {
         self.transactions.push(Box::new(transaction));
     }
 
+    /// The details of the recorded transactions, oldest first.
+    #[cfg(test)]
+    pub fn details(&self) -> Vec<Cow<'static, str>> {
+        self.transactions
+            .iter()
+            .map(|transaction| transaction.detail())
+            .collect()
+    }
+
     #[throws(anyhow::Error)]
     pub fn rollback(&mut self) {
         if self.transactions.is_empty() {
@@ -0,0 +1,135 @@
+use std::{
+    env,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use log_facade::Level;
+use once_cell::sync::OnceCell;
+
+use crate::prelude::*;
+
+static WRITER: OnceCell<Option<Mutex<Writer>>> = OnceCell::new();
+
+/// The CI service to format the output for, instead of rendering the progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CiProvider {
+    /// GitHub Actions, with collapsible groups and annotations
+    Github,
+}
+
+/// Sets the CI provider, which is GitHub Actions if not set and `GITHUB_ACTIONS` is `true`.
+pub fn set_ci_provider(provider: Option<CiProvider>) {
+    let provider = provider.or_else(|| {
+        (env::var("GITHUB_ACTIONS").as_deref() == Ok("true")).then_some(CiProvider::Github)
+    });
+    WRITER.get_or_init(|| provider.map(|provider| Mutex::new(Writer::new(provider))));
+}
+
+pub fn is_enabled() -> bool {
+    WRITER.get().map_or(false, Option::is_some)
+}
+
+pub fn start_group(message: &str) {
+    with_writer(|writer, out| writer.start_group(out, message));
+}
+
+pub fn end_group(message: &str, failed: bool) {
+    with_writer(|writer, out| writer.end_group(out, message, failed));
+}
+
+pub fn log(level: Level, message: &str) {
+    with_writer(|writer, out| writer.log(out, level, message));
+}
+
+fn with_writer(f: impl FnOnce(&mut Writer, &mut io::Stdout) -> io::Result<()>) {
+    if let Some(Some(writer)) = WRITER.get() {
+        f(
+            &mut writer.lock().expect(EXPECT_THREAD_NOT_POSIONED),
+            &mut io::stdout(),
+        )
+        .ok();
+    }
+}
+
+/// Writes workflow commands. Groups cannot be nested, so only the outermost progresses are
+/// grouped, and nested ones are printed as plain lines.
+struct Writer {
+    provider: CiProvider,
+    depth: usize,
+}
+
+impl Writer {
+    fn new(provider: CiProvider) -> Self {
+        Self { provider, depth: 0 }
+    }
+
+    #[throws(io::Error)]
+    fn start_group(&mut self, out: &mut impl Write, message: &str) {
+        match self.provider {
+            CiProvider::Github if self.depth == 0 => writeln!(out, "::group::{}", escape(message))?,
+            CiProvider::Github => writeln!(out, "{message}")?,
+        }
+        self.depth += 1;
+    }
+
+    #[throws(io::Error)]
+    fn end_group(&mut self, out: &mut impl Write, message: &str, failed: bool) {
+        self.depth = self.depth.saturating_sub(1);
+        match self.provider {
+            CiProvider::Github => {
+                if self.depth == 0 {
+                    writeln!(out, "::endgroup::")?;
+                }
+                if failed {
+                    writeln!(out, "::error::{} failed", escape(message))?;
+                }
+            }
+        }
+    }
+
+    #[throws(io::Error)]
+    fn log(&mut self, out: &mut impl Write, level: Level, message: &str) {
+        match (self.provider, level) {
+            (CiProvider::Github, Level::Error) => writeln!(out, "::error::{}", escape(message))?,
+            (CiProvider::Github, Level::Warn) => writeln!(out, "::warning::{}", escape(message))?,
+            (CiProvider::Github, _) => writeln!(out, "{message}")?,
+        }
+    }
+}
+
+/// Escapes the data of a workflow command, so it stays on one line.
+fn escape(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[throws(io::Error)]
+    fn groups_wrap_outermost_progress() {
+        let mut writer = Writer::new(CiProvider::Github);
+        let mut out = Vec::new();
+
+        writer.start_group(&mut out, "Deploying application")?;
+        writer.log(&mut out, Level::Info, "Applying chart")?;
+        writer.start_group(&mut out, "Waiting on pods")?;
+        writer.log(&mut out, Level::Warn, "Pod not ready\nretrying")?;
+        writer.end_group(&mut out, "Waiting on pods", true)?;
+        writer.end_group(&mut out, "Deploying application", false)?;
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "::group::Deploying application\n\
+             Applying chart\n\
+             Waiting on pods\n\
+             ::warning::Pod not ready%0Aretrying\n\
+             ::error::Waiting on pods failed\n\
+             ::endgroup::\n",
+        );
+    }
+}
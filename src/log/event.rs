@@ -0,0 +1,179 @@
+use std::{
+    env,
+    io::{self, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    sync::Mutex,
+};
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::prelude::*;
+
+static SINK: OnceCell<Mutex<EventSink>> = OnceCell::new();
+
+/// Connects to the Unix socket in `HOC_EVENT_SOCKET`, if set.
+pub fn init() {
+    let Some(path) = env::var_os("HOC_EVENT_SOCKET") else {
+        return;
+    };
+
+    if let Err(err) = connect(&path) {
+        warn!("Failed to connect to event socket {path:?}: {err}");
+    }
+}
+
+/// Connects to the Unix socket at `path`, unless already connected.
+#[throws(io::Error)]
+pub(in crate::log) fn connect(path: impl AsRef<Path>) {
+    SINK.set(Mutex::new(EventSink::connect(path)?)).ok();
+}
+
+/// Sends an event to the event socket, if connected. Events are best-effort, so a disconnected
+/// listener does not interrupt the run.
+pub fn emit_event(event: Event) {
+    if let Some(sink) = SINK.get() {
+        sink.lock()
+            .expect(EXPECT_THREAD_NOT_POSIONED)
+            .send(&event)
+            .ok();
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    Log { level: &'a str, message: &'a str },
+    ProgressStart { message: &'a str },
+    ProgressEnd { message: &'a str, failed: bool },
+    WaitingForInput { message: &'a str },
+}
+
+struct EventSink {
+    stream: UnixStream,
+    /// The part of the last line which the listener has not yet accepted.
+    pending: Vec<u8>,
+}
+
+impl EventSink {
+    #[throws(io::Error)]
+    fn connect(path: impl AsRef<Path>) -> Self {
+        let stream = UnixStream::connect(path)?;
+        stream.set_nonblocking(true)?;
+        Self {
+            stream,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writes the event without blocking. If the listener is not keeping up, the event is dropped.
+    #[throws(io::Error)]
+    fn send(&mut self, event: &Event) {
+        // A partially written line is finished first, so that lines are never interleaved.
+        self.write_pending()?;
+        if !self.pending.is_empty() {
+            return;
+        }
+
+        self.pending = serde_json::to_vec(event)?;
+        self.pending.push(b'\n');
+        self.write_pending()?;
+    }
+
+    #[throws(io::Error)]
+    fn write_pending(&mut self) {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => throw!(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(written) => {
+                    self.pending.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return,
+                Err(err) => throw!(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::net::UnixListener,
+    };
+
+    use log_facade::Level;
+
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[throws(io::Error)]
+    fn events_arrive_as_json_lines() {
+        let socket_dir = util::TempDir::new()?;
+        let socket_path = socket_dir.path().join("events.sock");
+        let listener = UnixListener::bind(&socket_path)?;
+
+        let mut sink = EventSink::connect(&socket_path)?;
+        let (stream, _) = listener.accept()?;
+
+        sink.send(&Event::ProgressStart { message: "Deploy" })?;
+        sink.send(&Event::Log {
+            level: Level::Info.as_str(),
+            message: "Applying chart",
+        })?;
+        sink.send(&Event::WaitingForInput {
+            message: "Continue?",
+        })?;
+        sink.send(&Event::ProgressEnd {
+            message: "Deploy",
+            failed: false,
+        })?;
+        drop(sink);
+
+        let lines = BufReader::new(stream)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            lines,
+            [
+                r#"{"event":"progress_start","message":"Deploy"}"#,
+                r#"{"event":"log","level":"INFO","message":"Applying chart"}"#,
+                r#"{"event":"waiting_for_input","message":"Continue?"}"#,
+                r#"{"event":"progress_end","message":"Deploy","failed":false}"#,
+            ],
+        );
+    }
+
+    #[test]
+    #[throws(io::Error)]
+    fn stalled_listener_does_not_block() {
+        let socket_dir = util::TempDir::new()?;
+        let socket_path = socket_dir.path().join("events.sock");
+        let listener = UnixListener::bind(&socket_path)?;
+
+        let mut sink = EventSink::connect(&socket_path)?;
+        let (stream, _) = listener.accept()?;
+
+        // Far more than fits in the socket buffer, so a blocking write would never return.
+        for _ in 0..100_000 {
+            sink.send(&Event::Log {
+                level: Level::Info.as_str(),
+                message: "Applying chart",
+            })?;
+        }
+        drop(sink);
+
+        let lines = BufReader::new(stream)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(lines.len() < 100_000);
+
+        // The last line may have been cut short when the sink was dropped.
+        assert!(lines[..lines.len() - 1]
+            .iter()
+            .all(|line| line == r#"{"event":"log","level":"INFO","message":"Applying chart"}"#));
+    }
+}
@@ -4,7 +4,9 @@ use std::{
     fs::{self, File},
     io::Write,
     panic,
+    path::PathBuf,
     sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 
 use chrono::{DateTime, Utc};
@@ -22,15 +24,20 @@ const MAX_DEFAULT_LEVEL: Level = if cfg!(debug_assertions) {
     Level::Info
 };
 
+/// How long messages are held back before being written, so the log file of a running command can
+/// be followed.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 static START_TIME: OnceCell<DateTime<Utc>> = OnceCell::new();
 
 pub struct Logger {
     level: Level,
+    quiet: bool,
 }
 
 impl Logger {
     #[throws(Error)]
-    pub(super) fn init() {
+    pub(super) fn init(quiet: bool, verbosity: u8) {
         let level_str = env::var("HOC_LOG")
             .map(|v| Cow::Owned(v.to_uppercase()))
             .unwrap_or(Cow::Borrowed(MAX_DEFAULT_LEVEL.as_str()));
@@ -43,9 +50,13 @@ impl Logger {
             _ => throw!(Error::UnknownLevel(level_str.into_owned())),
         };
 
+        let level = verbosity_level(verbosity).unwrap_or(level);
+
+        let level = if quiet { level.min(Level::Warn) } else { level };
+
         START_TIME.get_or_init(Utc::now);
 
-        let logger = Self { level };
+        let logger = Self { level, quiet };
 
         log_facade::set_boxed_logger(Box::new(logger))?;
         log_facade::set_max_level(LevelFilter::Trace);
@@ -65,7 +76,15 @@ impl Log for Logger {
         let args_str = record.args().to_string();
 
         if self.enabled(record.metadata()) {
-            Progress::get_or_init().push_simple_log(record.level(), args_str.clone());
+            if log::ci::is_enabled() {
+                log::ci::log(record.level(), &args_str);
+            } else if self.quiet || log::is_rendering_stopped() {
+                if let Some(line) = quiet_line(record.level(), &args_str) {
+                    eprintln!("{line}");
+                }
+            } else {
+                Progress::get_or_init().push_simple_log(record.level(), args_str.clone());
+            }
         }
 
         LoggerBuffer::get_or_init()
@@ -87,9 +106,40 @@ impl Log for Logger {
     }
 }
 
+/// Maps the number of `-v` flags to the level, overriding the default one.
+fn verbosity_level(verbosity: u8) -> Option<Level> {
+    match verbosity {
+        0 => None,
+        1 => Some(Level::Debug),
+        _ => Some(Level::Trace),
+    }
+}
+
+/// Formats a plain line without any styling, if the level is a warning or an error.
+fn quiet_line(level: Level, message: &str) -> Option<String> {
+    (level <= Level::Warn).then(|| format!("{level}: {message}"))
+}
+
+/// The directory containing the log files of all runs.
+#[throws(anyhow::Error)]
+pub fn logs_dir() -> PathBuf {
+    let home_dir = env::var("HOME").context("HOME environment variable should exist")?;
+    PathBuf::from(home_dir).join(".local/share/hoc/logs")
+}
+
+/// The log file of the current run.
+#[throws(anyhow::Error)]
+pub fn log_file() -> PathBuf {
+    let start_time = START_TIME.get().expect("start time should be initialized");
+    logs_dir()?
+        .join(start_time.format("%Y/%m/%d").to_string())
+        .join(format!("{}.txt", start_time.format("%T.%6f")))
+}
+
 pub(in crate::log) struct LoggerBuffer {
     messages: Vec<(LoggerMeta, String)>,
     longest_mod_name: usize,
+    last_flush: Option<Instant>,
 }
 
 impl LoggerBuffer {
@@ -97,6 +147,7 @@ impl LoggerBuffer {
         Self {
             messages: Vec::new(),
             longest_mod_name: 0,
+            last_flush: None,
         }
     }
 
@@ -113,24 +164,29 @@ impl LoggerBuffer {
     pub fn push(&mut self, meta: LoggerMeta, args: String) {
         self.messages.push((meta, args));
 
-        if self.messages.len() >= 100 {
+        let is_flush_due = self
+            .last_flush
+            .map_or(true, |last_flush| last_flush.elapsed() >= FLUSH_INTERVAL);
+        if self.messages.len() >= 100 || is_flush_due {
             self.flush()?;
         }
     }
 
     #[throws(anyhow::Error)]
     pub fn flush(&mut self) {
-        let start_time = START_TIME.get().expect("start time should be initialized");
-        let home_dir = env::var("HOME").context("HOME environment variable should exist")?;
-        let log_dir = format!(
-            "{home_dir}/.local/share/hoc/logs/{}",
-            start_time.format("%Y/%m/%d"),
-        );
-        fs::create_dir_all(&log_dir).context("directories should be able to be created")?;
+        // Without a logger, there is no log file to write to, e.g. in tests.
+        if START_TIME.get().is_none() {
+            return;
+        }
+
+        let log_file = log_file()?;
+        if let Some(log_dir) = log_file.parent() {
+            fs::create_dir_all(log_dir).context("directories should be able to be created")?;
+        }
         let mut file = File::options()
             .create(true)
             .append(true)
-            .open(format!("{log_dir}/{}.txt", start_time.format("%T.%6f")))
+            .open(log_file)
             .context("file should be unique")?;
 
         let mut longest_mod_name = self.longest_mod_name.max(
@@ -176,6 +232,7 @@ impl LoggerBuffer {
         }
 
         self.longest_mod_name = longest_mod_name;
+        self.last_flush.replace(Instant::now());
     }
 }
 
@@ -184,3 +241,34 @@ pub(in crate::log) struct LoggerMeta {
     pub level: Level,
     pub module: Option<Cow<'static, str>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_lines() {
+        assert_eq!(
+            quiet_line(Level::Error, "failed"),
+            Some("ERROR: failed".to_owned())
+        );
+        assert_eq!(
+            quiet_line(Level::Warn, "careful"),
+            Some("WARN: careful".to_owned())
+        );
+        assert_eq!(quiet_line(Level::Info, "info"), None);
+        assert_eq!(quiet_line(Level::Debug, "debug"), None);
+        assert_eq!(quiet_line(Level::Trace, "trace"), None);
+
+        let line = quiet_line(Level::Warn, "careful").unwrap();
+        assert!(!line.contains('\x1b'));
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        assert_eq!(verbosity_level(0), None);
+        assert_eq!(verbosity_level(1), Some(Level::Debug));
+        assert_eq!(verbosity_level(2), Some(Level::Trace));
+        assert_eq!(verbosity_level(3), Some(Level::Trace));
+    }
+}
@@ -1,19 +1,34 @@
-use std::fmt;
+use std::{
+    env, fmt, io, iter,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-pub use logger::Logger;
-pub use progress::{pause_rendering, ProgressHandle};
+pub use ci::{set_ci_provider, CiProvider};
+pub use event::{emit_event, Event};
+pub use logger::{log_file, logs_dir, Logger};
+pub use progress::{
+    pause_rendering, set_collapse_finished, set_indent, set_log_buffer, ProgressHandle,
+};
 
 use chrono::Utc;
-use crossterm::style::{Color, SetForegroundColor};
+use crossterm::{
+    style::{Color, SetForegroundColor},
+    tty::IsTty,
+};
 use log_facade::log_enabled;
+use once_cell::sync::OnceCell;
 use thiserror::Error;
 
 use crate::prelude::*;
 
 use self::logger::{LoggerBuffer, LoggerMeta};
 
+mod ci;
+mod event;
 mod logger;
+mod profile;
 mod progress;
+pub mod suspend;
 
 pub const CLEAR_COLOR: SetForegroundColor = SetForegroundColor(Color::Reset);
 pub const ERROR_COLOR: SetForegroundColor = SetForegroundColor(Color::Red);
@@ -22,19 +37,89 @@ pub const INFO_COLOR: SetForegroundColor = SetForegroundColor(Color::White);
 pub const DEBUG_COLOR: SetForegroundColor = SetForegroundColor(Color::DarkMagenta);
 pub const TRACE_COLOR: SetForegroundColor = SetForegroundColor(Color::DarkGrey);
 
+static QUIET: OnceCell<bool> = OnceCell::new();
+static USE_COLOR: OnceCell<bool> = OnceCell::new();
+static RENDERING_STOPPED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Use colors when printing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    /// Always use colors
+    Always,
+    /// Never use colors
+    Never,
+}
+
+impl ColorChoice {
+    fn use_color(self, is_tty: bool, no_color: bool) -> bool {
+        match self {
+            Self::Auto => is_tty && !no_color,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
 #[throws(Error)]
-pub fn init() {
-    Logger::init()?;
-    progress::init();
+pub fn init(quiet: bool, verbosity: u8, color: ColorChoice) {
+    QUIET.get_or_init(|| quiet);
+    USE_COLOR
+        .get_or_init(|| color.use_color(io::stdout().is_tty(), env::var_os("NO_COLOR").is_some()));
+
+    Logger::init(quiet, verbosity)?;
+    event::init();
+    if !quiet && !ci::is_enabled() {
+        progress::init();
+        suspend::listen();
+    }
+}
+
+/// Whether only warnings and errors are printed, without any progress rendering.
+pub fn is_quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
 }
 
+/// Whether the progress rendering should be colored.
+pub fn use_color() -> bool {
+    USE_COLOR.get().copied().unwrap_or(true)
+}
+
+/// Stops the progress rendering and restores the terminal. Later log messages are printed plainly.
 #[throws(Error)]
-pub fn cleanup() {
+pub fn stop_rendering() {
+    RENDERING_STOPPED.store(true, Ordering::SeqCst);
+    suspend::cleanup();
     progress::cleanup()?;
+}
+
+fn is_rendering_stopped() -> bool {
+    RENDERING_STOPPED.load(Ordering::SeqCst)
+}
+
+#[throws(Error)]
+pub fn cleanup() {
+    stop_rendering()?;
     Logger::cleanup();
 }
 
 pub fn progress(message: String, level: Option<Level>, module: &'static str) -> ProgressHandle {
+    start_progress(message, level, module, false)
+}
+
+/// Starts a single line progress, which shows neither nested logs nor the elapsed time.
+#[allow(unused)]
+pub fn spinner(message: String, level: Option<Level>, module: &'static str) -> ProgressHandle {
+    start_progress(message, level, module, true)
+}
+
+fn start_progress(
+    message: String,
+    level: Option<Level>,
+    module: &'static str,
+    is_spinner: bool,
+) -> ProgressHandle {
     LoggerBuffer::get_or_init()
         .push(
             LoggerMeta {
@@ -45,13 +130,164 @@ pub fn progress(message: String, level: Option<Level>, module: &'static str) ->
             format!("[PROGRESS START] {message}"),
         )
         .unwrap_or_else(|e| panic!("{e}"));
-    if level.is_none() || level.filter(|l| log_enabled!(*l)).is_some() {
-        progress::Progress::get_or_init().push_progress_log(message, level, module)
+    if is_quiet() {
+        ProgressHandle::new_for_buffer(message, level, module)
+    } else if level.is_none() || level.filter(|l| log_enabled!(*l)).is_some() {
+        if ci::is_enabled() {
+            ci::start_group(&message);
+            return ProgressHandle::new_for_ci(message, level, module);
+        }
+
+        let progress = progress::Progress::get_or_init();
+        if is_spinner {
+            progress.push_spinner_log(message, level, module)
+        } else {
+            progress.push_progress_log(message, level, module)
+        }
     } else {
         ProgressHandle::new_for_buffer(message, level, module)
     }
 }
 
+/// Starts a determinate progress, which is advanced with [`ProgressHandle::inc`].
+#[allow(unused)]
+pub fn progress_with_total(
+    message: String,
+    total: u64,
+    level: Option<Level>,
+    module: &'static str,
+) -> ProgressHandle {
+    let mut handle = progress(message, level, module);
+    handle.set_progress(0, total);
+    handle
+}
+
+/// Starts a progress for a numbered step, prefixed with the step fraction, e.g. `[2/5] Message`.
+#[allow(unused)]
+pub fn step(
+    current: usize,
+    total: usize,
+    message: String,
+    level: Option<Level>,
+    module: &'static str,
+) -> ProgressHandle {
+    progress(format_step(current, total, &message), level, module)
+}
+
+fn format_step(current: usize, total: usize, message: &str) -> String {
+    format!("[{current}/{total}] {message}")
+}
+
+/// Formats a byte count with its digits grouped in thousands, e.g. `1,234,567 bytes`.
+pub fn format_bytes_grouped(bytes: u64) -> String {
+    let digits = bytes.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    let unit = if bytes == 1 { "byte" } else { "bytes" };
+    format!("{grouped} {unit}")
+}
+
+/// Runs the closure within a progress, which is finished when the closure returns.
+#[allow(unused)]
+pub fn with_progress<T>(
+    message: String,
+    level: Option<Level>,
+    module: &'static str,
+    f: impl FnOnce() -> T,
+) -> T {
+    let _handle = progress(message, level, module);
+    f()
+}
+
+/// The number of running progresses the current log messages are nested within.
+#[allow(unused)]
+pub fn current_depth() -> usize {
+    progress::Progress::get_or_init().current_depth()
+}
+
+/// Records the run time of every progress, to be printed with [`print_profile`].
+pub fn enable_profile() {
+    profile::enable();
+}
+
+/// Prints the recorded run times, slowest first, if profiling is enabled.
+pub fn print_profile() {
+    let Some(rows) = profile::get().map(|profile| profile.report()) else {
+        return;
+    };
+    table(&["PROGRESS", "TIME"], &rows);
+}
+
+/// Logs an aligned table, where the header row is dimmed. Rows not fitting the terminal are
+/// truncated.
+#[allow(unused)]
+pub fn table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut lines = format_table(headers, rows);
+    let header = lines.remove(0);
+
+    let mut logger_buffer = LoggerBuffer::get_or_init();
+    for line in iter::once(&header).chain(&lines) {
+        logger_buffer
+            .push(
+                LoggerMeta {
+                    timestamp: Utc::now(),
+                    level: Level::Info,
+                    module: None,
+                },
+                line.clone(),
+            )
+            .unwrap_or_else(|e| panic!("{e}"));
+    }
+    drop(logger_buffer);
+
+    if !is_quiet() && log_enabled!(Level::Info) {
+        if ci::is_enabled() {
+            for line in iter::once(&header).chain(&lines) {
+                ci::log(Level::Info, line);
+            }
+        } else {
+            progress::Progress::get_or_init().push_table_log(header, lines);
+        }
+    }
+}
+
+/// Formats the rows as aligned lines, with the headers on the first line.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let num_columns = headers
+        .len()
+        .max(rows.iter().map(Vec::len).max().unwrap_or(0));
+
+    let mut widths = vec![0; num_columns];
+    let header_cells = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in iter::once(&header_cells).chain(rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    iter::once(&header_cells)
+        .chain(rows)
+        .map(|row| {
+            let mut line = String::new();
+            for (i, width) in widths.iter().enumerate() {
+                let cell = row.get(i).map_or("", String::as_str);
+                if i + 1 == num_columns {
+                    line += cell;
+                } else {
+                    line += &format!("{cell:<width$}  ");
+                }
+            }
+            line.trim_end().to_owned()
+        })
+        .collect()
+}
+
 pub fn level_color(level: Level) -> SetForegroundColor {
     match level {
         Level::Trace => TRACE_COLOR,
@@ -62,6 +298,47 @@ pub fn level_color(level: Level) -> SetForegroundColor {
     }
 }
 
+/// Runs `f`, returning the messages it logged on the current thread.
+#[cfg(test)]
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<(Level, String)>) {
+    use std::{cell::RefCell, sync::Once};
+
+    use log_facade::{LevelFilter, Log, Metadata, Record};
+
+    thread_local! {
+        static CAPTURED: RefCell<Option<Vec<(Level, String)>>> = RefCell::new(None);
+    }
+
+    struct CaptureLogger;
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            CAPTURED.with(|captured| {
+                if let Some(captured) = &mut *captured.borrow_mut() {
+                    captured.push((record.level(), record.args().to_string()));
+                }
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log_facade::set_boxed_logger(Box::new(CaptureLogger)).expect("no logger should be set");
+        log_facade::set_max_level(LevelFilter::Trace);
+    });
+
+    CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let value = f();
+    let captured = CAPTURED.with(|captured| captured.take().unwrap_or_default());
+    (value, captured)
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Unknown log level '{0}'")]
@@ -79,3 +356,59 @@ pub enum Error {
     #[error(transparent)]
     Crossterm(#[from] crossterm::ErrorKind),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_grouped() {
+        assert_eq!(format_bytes_grouped(0), "0 bytes");
+        assert_eq!(format_bytes_grouped(1), "1 byte");
+        assert_eq!(format_bytes_grouped(999), "999 bytes");
+        assert_eq!(format_bytes_grouped(1_000), "1,000 bytes");
+        assert_eq!(format_bytes_grouped(999_999), "999,999 bytes");
+        assert_eq!(format_bytes_grouped(1_234_567), "1,234,567 bytes");
+        assert_eq!(
+            format_bytes_grouped(u64::MAX),
+            "18,446,744,073,709,551,615 bytes"
+        );
+    }
+
+    #[test]
+    fn color_choices() {
+        assert!(ColorChoice::Auto.use_color(true, false));
+        assert!(!ColorChoice::Auto.use_color(true, true));
+        assert!(!ColorChoice::Auto.use_color(false, false));
+        assert!(ColorChoice::Always.use_color(false, true));
+        assert!(!ColorChoice::Never.use_color(true, false));
+    }
+
+    #[test]
+    fn table_columns_are_aligned() {
+        let lines = format_table(
+            &["NAME", "ADDRESS", "ROLE"],
+            &[
+                vec![
+                    "node-a".to_owned(),
+                    "10.0.0.1".to_owned(),
+                    "server".to_owned(),
+                ],
+                vec![
+                    "node-beta".to_owned(),
+                    "10.0.0.120".to_owned(),
+                    "agent".to_owned(),
+                ],
+            ],
+        );
+
+        assert_eq!(
+            lines,
+            [
+                "NAME       ADDRESS     ROLE",
+                "node-a     10.0.0.1    server",
+                "node-beta  10.0.0.120  agent",
+            ],
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use once_cell::sync::OnceCell;
+
+use crate::prelude::*;
+
+static PROFILE: OnceCell<Mutex<Profile>> = OnceCell::new();
+
+/// Starts recording the run time of every progress.
+pub fn enable() {
+    PROFILE.get_or_init(|| Mutex::new(Profile::new()));
+}
+
+pub fn get() -> Option<MutexGuard<'static, Profile>> {
+    PROFILE
+        .get()
+        .map(|profile| profile.lock().expect(EXPECT_THREAD_NOT_POSIONED))
+}
+
+pub struct Profile {
+    entries: Vec<(String, Duration)>,
+}
+
+impl Profile {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, message: &str, run_time: Duration) {
+        self.entries.push((message.to_owned(), run_time));
+    }
+
+    /// The recorded progresses, slowest first.
+    pub fn report(&self) -> Vec<Vec<String>> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|(_, a), (_, b)| b.cmp(a));
+        entries
+            .into_iter()
+            .map(|(message, run_time)| {
+                vec![message.clone(), format!("{:.1}s", run_time.as_secs_f64())]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_is_sorted_by_duration() {
+        let mut profile = Profile::new();
+        profile.record("Copying scripts", Duration::from_millis(1_200));
+        profile.record("Building image", Duration::from_millis(42_100));
+        profile.record("Pinging node", Duration::from_millis(300));
+
+        assert_eq!(
+            profile.report(),
+            [
+                ["Building image", "42.1s"],
+                ["Copying scripts", "1.2s"],
+                ["Pinging node", "0.3s"],
+            ]
+        );
+    }
+}
@@ -2,14 +2,21 @@ pub use progress_handle::ProgressHandle;
 
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex, MutexGuard},
+    iter,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     time::{Duration, Instant},
 };
 
 use log_facade::Level;
 use once_cell::sync::OnceCell;
 
-use crate::{log::Error, prelude::*};
+use crate::{
+    log::{emit_event, Error, Event},
+    prelude::*,
+};
 use render::PauseLock;
 
 mod render;
@@ -29,12 +36,87 @@ pub fn pause_rendering(height: usize) -> PauseLock {
     lock
 }
 
+/// Whether the rendering is paused, or about to be, for a prompt.
+pub fn is_rendering_paused() -> bool {
+    render::RenderThread::is_pause_requested()
+}
+
+const DEFAULT_LOG_BUFFER: usize = 10_000;
+
+static LOG_BUFFER: AtomicUsize = AtomicUsize::new(DEFAULT_LOG_BUFFER);
+
+/// Sets the number of logs buffered for rendering in each progress. Simple logs beyond it are
+/// dropped from the rendering, though they are still written to the log file.
+pub fn set_log_buffer(size: usize) {
+    LOG_BUFFER.store(size.max(1), Ordering::SeqCst);
+}
+
+fn log_buffer() -> usize {
+    LOG_BUFFER.load(Ordering::SeqCst)
+}
+
+const DEFAULT_INDENT: usize = 2;
+
+static INDENT: AtomicUsize = AtomicUsize::new(DEFAULT_INDENT);
+
+/// Sets the number of columns each nesting level of progress is indented with, between 1 and 4.
+pub fn set_indent(width: usize) {
+    INDENT.store(width.clamp(1, 4), Ordering::SeqCst);
+}
+
+fn indent() -> usize {
+    INDENT.load(Ordering::SeqCst)
+}
+
+static COLLAPSE_FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether the child logs of successfully finished progresses are collapsed into a summary
+/// line. Warnings, errors and failed progresses are kept visible.
+pub fn set_collapse_finished(collapse: bool) {
+    COLLAPSE_FINISHED.store(collapse, Ordering::SeqCst);
+}
+
+fn collapse_finished() -> bool {
+    COLLAPSE_FINISHED.load(Ordering::SeqCst)
+}
+
+/// Pushes the simple log, dropping the oldest simple logs if the buffer is full. The dropped logs
+/// are counted in a leading marker.
+fn push_bounded(logs: &mut VecDeque<Log>, simple_log: SimpleLog, buffer: usize) {
+    logs.push_back(Log::Simple(simple_log));
+
+    while logs.len() > buffer {
+        let dropped = match logs.front() {
+            Some(Log::Simple(marker)) if marker.dropped > 0 => marker.dropped,
+            _ => 0,
+        };
+
+        // Progress logs are kept, since they may still be running.
+        let oldest = logs
+            .iter()
+            .enumerate()
+            .skip(usize::from(dropped > 0))
+            .find_map(|(index, log)| matches!(log, Log::Simple(_)).then_some(index));
+        let Some(oldest) = oldest else {
+            break;
+        };
+        logs.remove(oldest);
+
+        if dropped > 0 {
+            logs[0] = Log::Simple(SimpleLog::dropped(dropped + 1));
+        } else {
+            logs.push_front(Log::Simple(SimpleLog::dropped(1)));
+        }
+    }
+}
+
 fn last_running_subprogress_mut<'a>(
     logs: impl Iterator<Item = &'a mut Log>,
 ) -> Option<&'a mut ProgressLog> {
     logs.filter_map(|log| {
         if let Log::Progress(progress_log) = log {
-            (!progress_log.is_finished()).then_some(progress_log)
+            // Spinners do not show any nested logs.
+            (!progress_log.is_finished() && !progress_log.is_spinner).then_some(progress_log)
         } else {
             None
         }
@@ -63,6 +145,11 @@ impl Progress {
     }
 
     pub fn push_simple_log(&self, level: Level, message: String) {
+        emit_event(Event::Log {
+            level: level.as_str(),
+            message: &message,
+        });
+
         // Find the current progress log.
         let mut logs_lock = self.logs.lock().expect(EXPECT_THREAD_NOT_POSIONED);
         let logs = &mut *logs_lock;
@@ -74,9 +161,11 @@ impl Progress {
             }
         } else {
             for line in message.lines() {
-                logs.push_back(Log::Simple(
+                push_bounded(
+                    logs,
                     SimpleLog::new(line.to_owned()).with_level(level),
-                ));
+                    log_buffer(),
+                );
             }
         }
     }
@@ -87,8 +176,27 @@ impl Progress {
         level: Option<Level>,
         module: &'static str,
     ) -> ProgressHandle {
+        emit_event(Event::ProgressStart { message: &message });
+
         let (subprogress_log, progress_handle) = ProgressLog::new(message, level, module);
+        self.push_subprogress_log(subprogress_log);
+        progress_handle
+    }
 
+    pub fn push_spinner_log(
+        &self,
+        message: String,
+        level: Option<Level>,
+        module: &'static str,
+    ) -> ProgressHandle {
+        emit_event(Event::ProgressStart { message: &message });
+
+        let (spinner_log, progress_handle) = ProgressLog::new_spinner(message, level, module);
+        self.push_subprogress_log(spinner_log);
+        progress_handle
+    }
+
+    fn push_subprogress_log(&self, subprogress_log: ProgressLog) {
         // Find the current progress log.
         let mut logs_lock = self.logs.lock().expect(EXPECT_THREAD_NOT_POSIONED);
         let logs = &mut *logs_lock;
@@ -99,8 +207,6 @@ impl Progress {
         } else {
             logs.push_back(Log::Progress(subprogress_log));
         }
-
-        progress_handle
     }
 
     fn push_pause_log(&self, height: usize) -> (Shared<bool>, Shared<Option<LevelMessage>>) {
@@ -122,6 +228,26 @@ impl Progress {
         (is_finished_mutex, message_mutex)
     }
 
+    pub fn push_table_log(&self, header: String, rows: Vec<String>) {
+        let header_log = SimpleLog::new(header).dimmed().truncated();
+        let row_logs = rows.into_iter().map(|row| SimpleLog::new(row).truncated());
+
+        // Find the current progress log.
+        let mut logs_lock = self.logs.lock().expect(EXPECT_THREAD_NOT_POSIONED);
+        let logs = &mut *logs_lock;
+        let progress_log = last_running_subprogress_mut(logs.iter_mut());
+
+        if let Some(progress_log) = progress_log {
+            for simple_log in iter::once(header_log).chain(row_logs) {
+                progress_log.push_simple_log(simple_log);
+            }
+        } else {
+            for simple_log in iter::once(header_log).chain(row_logs) {
+                logs.push_back(Log::Simple(simple_log));
+            }
+        }
+    }
+
     fn push_empty_log(&self) {
         // Find the current progress log.
         let mut logs_lock = self.logs.lock().expect(EXPECT_THREAD_NOT_POSIONED);
@@ -135,6 +261,18 @@ impl Progress {
         }
     }
 
+    /// The number of running progresses nested within each other.
+    pub fn current_depth(&self) -> usize {
+        let mut logs = self.logs();
+        let mut depth = 0;
+        let mut progress_log = last_running_subprogress_mut(logs.iter_mut());
+        while let Some(log) = progress_log {
+            depth += 1;
+            progress_log = last_running_subprogress_mut(log.logs.iter_mut());
+        }
+        depth
+    }
+
     fn logs(&self) -> MutexGuard<VecDeque<Log>> {
         self.logs.lock().expect(EXPECT_THREAD_NOT_POSIONED)
     }
@@ -165,6 +303,9 @@ enum Log {
 struct SimpleLog {
     level: Option<Level>,
     message: String,
+    dimmed: bool,
+    truncated: bool,
+    dropped: usize,
 }
 
 impl SimpleLog {
@@ -172,6 +313,17 @@ impl SimpleLog {
         Self {
             level: None,
             message,
+            dimmed: false,
+            truncated: false,
+            dropped: 0,
+        }
+    }
+
+    fn dropped(count: usize) -> Self {
+        let plural = if count == 1 { "" } else { "s" };
+        Self {
+            dropped: count,
+            ..Self::new(format!("\u{2026} {count} line{plural} dropped \u{2026}")).dimmed()
         }
     }
 
@@ -179,6 +331,16 @@ impl SimpleLog {
         self.level.replace(level);
         self
     }
+
+    fn dimmed(mut self) -> Self {
+        self.dimmed = true;
+        self
+    }
+
+    fn truncated(mut self) -> Self {
+        self.truncated = true;
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -186,8 +348,11 @@ pub struct ProgressLog {
     level: Option<Level>,
     message: String,
     start_time: Instant,
-    logs: Vec<Log>,
+    logs: VecDeque<Log>,
     run_time: Shared<Option<Duration>>,
+    has_failed: Shared<bool>,
+    completion: Shared<Option<f64>>,
+    is_spinner: bool,
 }
 
 impl ProgressLog {
@@ -198,11 +363,19 @@ impl ProgressLog {
             .is_some()
     }
 
+    fn has_failed(&self) -> bool {
+        *self.has_failed.lock().expect(EXPECT_THREAD_NOT_POSIONED)
+    }
+
+    fn completion(&self) -> Option<f64> {
+        *self.completion.lock().expect(EXPECT_THREAD_NOT_POSIONED)
+    }
+
     fn push_simple_log(&mut self, simple_log: SimpleLog) {
         if let Some(last_running_subprogress) = last_running_subprogress_mut(self.logs.iter_mut()) {
             last_running_subprogress.push_simple_log(simple_log);
         } else {
-            self.logs.push(Log::Simple(simple_log));
+            push_bounded(&mut self.logs, simple_log, log_buffer());
         }
     }
 
@@ -210,7 +383,7 @@ impl ProgressLog {
         if let Some(last_running_subprogress) = last_running_subprogress_mut(self.logs.iter_mut()) {
             last_running_subprogress.push_progress_log(progress_log);
         } else {
-            self.logs.push(Log::Progress(progress_log));
+            self.logs.push_back(Log::Progress(progress_log));
         }
     }
 
@@ -218,7 +391,7 @@ impl ProgressLog {
         if let Some(last_running_subprogress) = last_running_subprogress_mut(self.logs.iter_mut()) {
             last_running_subprogress.push_pause_log(pause_log);
         } else {
-            self.logs.push(Log::Pause(pause_log));
+            self.logs.push_back(Log::Pause(pause_log));
         }
     }
 }
@@ -253,9 +426,15 @@ enum LogType {
 }
 
 mod progress_handle {
+    use std::thread;
+
     use chrono::Utc;
 
-    use crate::log::logger::{LoggerBuffer, LoggerMeta};
+    use crate::log::{
+        ci,
+        logger::{LoggerBuffer, LoggerMeta},
+        profile,
+    };
 
     use super::*;
 
@@ -269,27 +448,49 @@ mod progress_handle {
                 message: message.clone(),
                 level,
                 start_time: Instant::now(),
-                logs: Vec::new(),
+                logs: VecDeque::new(),
                 run_time: Arc::new(Mutex::new(None)),
+                has_failed: Arc::new(Mutex::new(false)),
+                completion: Arc::new(Mutex::new(None)),
+                is_spinner: false,
             };
             let progress_handle = ProgressHandle::new(
                 message,
                 level,
                 module,
-                log.start_time,
-                Arc::clone(&log.run_time),
+                SharedData {
+                    start_time: log.start_time,
+                    run_time: Arc::clone(&log.run_time),
+                    has_failed: Arc::clone(&log.has_failed),
+                    completion: Arc::clone(&log.completion),
+                },
             );
 
             (log, progress_handle)
         }
+
+        /// A single line progress without any nested logs or elapsed time.
+        pub fn new_spinner(
+            message: String,
+            level: Option<Level>,
+            module: &'static str,
+        ) -> (Self, ProgressHandle) {
+            let (mut log, progress_handle) = Self::new(message, level, module);
+            log.is_spinner = true;
+            (log, progress_handle)
+        }
     }
 
     #[must_use]
     pub struct ProgressHandle {
-        timings: Option<TimingData>,
+        shared_data: Option<SharedData>,
         message: String,
         level: Option<Level>,
         module: &'static str,
+        has_failed: bool,
+        position: Option<(u64, u64)>,
+        start_time: Instant,
+        is_ci_group: bool,
     }
 
     impl ProgressHandle {
@@ -297,17 +498,17 @@ mod progress_handle {
             message: String,
             level: Option<Level>,
             module: &'static str,
-            start_time: Instant,
-            run_time: Shared<Option<Duration>>,
+            shared_data: SharedData,
         ) -> Self {
             Self {
-                timings: Some(TimingData {
-                    start_time,
-                    run_time,
-                }),
+                start_time: shared_data.start_time,
+                shared_data: Some(shared_data),
                 message,
                 level,
                 module,
+                has_failed: false,
+                position: None,
+                is_ci_group: false,
             }
         }
 
@@ -317,40 +518,252 @@ mod progress_handle {
             module: &'static str,
         ) -> Self {
             Self {
-                timings: None,
+                shared_data: None,
                 message,
                 level,
                 module,
+                has_failed: false,
+                position: None,
+                start_time: Instant::now(),
+                is_ci_group: false,
             }
         }
 
+        /// A progress printed as a group of CI workflow commands instead of being rendered.
+        pub(in crate::log) fn new_for_ci(
+            message: String,
+            level: Option<Level>,
+            module: &'static str,
+        ) -> Self {
+            let mut handle = Self::new_for_buffer(message, level, module);
+            handle.is_ci_group = true;
+            handle
+        }
+
         pub fn finish(self) {}
+
+        /// Sets the completion of the progress, turning it into a determinate progress if it was
+        /// not already one.
+        #[allow(unused)]
+        pub fn set_progress(&mut self, current: u64, total: u64) {
+            self.position.replace((current, total));
+
+            if let Some(shared_data) = &self.shared_data {
+                let completion = if total > 0 {
+                    (current as f64 / total as f64).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                shared_data
+                    .completion
+                    .lock()
+                    .expect(EXPECT_THREAD_NOT_POSIONED)
+                    .replace(completion);
+            }
+        }
+
+        /// Advances a determinate progress by `n` steps.
+        #[allow(unused)]
+        pub fn inc(&mut self, n: u64) {
+            if let Some((current, total)) = self.position {
+                self.set_progress(current.saturating_add(n).min(total), total);
+            }
+        }
+
+        /// The completion of a determinate progress, between 0 and 1.
+        #[allow(unused)]
+        pub fn completion(&self) -> Option<f64> {
+            let shared_data = self.shared_data.as_ref()?;
+            *shared_data
+                .completion
+                .lock()
+                .expect(EXPECT_THREAD_NOT_POSIONED)
+        }
+
+        /// Finishes the progress, marking it as failed.
+        #[allow(unused)]
+        pub fn fail(mut self) {
+            self.has_failed = true;
+        }
     }
 
     impl Drop for ProgressHandle {
         fn drop(&mut self) {
-            if let Some(timings) = &self.timings {
-                timings
+            // A progress dropped while unwinding did not finish successfully.
+            let has_failed = self.has_failed || thread::panicking();
+
+            if let Some(mut profile) = profile::get() {
+                profile.record(&self.message, self.start_time.elapsed());
+            }
+
+            if let Some(shared_data) = &self.shared_data {
+                *shared_data
+                    .has_failed
+                    .lock()
+                    .expect(EXPECT_THREAD_NOT_POSIONED) = has_failed;
+                shared_data
                     .run_time
                     .lock()
                     .expect(EXPECT_THREAD_NOT_POSIONED)
-                    .replace(timings.start_time.elapsed());
+                    .replace(shared_data.start_time.elapsed());
+
+                emit_event(Event::ProgressEnd {
+                    message: &self.message,
+                    failed: has_failed,
+                });
             }
+
+            if self.is_ci_group {
+                ci::end_group(&self.message, has_failed);
+            }
+
+            let (level, tag) = if has_failed {
+                (Level::Error, "PROGRESS FAILED")
+            } else {
+                (self.level.unwrap_or(Level::Info), "PROGRESS END")
+            };
             LoggerBuffer::get_or_init()
                 .push(
                     LoggerMeta {
                         timestamp: Utc::now(),
-                        level: self.level.unwrap_or(Level::Info),
+                        level,
                         module: Some(self.module.into()),
                     },
-                    format!("[PROGRESS END] {}", self.message),
+                    format!("[{tag}] {}", self.message),
                 )
                 .unwrap_or_else(|e| panic!("{e}"));
         }
     }
 
-    struct TimingData {
+    struct SharedData {
         start_time: Instant,
         run_time: Shared<Option<Duration>>,
+        has_failed: Shared<bool>,
+        completion: Shared<Option<f64>>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, BufRead, BufReader},
+        os::unix::net::UnixListener,
+    };
+
+    use super::*;
+    use crate::{log::event, util};
+
+    /// Whether the progress with the message is finished, searching the global progress logs.
+    fn is_progress_finished(message: &str) -> Option<bool> {
+        fn find(logs: &VecDeque<Log>, message: &str) -> Option<bool> {
+            logs.iter().find_map(|log| match log {
+                Log::Progress(progress_log) if progress_log.message == message => {
+                    Some(progress_log.is_finished())
+                }
+                Log::Progress(progress_log) => find(&progress_log.logs, message),
+                _ => None,
+            })
+        }
+
+        find(&Progress::get_or_init().logs(), message)
+    }
+
+    #[test]
+    fn progress_is_scoped_to_closure() {
+        let message = "Scoped to closure";
+
+        let during = crate::log::with_progress(message.to_owned(), None, module_path!(), || {
+            is_progress_finished(message)
+        });
+
+        assert_eq!(during, Some(false));
+        assert_eq!(is_progress_finished(message), Some(true));
+    }
+
+    #[test]
+    fn depth_increases_when_nested() {
+        let progress = Progress::new();
+        let module = module_path!();
+
+        let outer = progress.push_progress_log("Outer".to_owned(), None, module);
+        assert_eq!(progress.current_depth(), 1);
+
+        let inner = progress.push_progress_log("Inner".to_owned(), None, module);
+        assert_eq!(progress.current_depth(), 2);
+
+        drop(inner);
+        assert_eq!(progress.current_depth(), 1);
+
+        drop(outer);
+        assert_eq!(progress.current_depth(), 0);
+    }
+
+    #[test]
+    #[throws(io::Error)]
+    fn progress_emits_events() {
+        let socket_dir = util::TempDir::new()?;
+        let socket_path = socket_dir.path().join("events.sock");
+        let listener = UnixListener::bind(&socket_path)?;
+
+        event::connect(&socket_path)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let progress = Progress::new();
+        let handle = progress.push_progress_log("Emitting events".to_owned(), None, module_path!());
+        progress.push_simple_log(Level::Info, "First event line".to_owned());
+        progress.push_simple_log(Level::Warn, "Second event line".to_owned());
+        drop(handle);
+
+        // Other tests may log through the same sink, so only the lines from this test are kept.
+        let mut lines = Vec::new();
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.contains("Emitting events") || line.contains("event line") {
+                lines.push(line);
+            }
+            if lines.len() == 4 {
+                break;
+            }
+        }
+        assert_eq!(
+            lines,
+            [
+                r#"{"event":"progress_start","message":"Emitting events"}"#,
+                r#"{"event":"log","level":"INFO","message":"First event line"}"#,
+                r#"{"event":"log","level":"WARN","message":"Second event line"}"#,
+                r#"{"event":"progress_end","message":"Emitting events","failed":false}"#,
+            ],
+        );
+    }
+
+    #[test]
+    fn logs_beyond_buffer_are_dropped() {
+        let mut logs = VecDeque::new();
+        push_bounded(&mut logs, SimpleLog::new("first".to_owned()), 3);
+        let (progress_log, handle) = ProgressLog::new("progress".to_owned(), None, module_path!());
+        logs.push_back(Log::Progress(progress_log));
+        handle.finish();
+
+        for i in 0..1000 {
+            push_bounded(&mut logs, SimpleLog::new(format!("line {i}")), 3);
+        }
+
+        let messages: Vec<_> = logs
+            .iter()
+            .map(|log| match log {
+                Log::Simple(simple_log) => simple_log.message.as_str(),
+                Log::Progress(progress_log) => progress_log.message.as_str(),
+                Log::Pause(_) => "pause",
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "\u{2026} 1000 lines dropped \u{2026}",
+                "progress",
+                "line 999",
+            ]
+        );
     }
 }
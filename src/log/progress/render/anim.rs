@@ -1,28 +1,132 @@
+use std::env;
+
+use once_cell::sync::OnceCell;
+
 const LENGTH: usize = 8;
 const SLOWDOWN: usize = 4;
 
-const BRAILLE_SPIN_ANIMATION: [char; LENGTH] = ['⢹', '⣸', '⣴', '⣦', '⣇', '⡏', '⠟', '⠻'];
-const BOX_SIDE_SWELL_ANIMATION: [char; LENGTH] = ['│', '╿', '┃', '┃', '┃', '┃', '╽', '│'];
-const BOX_TURN_SWELL_ANIMATION: [char; LENGTH] = ['└', '┖', '┗', '┗', '┗', '┗', '┕', '└'];
-const BOX_END_SWELL_ANIMATION: [char; LENGTH] = ['╴', '╸', '╸', '╸', '╸', '╸', '╴', '╴'];
-const SEPARATOR_SWELL_ANIMATION: [char; LENGTH] = ['─', '─', '─', '─', '─', '─', '─', '─'];
-
-const BRAILLE_SPIN_PAUSED: char = '';
-const BOX_SIDE_SWELL_PAUSED: char = '│';
-const BOX_TURN_SWELL_PAUSED: char = '└';
-const BOX_END_SWELL_PAUSED: char = '╴';
-const SEPARATOR_SWELL_PAUSED: char = '─';
-
-const BRAILLE_SPIN_FINISHED: char = '';
-const BOX_SIDE_SWELL_FINISHED: char = '┃';
-const BOX_TURN_SWELL_FINISHED: char = '┗';
-const BOX_END_SWELL_FINISHED: char = '╸';
-const SEPARATOR_SWELL_FINISHED: char = '━';
-
-#[derive(Copy, Clone)]
+const UNICODE: Glyphs = Glyphs {
+    spin: Animation {
+        frames: ['⢹', '⣸', '⣴', '⣦', '⣇', '⡏', '⠟', '⠻'],
+        paused: '',
+        finished: '',
+    },
+    spin_failed: '\u{f00d}',
+    box_side: Animation {
+        frames: ['│', '╿', '┃', '┃', '┃', '┃', '╽', '│'],
+        paused: '│',
+        finished: '┃',
+    },
+    box_turn: Animation {
+        frames: ['└', '┖', '┗', '┗', '┗', '┗', '┕', '└'],
+        paused: '└',
+        finished: '┗',
+    },
+    box_end: Animation {
+        frames: ['╴', '╸', '╸', '╸', '╸', '╸', '╴', '╴'],
+        paused: '╴',
+        finished: '╸',
+    },
+    separator: Animation {
+        frames: ['─', '─', '─', '─', '─', '─', '─', '─'],
+        paused: '─',
+        finished: '━',
+    },
+    bar_filled: '━',
+    dash: '╶',
+    ellipsis: "\u{2026}",
+};
+
+const ASCII: Glyphs = Glyphs {
+    spin: Animation {
+        frames: ['|', '/', '-', '\\', '|', '/', '-', '\\'],
+        paused: '-',
+        finished: '*',
+    },
+    spin_failed: 'x',
+    box_side: Animation {
+        frames: ['|'; LENGTH],
+        paused: '|',
+        finished: '|',
+    },
+    box_turn: Animation {
+        frames: ['+'; LENGTH],
+        paused: '+',
+        finished: '+',
+    },
+    box_end: Animation {
+        frames: ['-'; LENGTH],
+        paused: '-',
+        finished: '-',
+    },
+    separator: Animation {
+        frames: ['-'; LENGTH],
+        paused: '-',
+        finished: '-',
+    },
+    bar_filled: '#',
+    dash: '-',
+    ellipsis: "...",
+};
+
+struct Glyphs {
+    spin: Animation,
+    spin_failed: char,
+    box_side: Animation,
+    box_turn: Animation,
+    box_end: Animation,
+    separator: Animation,
+    bar_filled: char,
+    dash: char,
+    ellipsis: &'static str,
+}
+
+struct Animation {
+    frames: [char; LENGTH],
+    paused: char,
+    finished: char,
+}
+
+impl Animation {
+    fn frame(&self, state: State) -> char {
+        match state {
+            State::Animating(frame) => self.frames[frame.rem_euclid(LENGTH)],
+            State::Paused => self.paused,
+            State::Finished | State::Failed => self.finished,
+        }
+    }
+}
+
+/// The set of characters used for rendering, selected with `HOC_ANIMATION`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Style {
+    Unicode,
+    Ascii,
+}
+
+impl Style {
+    pub fn get() -> Self {
+        static STYLE: OnceCell<Style> = OnceCell::new();
+
+        *STYLE.get_or_init(|| match env::var("HOC_ANIMATION") {
+            Ok(style) if style.eq_ignore_ascii_case("ascii") => Self::Ascii,
+            _ => Self::Unicode,
+        })
+    }
+
+    fn glyphs(self) -> &'static Glyphs {
+        match self {
+            Self::Unicode => &UNICODE,
+            Self::Ascii => &ASCII,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum State {
     Animating(usize),
     Finished,
+    Failed,
     Paused,
 }
 
@@ -37,66 +141,50 @@ impl State {
 }
 
 pub fn braille_spin(state: State) -> char {
-    get_frame(
-        state,
-        BRAILLE_SPIN_ANIMATION,
-        BRAILLE_SPIN_PAUSED,
-        BRAILLE_SPIN_FINISHED,
-    )
+    spin(Style::get(), state)
 }
 
 pub fn box_side_swell(state: State) -> char {
-    get_frame(
-        state,
-        BOX_SIDE_SWELL_ANIMATION,
-        BOX_SIDE_SWELL_PAUSED,
-        BOX_SIDE_SWELL_FINISHED,
-    )
+    Style::get().glyphs().box_side.frame(state)
 }
 
 pub fn box_turn_swell(state: State) -> char {
-    get_frame(
-        state,
-        BOX_TURN_SWELL_ANIMATION,
-        BOX_TURN_SWELL_PAUSED,
-        BOX_TURN_SWELL_FINISHED,
-    )
+    Style::get().glyphs().box_turn.frame(state)
 }
 
 pub fn box_end_swell(state: State) -> char {
-    get_frame(
-        state,
-        BOX_END_SWELL_ANIMATION,
-        BOX_END_SWELL_PAUSED,
-        BOX_END_SWELL_FINISHED,
-    )
+    Style::get().glyphs().box_end.frame(state)
 }
 
 pub fn separator_swell(state: State) -> char {
-    get_frame(
-        state,
-        SEPARATOR_SWELL_ANIMATION,
-        SEPARATOR_SWELL_PAUSED,
-        SEPARATOR_SWELL_FINISHED,
-    )
-}
-
-fn get_frame(
-    state: State,
-    animation_chars: [char; LENGTH],
-    paused_char: char,
-    finished_char: char,
-) -> char {
-    match state {
-        State::Animating(frame) => {
-            let index = frame.rem_euclid(LENGTH);
-            animation_chars[index]
-        }
-        State::Paused => paused_char,
-        State::Finished => finished_char,
+    Style::get().glyphs().separator.frame(state)
+}
+
+/// The character for the filled part of a progress bar.
+pub fn bar_filled() -> char {
+    Style::get().glyphs().bar_filled
+}
+
+/// The character for dashed lines and the empty part of a progress bar.
+pub fn dash() -> char {
+    Style::get().glyphs().dash
+}
+
+/// The marker for omitted content.
+pub fn ellipsis() -> &'static str {
+    Style::get().glyphs().ellipsis
+}
+
+fn spin(style: Style, state: State) -> char {
+    let glyphs = style.glyphs();
+    if let State::Failed = state {
+        return glyphs.spin_failed;
     }
+
+    glyphs.spin.frame(state)
 }
 
+#[derive(Clone)]
 pub struct Frames {
     frame_index: usize,
     slowdown_index: usize,
@@ -120,3 +208,35 @@ impl Iterator for Frames {
         Some(self.frame_index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_style_is_ascii() {
+        let glyphs = Style::Ascii.glyphs();
+        let states = (0..LENGTH).map(State::Animating).chain([
+            State::Paused,
+            State::Finished,
+            State::Failed,
+        ]);
+
+        for state in states {
+            for animation in [
+                &glyphs.spin,
+                &glyphs.box_side,
+                &glyphs.box_turn,
+                &glyphs.box_end,
+                &glyphs.separator,
+            ] {
+                assert!(animation.frame(state).is_ascii());
+            }
+            assert!(spin(Style::Ascii, state).is_ascii());
+        }
+
+        assert!(glyphs.bar_filled.is_ascii());
+        assert!(glyphs.dash.is_ascii());
+        assert!(glyphs.ellipsis.is_ascii());
+    }
+}
@@ -1,7 +1,10 @@
 use std::{
-    io, panic,
+    env,
+    io::{self, Write},
+    panic,
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
         Arc, Condvar, Mutex,
     },
     thread::{self, JoinHandle},
@@ -71,17 +74,19 @@ impl RenderThread {
 
         let thread_handle = thread::spawn(move || {
             io::stdout().execute(cursor::Hide)?;
+            let _show_cursor = ShowCursorGuard(io::stdout());
 
             let mut render_info = RenderInfo::new();
             let mut previous_height = None;
 
             let (terminal_cols, _) = terminal::size()?;
-            let mut view = RootView::new(terminal_cols as usize);
+            let mut view = RootView::new(view_width(terminal_cols));
+            let mut title = term::Title::from_env();
 
             while !wants_terminate.load(Ordering::SeqCst) {
                 let (terminal_cols, terminal_rows) = terminal::size()?;
 
-                view.set_max_width(terminal_cols as usize);
+                view.set_max_width(view_width(terminal_cols));
 
                 {
                     let (wants_pause_mutex, wants_pause_cvar) = &*wants_pause;
@@ -89,7 +94,7 @@ impl RenderThread {
                         wants_pause_mutex.lock().expect(EXPECT_THREAD_NOT_POSIONED);
 
                     if let Some(pause_height) = *wants_pause_lock {
-                        render_info.is_paused = true;
+                        render_info.pause();
 
                         let progress = super::Progress::get_or_init();
                         if progress.last_log_type() == Some(LogType::RunningProgress) {
@@ -113,7 +118,9 @@ impl RenderThread {
                                 Log::Progress(progress_log) => {
                                     let is_running = !progress_log.is_finished();
                                     if is_running {
-                                        view.set_max_height(terminal_rows as usize);
+                                        view.set_max_height(
+                                            terminal_rows.saturating_sub(1) as usize
+                                        );
                                     }
 
                                     Self::print_progress_log(
@@ -173,7 +180,9 @@ impl RenderThread {
 
                         {
                             let _lock = wants_pause_cvar
-                                .wait_while(wants_pause_lock, |wants_pause| wants_pause.is_some())
+                                .wait_while(wants_pause_lock, |wants_pause| {
+                                    wants_pause.is_some() && !wants_terminate.load(Ordering::SeqCst)
+                                })
                                 .expect(EXPECT_THREAD_NOT_POSIONED);
                         }
 
@@ -199,9 +208,9 @@ impl RenderThread {
                     }
 
                     {
-                        view.set_max_width(terminal_cols as usize);
+                        view.set_max_width(view_width(terminal_cols));
 
-                        render_info.is_paused = false;
+                        render_info.resume();
 
                         let mut logs = super::Progress::get_or_init().logs();
                         while let Some(log) = logs.pop_front() {
@@ -218,7 +227,10 @@ impl RenderThread {
                                 Log::Progress(progress_log) => {
                                     let is_running = !progress_log.is_finished();
                                     if is_running {
-                                        view.set_max_height(terminal_rows as usize);
+                                        title.set(&mut io::stdout(), &progress_log.message)?;
+                                        view.set_max_height(
+                                            terminal_rows.saturating_sub(1) as usize
+                                        );
                                     }
 
                                     Self::print_progress_log(
@@ -247,12 +259,14 @@ impl RenderThread {
 
                 spin_sleep::sleep(Duration::from_nanos(16_666_667));
 
-                render_info.advance_animation();
+                if !log::suspend::is_suspended() {
+                    render_info.advance_animation();
+                }
             }
 
             let (terminal_cols, _) = terminal::size()?;
 
-            view.set_max_width(terminal_cols as usize);
+            view.set_max_width(view_width(terminal_cols));
             view.set_infinite_height();
 
             let mut logs = super::Progress::get_or_init().logs();
@@ -285,7 +299,8 @@ impl RenderThread {
                 }
             }
 
-            execute!(io::stdout(), style::Print("\n"), cursor::Show)?;
+            title.restore(&mut io::stdout())?;
+            execute!(io::stdout(), style::Print("\n"))?;
 
             Ok(())
         });
@@ -303,6 +318,16 @@ impl RenderThread {
         PauseLock::new(height)?
     }
 
+    pub fn is_pause_requested() -> bool {
+        Self::cell().get().map_or(false, |render_thread| {
+            let (wants_pause_mutex, _) = &*render_thread.wants_pause;
+            wants_pause_mutex
+                .lock()
+                .expect(EXPECT_THREAD_NOT_POSIONED)
+                .is_some()
+        })
+    }
+
     #[throws(Error)]
     fn print_simple_log(
         view: &mut RootView,
@@ -431,6 +456,14 @@ impl RenderThread {
     #[throws(Error)]
     pub fn terminate(&self) {
         self.wants_terminate.store(true, Ordering::SeqCst);
+
+        // Wake up the render thread in case it is waiting on a pause lock to be released.
+        {
+            let (wants_pause_mutex, wants_pause_cvar) = &*self.wants_pause;
+            let _lock = wants_pause_mutex.lock().expect(EXPECT_THREAD_NOT_POSIONED);
+            wants_pause_cvar.notify_all();
+        }
+
         if let Some(thread_handle) = self.handle.lock().expect(EXPECT_THREAD_NOT_POSIONED).take() {
             thread_handle
                 .join()
@@ -439,14 +472,35 @@ impl RenderThread {
     }
 }
 
+/// Shows the cursor when dropped, also if the render thread fails or panics.
+struct ShowCursorGuard<W: Write>(W);
+
+impl<W: Write> Drop for ShowCursorGuard<W> {
+    fn drop(&mut self) {
+        let _ = execute!(self.0, cursor::Show);
+    }
+}
+
 #[must_use]
 pub struct PauseLock {
     data: PauseData,
+    is_detached: bool,
 }
 
 impl PauseLock {
     #[throws(Error)]
     fn new(height: usize) -> Self {
+        // Without any rendering, there is nothing to pause.
+        if log::is_quiet() || log::ci::is_enabled() {
+            return Self {
+                data: PauseData {
+                    message: Arc::new(Mutex::new(None)),
+                    indentation: 0,
+                },
+                is_detached: true,
+            };
+        }
+
         let render_thread = RenderThread::get_or_init();
 
         {
@@ -461,6 +515,9 @@ impl PauseLock {
             wants_pause_cvar.notify_one();
         }
 
+        // The hotkey listener must not take keys meant for the prompt.
+        log::suspend::wait_for_listener();
+
         let data = {
             let (is_paused_mutex, is_paused_cvar) = &*render_thread.is_paused;
             let mut is_paused_lock = is_paused_mutex.lock().expect(EXPECT_THREAD_NOT_POSIONED);
@@ -479,13 +536,29 @@ impl PauseLock {
             }
         };
 
-        Self { data }
+        Self {
+            data,
+            is_detached: false,
+        }
     }
 
     pub fn indentation(&self) -> usize {
         self.data.indentation
     }
 
+    /// Runs `f` on a separate thread, returning `None` if termination of the render thread is
+    /// requested before it finishes. The thread never outlives the call.
+    pub fn wait_for<T, F>(&self, f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        match RenderThread::cell().get() {
+            Some(render_thread) => run_cancellable(f, &render_thread.wants_terminate),
+            None => thread::spawn(f).join().ok(),
+        }
+    }
+
     pub fn finish_with_message(self, level: Level, message: String) {
         self.data
             .message
@@ -497,6 +570,10 @@ impl PauseLock {
 
 impl Drop for PauseLock {
     fn drop(&mut self) {
+        if self.is_detached {
+            return;
+        }
+
         let render_thread = RenderThread::get_or_init();
 
         {
@@ -516,6 +593,69 @@ impl Drop for PauseLock {
     }
 }
 
+/// Runs `f` on a separate thread, giving up on its value as soon as `is_canceled` is set. The
+/// thread is joined either way, so a canceled prompt still has to be dismissed before returning.
+fn run_cancellable<T, F>(f: F, is_canceled: &AtomicBool) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || sender.send(f()).ok());
+
+    let value = wait_cancellable(receiver, is_canceled);
+    let _ = handle.join();
+    value
+}
+
+/// Waits for a value on `receiver`, giving up as soon as `is_canceled` is set.
+fn wait_cancellable<T>(receiver: Receiver<T>, is_canceled: &AtomicBool) -> Option<T> {
+    loop {
+        if is_canceled.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        match receiver.recv_timeout(Duration::from_millis(50)) {
+            Ok(value) => return Some(value),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// The width to render within, capped by `HOC_MAX_WIDTH` if set. The cap never exceeds the
+/// terminal width.
+fn view_width(terminal_cols: u16) -> usize {
+    static MAX_WIDTH: OnceCell<Option<usize>> = OnceCell::new();
+
+    let max_width = *MAX_WIDTH.get_or_init(|| {
+        env::var("HOC_MAX_WIDTH")
+            .ok()
+            .and_then(|width| width.parse().ok())
+            .filter(|&width| width > 0)
+    });
+    capped_width(terminal_cols as usize, max_width)
+}
+
+fn capped_width(terminal_cols: usize, max_width: Option<usize>) -> usize {
+    max_width.map_or(terminal_cols, |max_width| max_width.min(terminal_cols))
+}
+
+/// Formats a duration in seconds, with `precision` number of decimals.
+fn format_elapsed(d: Duration, precision: u8) -> String {
+    let precision = precision.min(9) as u32;
+    if precision == 0 {
+        return format!("{}s", d.as_secs());
+    }
+
+    let fraction = d.subsec_nanos() / 10u32.pow(9 - precision);
+    format!(
+        "{}.{fraction:0width$}s",
+        d.as_secs(),
+        width = precision as usize,
+    )
+}
+
 struct PauseData {
     message: Arc<Mutex<Option<(Level, String)>>>,
     indentation: usize,
@@ -526,7 +666,10 @@ struct RenderInfo {
     pause_cursor: Option<Position>,
     frames: anim::Frames,
     animation_frame: usize,
+    paused_animation: Option<(anim::Frames, usize)>,
     previous_log_type: Option<LogType>,
+    indent: usize,
+    collapse_finished: bool,
 }
 
 impl RenderInfo {
@@ -540,16 +683,59 @@ impl RenderInfo {
             pause_cursor: None,
             frames,
             animation_frame,
+            paused_animation: None,
             previous_log_type: None,
+            indent: super::indent(),
+            collapse_finished: super::collapse_finished(),
         }
     }
 
     fn advance_animation(&mut self) {
         self.animation_frame = self.frames.next().expect(Self::EXPECT_INFINITE_ANIM);
     }
+
+    fn pause(&mut self) {
+        self.is_paused = true;
+        self.paused_animation
+            .replace((self.frames.clone(), self.animation_frame));
+    }
+
+    fn resume(&mut self) {
+        self.is_paused = false;
+
+        // Continue the animation from where it was paused.
+        if let Some((frames, animation_frame)) = self.paused_animation.take() {
+            self.frames = frames;
+            self.animation_frame = animation_frame;
+        }
+    }
+}
+
+impl Log {
+    fn render_height(&self, render_info: &RenderInfo) -> usize {
+        match self {
+            Log::Simple(simple_log) => simple_log.render_height(),
+            Log::Progress(progress_log) => progress_log.render_height(render_info),
+            Log::Pause(pause_log) => pause_log.render_height(),
+        }
+    }
+
+    /// Whether the log is kept visible when its parent progress is collapsed. A progress is kept
+    /// visible if any of its own logs are.
+    fn is_always_visible(&self) -> bool {
+        match self {
+            Log::Simple(simple_log) => matches!(simple_log.level, Some(Level::Warn | Level::Error)),
+            Log::Progress(progress_log) => {
+                progress_log.has_failed() || progress_log.logs.iter().any(Log::is_always_visible)
+            }
+            Log::Pause(_) => false,
+        }
+    }
 }
 
 impl SimpleLog {
+    const DIMMED_COLOR: Color = Color::DarkGrey;
+
     fn render_height(&self) -> usize {
         1
     }
@@ -564,8 +750,20 @@ impl SimpleLog {
             );
             view.clear_color();
         } else {
+            if self.dimmed {
+                view.set_color(Self::DIMMED_COLOR);
+            } else {
+                view.clear_color();
+            }
+
+            if self.truncated {
+                let max_chars = view.max_width().saturating_sub(view.cursor().column());
+                render!(view => self.message.chars().take(max_chars).collect::<String>());
+            } else {
+                render!(view => self.message);
+            }
+
             view.clear_color();
-            render!(view => self.message);
         }
     }
 
@@ -587,21 +785,65 @@ impl SimpleLog {
 impl ProgressLog {
     const RUNNING_COLOR: Color = Color::Yellow;
     const FINISHED_COLOR: Color = Color::DarkCyan;
+    const BAR_WIDTH: usize = 20;
+    const ELAPSED_PRECISION: u8 = 1;
+
+    fn style(&self, render_info: &RenderInfo) -> (anim::State, Color) {
+        if !self.is_finished() {
+            let animation_state = if render_info.is_paused {
+                anim::State::Paused
+            } else {
+                anim::State::Animating(render_info.animation_frame)
+            };
+            (animation_state, Self::RUNNING_COLOR)
+        } else if self.has_failed() {
+            (anim::State::Failed, log::level_color(Level::Error).0)
+        } else {
+            let color = self
+                .level
+                .map_or(Self::FINISHED_COLOR, |l| log::level_color(l).0);
+            (anim::State::Finished, color)
+        }
+    }
 
     fn render_height(&self, render_info: &RenderInfo) -> usize {
-        if self.logs.is_empty() && !render_info.is_paused {
+        if self.is_spinner || self.logs.is_empty() && !render_info.is_paused {
             1
         } else {
-            2 + self
-                .logs
+            let (summary, logs) = self.visible_logs(render_info);
+            2 + summary.map_or(0, |summary| summary.render_height(render_info))
+                + logs
+                    .into_iter()
+                    .map(|log| log.render_height(render_info))
+                    .sum::<usize>()
+        }
+    }
+
+    /// The child logs to render. Once the progress has finished successfully, and finished
+    /// progresses are collapsed, the logs not always visible are replaced by a summary line.
+    fn visible_logs(&self, render_info: &RenderInfo) -> (Option<Log>, Vec<&Log>) {
+        let collapse = render_info.collapse_finished && self.is_finished() && !self.has_failed();
+        let collapsed = if collapse {
+            self.logs
                 .iter()
-                .map(|log| match log {
-                    Log::Simple(simple_log) => simple_log.render_height(),
-                    Log::Progress(progress_log) => progress_log.render_height(render_info),
-                    Log::Pause(pause_log) => pause_log.render_height(),
-                })
-                .sum::<usize>()
+                .filter(|log| !log.is_always_visible())
+                .count()
+        } else {
+            0
+        };
+
+        if collapsed == 0 {
+            return (None, self.logs.iter().collect());
         }
+
+        let plural = if collapsed == 1 { "" } else { "s" };
+        let summary = SimpleLog::new(format!("{collapsed} step{plural} completed")).dimmed();
+        let logs = self
+            .logs
+            .iter()
+            .filter(|log| log.is_always_visible())
+            .collect();
+        (Some(Log::Simple(summary)), logs)
     }
 
     fn render(&self, view: &mut impl View, render_info: &mut RenderInfo) {
@@ -611,20 +853,7 @@ impl ProgressLog {
 
         let run_time = *self.run_time.lock().expect(EXPECT_THREAD_NOT_POSIONED);
         let is_finished = run_time.is_some();
-
-        let animation_state = if is_finished {
-            anim::State::Finished
-        } else if render_info.is_paused {
-            anim::State::Paused
-        } else {
-            anim::State::Animating(render_info.animation_frame)
-        };
-        let color = if is_finished {
-            self.level
-                .map_or(Self::FINISHED_COLOR, |l| log::level_color(l).0)
-        } else {
-            Self::RUNNING_COLOR
-        };
+        let (animation_state, color) = self.style(render_info);
 
         // Print indicator and progress message.
         view.set_color(color);
@@ -634,24 +863,26 @@ impl ProgressLog {
             self.message,
         );
 
-        let render_elapsed = |view: &mut _| match run_time {
-            None => {
-                let elapsed = self.start_time.elapsed();
-                render!(view =>
-                    elapsed.as_secs(),
-                    ".",
-                    elapsed.as_millis() % 1000 / 100,
-                    "s",
-                );
-            }
-            Some(elapsed) => {
-                render!(view =>
-                    elapsed.as_secs(),
-                    ".",
-                    format!("{:03}", elapsed.as_millis() % 1000),
-                    "s",
-                );
-            }
+        if self.is_spinner {
+            view.clear_color();
+            return;
+        }
+
+        // Print the progress bar if the completion is known.
+        if let Some(completion) = self.completion().filter(|_| !is_finished) {
+            let filled = (completion * Self::BAR_WIDTH as f64).round() as usize;
+            render!(view =>
+                " ",
+                anim::bar_filled().to_string().repeat(filled),
+                anim::dash().to_string().repeat(Self::BAR_WIDTH - filled),
+                " ",
+                format!("{:.0}%", completion * 100.0),
+            );
+        }
+
+        let render_elapsed = |view: &mut _| {
+            let elapsed = run_time.unwrap_or_else(|| self.start_time.elapsed());
+            render!(view => format_elapsed(elapsed, Self::ELAPSED_PRECISION));
         };
 
         let render_no_nested = self.logs.is_empty() || view.max_height() == Some(1);
@@ -661,7 +892,7 @@ impl ProgressLog {
             view.cursor_mut().move_to_column(0);
             render!(view =>
                 anim::box_turn_swell(animation_state),
-                "╶".repeat(view.max_width() - 1),
+                anim::dash().to_string().repeat(view.max_width() - 1),
             );
             view.clear_color();
 
@@ -680,12 +911,16 @@ impl ProgressLog {
             return;
         };
 
+        let (summary, logs) = self.visible_logs(render_info);
+        let logs: Vec<_> = summary.iter().chain(logs).collect();
+
         // Reserve two rows for the header and the footer.
-        let inner_max_height = view.max_height().map(|h| h - 2);
+        let mut inner_max_height = view.max_height().map(|h| h - 2);
         // Keep track of the number of render lines required for the submessages.
         let mut remaining_height = self.render_height(render_info) - 2;
 
         let start_row = view.cursor().row() + 1;
+        let indent = render_info.indent;
         let render_prefix = |view: &mut _| {
             View::cursor_mut(view).move_down(1);
             View::cursor_mut(view).move_to_column(0);
@@ -694,11 +929,59 @@ impl ProgressLog {
 
             render!(view =>
                 anim::box_side_swell(animation_state.frame_offset(frame_offset)),
-                " ",
+                " ".repeat(indent - 1),
             );
         };
 
-        for log in self.logs.iter() {
+        // Collapse the middle submessages into a single line if they don't fit. The first
+        // submessages are kept in the upper half, and the last ones fill the rest.
+        let mut collapsed = None;
+        if let Some(max_height) = inner_max_height.filter(|&h| h > 1 && remaining_height > h) {
+            let max_height = max_height - 1;
+
+            let mut head_len = 0;
+            let mut head_height = 0;
+            for log in logs.iter() {
+                let height = log.render_height(render_info);
+                if head_height + height > max_height / 2 {
+                    break;
+                }
+                head_len += 1;
+                head_height += height;
+            }
+
+            let tail_max_height = max_height - head_height;
+            let mut tail_height = remaining_height - head_height;
+            let mut hidden = 0;
+            for log in logs[head_len..].iter() {
+                let height = log.render_height(render_info);
+                if tail_height - height < tail_max_height {
+                    break;
+                }
+                tail_height -= height;
+                hidden += 1;
+            }
+
+            collapsed.replace((head_len, hidden));
+            inner_max_height.replace(tail_max_height);
+        }
+
+        for (index, log) in logs.into_iter().enumerate() {
+            if let Some((_, hidden)) = collapsed.filter(|&(head_len, _)| head_len == index) {
+                view.set_color(color);
+                render_prefix(view);
+                view.set_color(SimpleLog::DIMMED_COLOR);
+                let ellipsis = anim::ellipsis();
+                render!(view => format!("{ellipsis} {hidden} more {ellipsis}"));
+                view.clear_color();
+            }
+
+            // The first submessages are rendered in full when the middle ones are collapsed.
+            let inner_max_height = match collapsed {
+                Some((head_len, _)) if index < head_len => None,
+                _ => inner_max_height,
+            };
+
             view.set_color(color);
 
             match log {
@@ -732,8 +1015,8 @@ impl ProgressLog {
                     }
 
                     let mut subview = view.subview(
-                        Position::new(start_row, 2),
-                        view.max_width() - 2,
+                        Position::new(start_row, indent),
+                        view.max_width() - indent,
                         max_height,
                     );
                     progress_log.render(&mut subview, render_info);
@@ -762,8 +1045,8 @@ impl ProgressLog {
                     }
 
                     let mut subview = view.subview(
-                        Position::new(start_row, 2),
-                        view.max_width() - 2,
+                        Position::new(start_row, indent),
+                        view.max_width() - indent,
                         max_height,
                     );
                     pause_log.render(&mut subview, render_info);
@@ -786,7 +1069,7 @@ impl ProgressLog {
         if render_info.is_paused && !is_finished {
             // Print dashed line to indicate paused, incomplete progress.
             render!(view =>
-                "╶".repeat(view.max_width() - 1),
+                anim::dash().to_string().repeat(view.max_width() - 1),
             );
         } else {
             // Print elapsed time.
@@ -841,3 +1124,365 @@ impl PauseLog {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    fn render_header(progress_log: &ProgressLog) -> String {
+        let mut root_view = RootView::new(80);
+        progress_log.render(&mut root_view, &mut RenderInfo::new());
+        root_view.line_contents().remove(0)
+    }
+
+    #[test]
+    fn indeterminate_to_determinate() {
+        let (progress_log, mut handle) =
+            ProgressLog::new("progress".to_owned(), None, module_path!());
+
+        let header = render_header(&progress_log);
+        assert!(header.starts_with(anim::braille_spin(anim::State::Animating(0))));
+        assert!(!header.contains(anim::bar_filled()));
+        assert!(!header.contains('%'));
+
+        handle.set_progress(40, 100);
+
+        let header = render_header(&progress_log);
+        let bar = format!(
+            "{}{}",
+            anim::bar_filled().to_string().repeat(8),
+            anim::dash().to_string().repeat(12)
+        );
+        assert!(header.starts_with(&format!(
+            "{} progress {bar} 40%",
+            anim::braille_spin(anim::State::Animating(0))
+        )));
+
+        handle.finish();
+    }
+
+    #[test]
+    fn numbered_step_header() {
+        let (progress_log, handle) = ProgressLog::new(
+            log::format_step(2, 5, "Installing packages"),
+            None,
+            module_path!(),
+        );
+
+        let header = render_header(&progress_log);
+        assert!(header.contains("2/5"));
+        assert!(header.contains("Installing packages"));
+
+        handle.finish();
+    }
+
+    /// Renders the header of the progress with the message, searching the global progress logs.
+    fn render_global_header(message: &str) -> Option<String> {
+        fn find<'a>(logs: impl Iterator<Item = &'a Log>, message: &str) -> Option<String> {
+            logs.filter_map(|log| match log {
+                Log::Progress(progress_log) if progress_log.message == message => {
+                    Some(render_header(progress_log))
+                }
+                Log::Progress(progress_log) => find(progress_log.logs.iter(), message),
+                _ => None,
+            })
+            .next()
+        }
+
+        find(super::super::Progress::get_or_init().logs().iter(), message)
+    }
+
+    #[test]
+    fn increment_to_completion() {
+        let message = "Increment to completion";
+        let mut handle = log::progress_with_total(message.to_owned(), 4, None, module_path!());
+        assert_eq!(handle.completion(), Some(0.0));
+
+        for _ in 0..4 {
+            handle.inc(1);
+        }
+        assert_eq!(handle.completion(), Some(1.0));
+        assert!(render_global_header(message).unwrap().contains(" 100%"));
+
+        handle.inc(1);
+        assert_eq!(handle.completion(), Some(1.0));
+
+        handle.finish();
+    }
+
+    #[test]
+    fn animation_is_continuous_across_pause() {
+        let mut render_info = RenderInfo::new();
+        let mut expected_frames = anim::Frames::new();
+        expected_frames.next();
+
+        for _ in 0..5 {
+            render_info.advance_animation();
+            expected_frames.next();
+        }
+        let frame_before_pause = render_info.animation_frame;
+
+        render_info.pause();
+        for _ in 0..11 {
+            render_info.advance_animation();
+        }
+        render_info.resume();
+
+        assert!(!render_info.is_paused);
+        assert_eq!(render_info.animation_frame, frame_before_pause);
+
+        for _ in 0..20 {
+            render_info.advance_animation();
+            assert_eq!(Some(render_info.animation_frame), expected_frames.next());
+        }
+    }
+
+    #[test]
+    fn collapse_children_over_budget() {
+        let (mut progress_log, handle) =
+            ProgressLog::new("progress".to_owned(), None, module_path!());
+        for i in 0..10 {
+            progress_log.push_simple_log(SimpleLog::new(format!("message {i}")));
+        }
+
+        let mut root_view = RootView::new(80);
+        root_view.set_max_height(6);
+        progress_log.render(&mut root_view, &mut RenderInfo::new());
+        let lines = root_view.line_contents();
+
+        let ellipsis = anim::ellipsis();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].ends_with("message 0"));
+        assert!(lines[2].ends_with(&format!("{ellipsis} 7 more {ellipsis}")));
+        assert!(lines[3].ends_with("message 8"));
+        assert!(lines[4].ends_with("message 9"));
+        assert!(!lines.iter().any(|l| l.ends_with("message 7")));
+
+        handle.finish();
+    }
+
+    #[test]
+    fn collapse_finished_children() {
+        let (mut progress_log, handle) =
+            ProgressLog::new("progress".to_owned(), None, module_path!());
+        for i in 0..3 {
+            progress_log.push_simple_log(SimpleLog::new(format!("message {i}")));
+        }
+        progress_log.push_simple_log(SimpleLog::new("careful".to_owned()).with_level(Level::Warn));
+        handle.finish();
+
+        let mut render_info = RenderInfo::new();
+        render_info.collapse_finished = true;
+        let mut root_view = RootView::new(80);
+        progress_log.render(&mut root_view, &mut render_info);
+        let lines = root_view.line_contents();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with("3 steps completed"));
+        assert!(lines[2].ends_with("careful"));
+        assert!(!lines.iter().any(|l| l.contains("message")));
+
+        render_info.collapse_finished = false;
+        let mut root_view = RootView::new(80);
+        progress_log.render(&mut root_view, &mut render_info);
+        assert_eq!(root_view.line_contents().len(), 6);
+    }
+
+    #[test]
+    fn collapse_keeps_nested_warnings() {
+        let (mut progress_log, handle) =
+            ProgressLog::new("progress".to_owned(), None, module_path!());
+        let (mut nested_log, nested_handle) =
+            ProgressLog::new("nested".to_owned(), None, module_path!());
+        nested_log.push_simple_log(SimpleLog::new("message".to_owned()));
+        nested_log.push_simple_log(SimpleLog::new("careful".to_owned()).with_level(Level::Warn));
+        progress_log.push_progress_log(nested_log);
+        progress_log.push_simple_log(SimpleLog::new("message".to_owned()));
+        nested_handle.finish();
+        handle.finish();
+
+        let mut render_info = RenderInfo::new();
+        render_info.collapse_finished = true;
+        let mut root_view = RootView::new(80);
+        progress_log.render(&mut root_view, &mut render_info);
+        let lines = root_view.line_contents();
+
+        assert!(lines.iter().any(|l| l.contains("nested")));
+        assert!(lines.iter().any(|l| l.ends_with("careful")));
+        assert!(!lines.iter().any(|l| l.ends_with("message")));
+    }
+
+    #[test]
+    fn nested_progress_indentation() {
+        let column = |line: &str, s: &str| line[..line.find(s).unwrap()].chars().count();
+
+        for indent in [1, 2] {
+            let (mut progress_log, handle) =
+                ProgressLog::new("progress".to_owned(), None, module_path!());
+            let (nested_log, nested_handle) =
+                ProgressLog::new("nested".to_owned(), None, module_path!());
+            progress_log.push_progress_log(nested_log);
+            progress_log.push_simple_log(SimpleLog::new("message".to_owned()));
+
+            let mut render_info = RenderInfo::new();
+            render_info.indent = indent;
+            let mut root_view = RootView::new(80);
+            progress_log.render(&mut root_view, &mut render_info);
+            let lines = root_view.line_contents();
+
+            assert_eq!(column(&lines[1], "nested"), indent + 2);
+            assert_eq!(column(&lines[2], "message"), 2 * indent);
+
+            nested_handle.finish();
+            handle.finish();
+        }
+    }
+
+    #[test]
+    fn spinner_is_single_line() {
+        let (mut spinner_log, handle) =
+            ProgressLog::new_spinner("spinner".to_owned(), None, module_path!());
+        spinner_log.push_simple_log(SimpleLog::new("message".to_owned()));
+
+        let mut render_info = RenderInfo::new();
+        render_info.pause();
+        let mut root_view = RootView::new(80);
+        spinner_log.render(&mut root_view, &mut render_info);
+        assert_eq!(
+            root_view.line_contents(),
+            [format!(
+                "{} spinner",
+                anim::braille_spin(anim::State::Paused)
+            )],
+        );
+
+        handle.finish();
+
+        let mut root_view = RootView::new(80);
+        spinner_log.render(&mut root_view, &mut RenderInfo::new());
+        assert_eq!(
+            root_view.line_contents(),
+            [format!(
+                "{} spinner",
+                anim::braille_spin(anim::State::Finished)
+            )],
+        );
+    }
+
+    #[test]
+    fn max_width_caps_view() {
+        assert_eq!(capped_width(80, None), 80);
+        assert_eq!(capped_width(80, Some(120)), 80);
+
+        let simple_log = SimpleLog::new("x".repeat(50)).truncated();
+        let mut root_view = RootView::new(capped_width(80, Some(20)));
+        simple_log.render(&mut root_view);
+        assert_eq!(root_view.line_contents(), ["x".repeat(20)]);
+    }
+
+    #[test]
+    fn cursor_is_shown_after_panic() {
+        let mut out = Vec::new();
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _show_cursor = ShowCursorGuard(&mut out);
+            panic!("simulated error");
+        }));
+        assert!(res.is_err());
+
+        assert_eq!(out, b"\x1b[?25h");
+    }
+
+    #[test]
+    fn format_elapsed_precision() {
+        let d = Duration::from_millis(999);
+        assert_eq!(format_elapsed(d, 0), "0s");
+        assert_eq!(format_elapsed(d, 1), "0.9s");
+        assert_eq!(format_elapsed(d, 3), "0.999s");
+
+        let d = Duration::from_millis(1000);
+        assert_eq!(format_elapsed(d, 0), "1s");
+        assert_eq!(format_elapsed(d, 1), "1.0s");
+        assert_eq!(format_elapsed(d, 3), "1.000s");
+
+        let d = Duration::from_millis(61_050);
+        assert_eq!(format_elapsed(d, 0), "61s");
+        assert_eq!(format_elapsed(d, 1), "61.0s");
+        assert_eq!(format_elapsed(d, 2), "61.05s");
+        assert_eq!(format_elapsed(d, 3), "61.050s");
+    }
+
+    #[test]
+    fn finished_progress_style() {
+        let (progress_log, handle) = ProgressLog::new("progress".to_owned(), None, module_path!());
+        handle.finish();
+
+        assert_eq!(
+            progress_log.style(&RenderInfo::new()),
+            (anim::State::Finished, ProgressLog::FINISHED_COLOR),
+        );
+    }
+
+    #[test]
+    fn failed_progress_style() {
+        let (progress_log, handle) = ProgressLog::new("progress".to_owned(), None, module_path!());
+        handle.fail();
+
+        assert_eq!(
+            progress_log.style(&RenderInfo::new()),
+            (anim::State::Failed, log::level_color(Level::Error).0),
+        );
+    }
+
+    #[test]
+    fn progress_dropped_while_panicking_fails() {
+        let (progress_log, handle) = ProgressLog::new("progress".to_owned(), None, module_path!());
+
+        let res = panic::catch_unwind(AssertUnwindSafe(move || {
+            let _handle = handle;
+            panic!("simulated error");
+        }));
+        assert!(res.is_err());
+
+        assert!(progress_log.is_finished());
+        assert_eq!(
+            progress_log.style(&RenderInfo::new()),
+            (anim::State::Failed, log::level_color(Level::Error).0),
+        );
+    }
+
+    #[test]
+    fn canceled_wait_joins_thread() {
+        let is_canceled = AtomicBool::new(true);
+        let is_finished = Arc::new(AtomicBool::new(false));
+
+        let is_finished_clone = Arc::clone(&is_finished);
+        let res = run_cancellable(
+            move || {
+                thread::sleep(Duration::from_millis(100));
+                is_finished_clone.store(true, Ordering::SeqCst);
+            },
+            &is_canceled,
+        );
+
+        assert_eq!(res, None);
+        assert!(is_finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn pending_wait_is_canceled_on_terminate() {
+        let (_sender, receiver) = mpsc::channel::<String>();
+        let is_canceled = Arc::new(AtomicBool::new(false));
+
+        let is_canceled_clone = Arc::clone(&is_canceled);
+        let terminator = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            is_canceled_clone.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(wait_cancellable(receiver, &is_canceled), None);
+        terminator.join().unwrap();
+    }
+}
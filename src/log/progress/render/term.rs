@@ -1,9 +1,10 @@
 use std::{
+    env,
     io::{self, Write},
     ops::{Deref, DerefMut},
 };
 
-use crossterm::{cursor, queue, terminal, QueueableCommand};
+use crossterm::{cursor, queue, terminal, tty::IsTty, QueueableCommand};
 
 use crate::{log::Error, prelude::*};
 
@@ -76,3 +77,79 @@ pub fn clear(lines_up: u16) {
         }
     }
 }
+
+/// Reflects the current top-level progress in the terminal title.
+pub struct Title {
+    is_enabled: bool,
+    current: Option<String>,
+}
+
+impl Title {
+    pub fn from_env() -> Self {
+        let is_enabled = env::var("HOC_SET_TITLE").as_deref() == Ok("1") && io::stdout().is_tty();
+        Self::new(is_enabled)
+    }
+
+    fn new(is_enabled: bool) -> Self {
+        Self {
+            is_enabled,
+            current: None,
+        }
+    }
+
+    #[throws(Error)]
+    pub fn set(&mut self, out: &mut impl Write, status: &str) {
+        if !self.is_enabled || self.current.as_deref() == Some(status) {
+            return;
+        }
+
+        if self.current.is_none() {
+            // Save the original title on the terminal's stack, so it can be restored.
+            out.write_all(b"\x1b[22;0t")?;
+        }
+
+        queue!(out, terminal::SetTitle(format!("hoc: {status}")))?;
+        self.current.replace(status.to_owned());
+    }
+
+    #[throws(Error)]
+    pub fn restore(&mut self, out: &mut impl Write) {
+        if self.current.take().is_some() {
+            out.write_all(b"\x1b[23;0t")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[throws(Error)]
+    fn title_is_set_for_new_progress() {
+        let mut out = Vec::new();
+        let mut title = Title::new(true);
+
+        title.set(&mut out, "Deploying node")?;
+        let written = String::from_utf8_lossy(&out).into_owned();
+        assert!(written.ends_with("\x1b]0;hoc: Deploying node\x07"));
+
+        out.clear();
+        title.set(&mut out, "Deploying node")?;
+        assert!(out.is_empty());
+
+        title.restore(&mut out)?;
+        assert_eq!(out, b"\x1b[23;0t");
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn title_is_not_set_when_disabled() {
+        let mut out = Vec::new();
+        let mut title = Title::new(false);
+
+        title.set(&mut out, "Deploying node")?;
+        title.restore(&mut out)?;
+        assert!(out.is_empty());
+    }
+}
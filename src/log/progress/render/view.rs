@@ -1,8 +1,10 @@
 use std::{
+    borrow::Cow,
     fmt::{self, Display, Formatter},
     io::{self, Write},
-    iter,
+    iter::{self, Peekable},
     ops::{Add, Sub},
+    str::CharIndices,
 };
 
 use crossterm::{
@@ -11,7 +13,10 @@ use crossterm::{
     terminal, QueueableCommand,
 };
 
-use crate::{log::Error, prelude::*};
+use crate::{
+    log::{self, Error},
+    prelude::*,
+};
 
 macro_rules! render {
     ($view:ident => $($content:expr),+ $(,)?) => {{
@@ -29,29 +34,21 @@ fn render_view(
     lines: &mut [(Line, Vec<ColorSpan>)],
 ) {
     let (line, current_color_spans) = &mut lines[cursor.row];
-    let current_char_count = line.content.chars().count();
+    let current_char_count = char_count_without_styling(&line.content);
     let start_column = cursor.column;
     if start_column > current_char_count {
         line.content
             .extend(iter::repeat(' ').take(start_column - current_char_count))
     }
 
-    let start_index: usize = line
-        .content
-        .chars()
-        .map(char::len_utf8)
-        .take(start_column)
-        .sum();
+    let start_index = column_index(&line.content, start_column);
 
     let char_count = content
         .replace_with(&mut |s| {
-            let char_count = s.chars().count();
-            let end_index = line
-                .content
-                .chars()
-                .take(start_index + char_count)
-                .map(char::len_utf8)
-                .sum();
+            // Escape sequences are kept in the line, but they are not visible, so they do not
+            // count towards the width of the line.
+            let char_count = char_count_without_styling(s);
+            let end_index = column_index(&line.content, start_column + char_count);
             line.content.replace_range(start_index..end_index, s);
             ContentSize(char_count)
         })
@@ -141,6 +138,80 @@ fn render_view(
     cursor.move_to_column(end_column);
 }
 
+/// Removes all ANSI escape sequences from `s`.
+pub fn normalize_styling(s: &str) -> Cow<str> {
+    if !s.contains('\x1b') {
+        return Cow::Borrowed(s);
+    }
+
+    Cow::Owned(VisibleChars::new(s).map(|(_, c)| c).collect())
+}
+
+/// Counts the visible characters in `s`, ignoring any ANSI escape sequences.
+pub fn char_count_without_styling(s: &str) -> usize {
+    VisibleChars::new(s).count()
+}
+
+/// Returns the byte index of the visible character at `column` in `s`, or the length of `s` if
+/// there are not that many visible characters.
+fn column_index(s: &str, column: usize) -> usize {
+    VisibleChars::new(s)
+        .nth(column)
+        .map_or(s.len(), |(index, _)| index)
+}
+
+/// Iterates over the characters of a string which are not part of an ANSI escape sequence, along
+/// with their byte indices.
+struct VisibleChars<'a> {
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> VisibleChars<'a> {
+    fn new(s: &'a str) -> Self {
+        Self {
+            chars: s.char_indices().peekable(),
+        }
+    }
+}
+
+impl Iterator for VisibleChars<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, c) = self.chars.next()?;
+            if c != '\x1b' {
+                return Some((index, c));
+            }
+
+            match self.chars.next().map(|(_, c)| c) {
+                // Control Sequence Introducer, which is terminated by a byte in the range 0x40–0x7E.
+                Some('[') => {
+                    for (_, c) in self.chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Operating System Command, which is terminated by BEL or ST.
+                Some(']') => {
+                    while let Some((_, c)) = self.chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' && self.chars.peek().map(|&(_, c)| c) == Some('\\') {
+                            self.chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Two-character sequence.
+                _ => (),
+            }
+        }
+    }
+}
+
 fn assert_subview(
     origin: Position,
     parent_max_width: usize,
@@ -289,27 +360,50 @@ impl RootView {
         self.extend_line_buffer();
 
         let mut stdout = io::stdout();
+        self.queue_lines(&mut stdout, log::use_color())?;
+
+        stdout.flush()?;
+
+        let print_height = self.height;
+
+        self.lines.iter_mut().for_each(|(l, c)| {
+            l.content.clear();
+            l.empty = true;
+            c.clear()
+        });
+        self.height = 0;
+        self.cursor = Position::new(0, 0);
 
+        return print_height;
+    }
+
+    #[throws(Error)]
+    fn queue_lines(&self, out: &mut impl Write, use_color: bool) {
         for (i, (line, color_spans)) in self.lines.iter().take(self.height).enumerate() {
             if i > 0 {
-                stdout.queue(style::Print("\n"))?;
+                out.queue(style::Print("\n"))?;
             }
 
             if line.empty {
                 continue;
             }
 
+            let content = if use_color {
+                Cow::Borrowed(line.content.as_str())
+            } else {
+                normalize_styling(&line.content)
+            };
+
             let mut start = 0;
-            for color_span in color_spans {
-                let chars = line.content.chars().map(char::len_utf8);
-                let color_start_index: usize = chars.clone().take(color_span.start).sum();
-                let color_end_index: usize = chars.take(color_span.end).sum();
+            for color_span in color_spans.iter().filter(|_| use_color) {
+                let color_start_index = column_index(&content, color_span.start);
+                let color_end_index = column_index(&content, color_span.end);
 
                 queue!(
-                    stdout,
-                    style::Print(&line.content[start..color_start_index]),
+                    out,
+                    style::Print(&content[start..color_start_index]),
                     style::SetForegroundColor(color_span.color),
-                    style::Print(&line.content[color_start_index..color_end_index]),
+                    style::Print(&content[color_start_index..color_end_index]),
                     style::SetForegroundColor(Color::Reset),
                 )?;
 
@@ -317,25 +411,20 @@ impl RootView {
             }
 
             queue!(
-                stdout,
-                style::Print(&line.content[start..]),
+                out,
+                style::Print(&content[start..]),
                 terminal::Clear(terminal::ClearType::UntilNewLine),
             )?;
         }
+    }
 
-        stdout.flush()?;
-
-        let print_height = self.height;
-
-        self.lines.iter_mut().for_each(|(l, c)| {
-            l.content.clear();
-            l.empty = true;
-            c.clear()
-        });
-        self.height = 0;
-        self.cursor = Position::new(0, 0);
-
-        return print_height;
+    #[cfg(test)]
+    pub fn line_contents(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .take(self.height)
+            .map(|(l, _)| l.content.clone())
+            .collect()
     }
 }
 
@@ -543,3 +632,68 @@ impl Sub for Position {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style::Stylize;
+
+    use super::*;
+
+    fn queued_output(use_color: bool) -> String {
+        let mut view = RootView::new(80);
+        view.set_color(Color::Red);
+        render!(view => "colored");
+        view.clear_color();
+        view.extend_line_buffer();
+
+        let mut out = Vec::new();
+        view.queue_lines(&mut out, use_color).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn colors_are_queued_when_enabled() {
+        let output = queued_output(true);
+        assert!(output.contains(&style::SetForegroundColor(Color::Red).to_string()));
+    }
+
+    #[test]
+    fn colors_are_stripped_when_disabled() {
+        let output = queued_output(false);
+        assert!(!output.contains(&style::SetForegroundColor(Color::Red).to_string()));
+        assert!(output.starts_with("colored"));
+    }
+
+    #[test]
+    fn colored_width_equals_plain_width() {
+        let plain = "a message ✓";
+        let colored = plain.with(Color::Red).to_string();
+        assert_ne!(plain, colored);
+        assert_eq!(char_count_without_styling(&colored), plain.chars().count());
+
+        let nested = format!("{}{}", "a message ".dark_cyan(), "✓".bold().yellow());
+        assert_eq!(char_count_without_styling(&nested), plain.chars().count());
+    }
+
+    #[test]
+    fn normalize_styling_strips_escapes() {
+        assert_eq!(normalize_styling("plain"), "plain");
+        assert_eq!(normalize_styling("\x1b[38;5;9mred\x1b[39m"), "red");
+        assert_eq!(normalize_styling("\x1b]0;title\x07text"), "text");
+        assert_eq!(normalize_styling("\x1b]0;title\x1b\\text"), "text");
+    }
+
+    #[test]
+    fn rendered_colored_content_has_plain_width() {
+        let mut root_view = RootView::new(80);
+        let view = &mut root_view;
+        let colored = "colored".with(Color::Red).to_string();
+        render!(view => colored, " text");
+        assert_eq!(view.cursor().column(), "colored text".len());
+        assert_eq!(root_view.line_contents(), [format!("{colored} text")]);
+
+        let mut out = Vec::new();
+        root_view.queue_lines(&mut out, false).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("colored text"));
+    }
+}
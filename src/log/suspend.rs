@@ -0,0 +1,268 @@
+use std::{
+    io,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Mutex, MutexGuard, Once,
+    },
+    thread,
+    time::Duration,
+};
+
+use crossterm::{style::Stylize, tty::IsTty};
+use once_cell::sync::OnceCell;
+
+use crate::{log::progress, prelude::*};
+
+/// The key toggling between suspending and resuming the run.
+pub const HOTKEY: u8 = b'p';
+
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+static SUSPENDER: OnceCell<Mutex<Suspender>> = OnceCell::new();
+static ORIGINAL_TERMIOS: OnceCell<libc::termios> = OnceCell::new();
+static WANTS_TERMINATE: AtomicBool = AtomicBool::new(false);
+
+/// Held by the listener while it reads stdin.
+static LISTENER: Mutex<()> = Mutex::new(());
+
+/// The process groups of the children, readable from the signal handler without locking. Children
+/// beyond the capacity do not get the terminating signals forwarded to them.
+static CHILD_GROUPS: [AtomicI32; MAX_CHILD_GROUPS] = [NO_CHILD_GROUP; MAX_CHILD_GROUPS];
+const MAX_CHILD_GROUPS: usize = 32;
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CHILD_GROUP: AtomicI32 = AtomicI32::new(0);
+
+static SIGNAL_HANDLERS: Once = Once::new();
+
+fn suspender() -> MutexGuard<'static, Suspender> {
+    SUSPENDER
+        .get_or_init(|| Mutex::new(Suspender::new()))
+        .lock()
+        .expect(EXPECT_THREAD_NOT_POSIONED)
+}
+
+/// Whether the run is suspended, in which case the progress animation is stopped.
+pub fn is_suspended() -> bool {
+    SUSPENDER.get().map_or(false, |_| suspender().is_suspended)
+}
+
+/// Registers a local child process, to be stopped while the run is suspended. The child is
+/// expected to lead its own process group, so that its descendants are stopped along with it.
+pub fn register_child(pid: u32) {
+    install_signal_handlers();
+
+    let pgid = pid as i32;
+    for slot in &CHILD_GROUPS {
+        if slot
+            .compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            break;
+        }
+    }
+
+    suspender().register(pid, send_signal);
+}
+
+pub fn unregister_child(pid: u32) {
+    let pgid = pid as i32;
+    for slot in &CHILD_GROUPS {
+        let _ = slot.compare_exchange(pgid, 0, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    suspender().unregister(pid);
+}
+
+/// Waits for the listener to stop reading stdin. It does not read again until the rendering is no
+/// longer paused, so a prompt started after this gets all input.
+pub fn wait_for_listener() {
+    drop(LISTENER.lock().expect(EXPECT_THREAD_NOT_POSIONED));
+}
+
+/// Suspends the run if it is running, or resumes it if it is suspended. Returns whether the run
+/// is suspended afterwards.
+pub fn toggle() -> bool {
+    let is_suspended = suspender().toggle(send_signal);
+    if is_suspended {
+        info!(
+            "Run suspended, press '{}' to resume",
+            char::from(HOTKEY).to_string().bold()
+        );
+    } else {
+        info!("Run resumed");
+    }
+    is_suspended
+}
+
+/// Listens for the hotkey on stdin, if it is a terminal. Keys are not read while prompting, so
+/// the prompts get all input.
+pub fn listen() {
+    if !io::stdin().is_tty() || ORIGINAL_TERMIOS.get().is_some() {
+        return;
+    }
+
+    // Read keys as they are pressed, without echoing them, while leaving the output processing as
+    // is.
+    let mut termios = MaybeUninit::uninit();
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+        return;
+    }
+    let original = unsafe { termios.assume_init() };
+    ORIGINAL_TERMIOS.get_or_init(|| original);
+
+    let mut termios = original;
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) };
+    install_signal_handlers();
+
+    thread::spawn(|| {
+        while !WANTS_TERMINATE.load(Ordering::SeqCst) {
+            let key = {
+                let _listener = LISTENER.lock().expect(EXPECT_THREAD_NOT_POSIONED);
+                if progress::is_rendering_paused() || !poll_stdin() {
+                    None
+                } else {
+                    let mut key = 0u8;
+                    let read =
+                        unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) };
+                    (read == 1).then_some(key)
+                }
+            };
+
+            match key {
+                Some(HOTKEY) => {
+                    toggle();
+                }
+                Some(_) => (),
+                None => spin_sleep::sleep(POLL_TIMEOUT),
+            }
+        }
+    });
+}
+
+/// Stops listening for the hotkey, resumes any stopped processes and restores the terminal.
+pub fn cleanup() {
+    WANTS_TERMINATE.store(true, Ordering::SeqCst);
+
+    if is_suspended() {
+        suspender().toggle(send_signal);
+    }
+
+    if let Some(termios) = ORIGINAL_TERMIOS.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+}
+
+fn poll_stdin() -> bool {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, POLL_TIMEOUT.as_millis() as libc::c_int) > 0 }
+}
+
+/// Restores the terminal and passes terminating signals on to the children, which are not in the
+/// foreground process group and would otherwise not get them.
+fn install_signal_handlers() {
+    SIGNAL_HANDLERS.call_once(|| {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            unsafe { libc::signal(signal, handle_signal as libc::sighandler_t) };
+        }
+    });
+}
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if let Some(termios) = ORIGINAL_TERMIOS.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+
+    for slot in &CHILD_GROUPS {
+        let pgid = slot.load(Ordering::SeqCst);
+        if pgid != 0 {
+            // Stopped children only act on the signal once continued.
+            unsafe {
+                libc::kill(-pgid, signal);
+                libc::kill(-pgid, libc::SIGCONT);
+            }
+        }
+    }
+
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+fn send_signal(pid: u32, stop: bool) {
+    let signal = if stop { libc::SIGSTOP } else { libc::SIGCONT };
+    if unsafe { libc::kill(-(pid as libc::pid_t), signal) } != 0 {
+        warn!(
+            "Failed to {} process {pid}: {}",
+            if stop { "stop" } else { "continue" },
+            io::Error::last_os_error(),
+        );
+    }
+}
+
+struct Suspender {
+    is_suspended: bool,
+    children: Vec<u32>,
+}
+
+impl Suspender {
+    fn new() -> Self {
+        Self {
+            is_suspended: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn toggle(&mut self, mut signal: impl FnMut(u32, bool)) -> bool {
+        self.is_suspended = !self.is_suspended;
+        for &pid in &self.children {
+            signal(pid, self.is_suspended);
+        }
+        self.is_suspended
+    }
+
+    /// Processes spawned while suspended are stopped right away.
+    fn register(&mut self, pid: u32, mut signal: impl FnMut(u32, bool)) {
+        self.children.push(pid);
+        if self.is_suspended {
+            signal(pid, true);
+        }
+    }
+
+    fn unregister(&mut self, pid: u32) {
+        self.children.retain(|&child| child != pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suspend_and_resume_children() {
+        let mut suspender = Suspender::new();
+        let mut signals = Vec::new();
+
+        suspender.register(10, |pid, stop| signals.push((pid, stop)));
+        suspender.register(11, |pid, stop| signals.push((pid, stop)));
+        assert!(signals.is_empty());
+
+        assert!(suspender.toggle(|pid, stop| signals.push((pid, stop))));
+        suspender.register(12, |pid, stop| signals.push((pid, stop)));
+        suspender.unregister(10);
+        assert!(!suspender.toggle(|pid, stop| signals.push((pid, stop))));
+
+        assert_eq!(
+            signals,
+            [(10, true), (11, true), (12, true), (11, false), (12, false)]
+        );
+    }
+}
@@ -8,6 +8,20 @@ macro_rules! progress_with_handle {
     }};
 }
 
+#[allow(unused_macros)]
+macro_rules! step {
+    ($current:expr, $total:expr, $($args:tt)*) => {
+        let _handle = $crate::log::step($current, $total, format!($($args)*), None, module_path!());
+    };
+}
+
+#[allow(unused_macros)]
+macro_rules! spinner {
+    ($($args:tt)*) => {{
+        $crate::log::spinner(format!($($args)*), None, module_path!())
+    }};
+}
+
 macro_rules! progress {
     ($($args:tt)*) => {
         let _handle = progress_with_handle!($($args)*);
@@ -35,6 +49,24 @@ macro_rules! kv {
     }};
 }
 
+/// Builds a key from separate components, rejecting any component that would change the structure
+/// of the key.
+///
+/// ## Example
+///
+/// ```no_run
+/// let node_name = "betsy";
+/// let key = key!("nodes", node_name, "network", "address")?;
+/// ```
+#[allow(unused_macros)]
+macro_rules! key {
+    ($($component:expr),+ $(,)?) => {
+        Ok::<_, $crate::context::Error>($crate::context::key::KeyBuilder::new())
+            $(.and_then(|builder| builder.push($component)))+
+            .map($crate::context::key::KeyBuilder::build)
+    };
+}
+
 macro_rules! files {
     ($($args:tt)*) => {{
         let key = $crate::util::from_arguments_to_key_cow(format_args!($($args)*));
@@ -2,10 +2,12 @@ use std::{
     env,
     path::{Path, PathBuf},
     process::ExitCode,
+    thread,
 };
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use once_cell::{sync::OnceCell, unsync};
 use scopeguard::defer;
 
 use self::{command::Command, context::Context, ledger::Ledger, prelude::*};
@@ -23,29 +25,59 @@ mod process;
 mod prompt;
 mod util;
 
+static CONTEXT_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+
 fn home_dir() -> PathBuf {
     let home_dir = env::var("HOME").expect(EXPECT_HOME_ENV_VAR);
     PathBuf::from(home_dir)
 }
 
+/// Overrides the base directory of the context, cache, files, temp and source directories. Fails if
+/// the directory has already been set.
+#[throws(anyhow::Error)]
+fn set_context_dir(context_dir: Option<PathBuf>) {
+    if CONTEXT_DIR.set(context_dir).is_err() {
+        bail!("The context directory has already been set");
+    }
+}
+
+fn context_dir() -> Option<&'static Path> {
+    CONTEXT_DIR.get().and_then(Option::as_deref)
+}
+
 fn local_context_file_path() -> PathBuf {
-    home_dir().join(".local/share/hoc/context.yaml")
+    match context_dir() {
+        Some(context_dir) => context_dir.join("context.yaml"),
+        None => home_dir().join(".local/share/hoc/context.yaml"),
+    }
 }
 
 fn local_files_dir() -> PathBuf {
-    home_dir().join(".local/share/hoc/files")
+    match context_dir() {
+        Some(context_dir) => context_dir.join("files"),
+        None => home_dir().join(".local/share/hoc/files"),
+    }
 }
 
 fn local_cache_dir() -> PathBuf {
-    home_dir().join(".cache/hoc/cache")
+    match context_dir() {
+        Some(context_dir) => context_dir.join("cache"),
+        None => home_dir().join(".cache/hoc/cache"),
+    }
 }
 
 fn local_temp_dir() -> PathBuf {
-    home_dir().join(".cache/hoc/temp")
+    match context_dir() {
+        Some(context_dir) => context_dir.join("temp"),
+        None => home_dir().join(".cache/hoc/temp"),
+    }
 }
 
 fn local_source_dir() -> PathBuf {
-    home_dir().join(".cache/hoc/source")
+    match context_dir() {
+        Some(context_dir) => context_dir.join("source"),
+        None => home_dir().join(".cache/hoc/source"),
+    }
 }
 
 fn container_files_dir() -> &'static Path {
@@ -68,29 +100,138 @@ fn container_source_dir() -> &'static Path {
 struct App {
     #[clap(subcommand)]
     command: Command,
+
+    /// Only print warnings and errors, without any progress rendering
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Print more log messages: `-v` for debug and `-vv` for trace
+    #[clap(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Base directory for the context, overriding the default locations [env: HOC_CONTEXT_DIR]
+    #[clap(long, global = true)]
+    context_dir: Option<PathBuf>,
+
+    /// Answer yes to all confirmations and accept default values [env: HOC_ASSUME_YES]
+    #[clap(short = 'y', long, global = true)]
+    assume_yes: bool,
+
+    /// When to use colors in the output [env: HOC_COLOR]
+    #[clap(long, global = true, value_enum, value_name = "WHEN")]
+    color: Option<log::ColorChoice>,
+
+    /// Keep temporary files after the run for inspection [env: HOC_KEEP_TEMP]
+    #[clap(long, global = true)]
+    keep_temp: bool,
+
+    /// Print workflow commands for a CI service instead of rendering the progress, detected from
+    /// `GITHUB_ACTIONS` if not set
+    #[clap(long, global = true, value_enum, value_name = "PROVIDER")]
+    ci: Option<log::CiProvider>,
+
+    /// Print the run time of every progress at the end of the run
+    #[clap(long, global = true)]
+    profile: bool,
+
+    /// Hide the values of keys matching this template in the logs, e.g. `admin/passwords/*`
+    #[clap(long, global = true, value_name = "KEY")]
+    redact: Vec<String>,
+
+    /// Print the value of this key to stdout after a successful run
+    #[clap(long, global = true, value_name = "KEY")]
+    output: Option<String>,
 }
 
 impl App {
     #[throws(Error)]
-    fn run(self) {
-        match self.command.run() {
-            Ok(()) => (),
+    fn run(self, output: &unsync::OnceCell<String>) -> ExitCode {
+        let output_key = self.output;
+
+        let run_id = self
+            .command
+            .history_name()
+            .map(|name| Context::get_or_init().history_mut().start(name));
+
+        let result = self.command.run();
+
+        if let Some(run_id) = run_id {
+            Context::get_or_init()
+                .history_mut()
+                .finish(run_id, result.is_ok());
+        }
+
+        match result {
+            Ok(()) => {
+                if let Some(key) = output_key {
+                    let item = kv!("{key}")
+                        .get()
+                        .with_context(|| format!("Failed to get output {key:?}"))?;
+                    output.set(item.to_output_string()?).ok();
+                }
+
+                ExitCode::SUCCESS
+            }
             Err(err) => {
                 error!("{err}");
                 Ledger::get_or_init().rollback()?;
+                exit_code(&err)
             }
         }
     }
 }
 
+fn exit_code(err: &Error) -> ExitCode {
+    err.downcast_ref::<command::deploy::Error>()
+        .map_or(ExitCode::FAILURE, command::deploy::Error::exit_code)
+}
+
 #[throws(Error)]
 fn main() -> ExitCode {
     let app = App::parse();
 
-    log::init()?;
-    Context::get_or_init().load()?;
+    let color = match app.color {
+        Some(color) => color,
+        None => match env::var("HOC_COLOR") {
+            Ok(color) => log::ColorChoice::from_str(&color, true).map_err(Error::msg)?,
+            Err(_) => log::ColorChoice::default(),
+        },
+    };
+    if let Ok(log_buffer) = env::var("HOC_LOG_BUFFER") {
+        log::set_log_buffer(log_buffer.parse()?);
+    }
+    if let Ok(indent) = env::var("HOC_INDENT") {
+        log::set_indent(indent.parse()?);
+    }
+    log::set_collapse_finished(app.verbose == 0);
+    log::set_ci_provider(app.ci);
+    log::init(app.quiet, app.verbose, color)?;
+    context::kv::set_redacted(&app.redact);
+    prompt::set_assume_yes(app.assume_yes || env::var_os("HOC_ASSUME_YES").is_some());
+    context::fs::temp::set_keep_temp(app.keep_temp || env::var_os("HOC_KEEP_TEMP").is_some());
+    if app.profile {
+        log::enable_profile();
+    }
+    context::fs::temp::set_cleanup_dry_run(env::var_os("HOC_CLEANUP_DRY_RUN").is_some());
+
+    let context_dir = app
+        .context_dir
+        .clone()
+        .or_else(|| env::var_os("HOC_CONTEXT_DIR").map(PathBuf::from));
+    Context::init(context_dir, app.command.is_read_only())?;
+
+    // Printed last, so it is not mixed up with the progress rendering.
+    let output = unsync::OnceCell::new();
 
     defer! {
+        // When unwinding from a panic, restore the terminal first, so it is not left broken if
+        // persisting the context fails as well.
+        if thread::panicking() {
+            if let Err(err) = log::stop_rendering() {
+                eprintln!("{err}");
+            }
+        }
+
         if let Err(err) = Context::get_or_init().persist() {
             error!("{err}");
         }
@@ -99,13 +240,19 @@ fn main() -> ExitCode {
             error!("{err}");
         }
 
+        log::print_profile();
+
         if let Err(err) = log::cleanup() {
             eprintln!("{err}");
         }
+
+        if let Some(output) = output.get() {
+            println!("{output}");
+        }
     }
 
-    let exit_code = match app.run() {
-        Ok(()) => ExitCode::SUCCESS,
+    let exit_code = match app.run(&output) {
+        Ok(exit_code) => exit_code,
         Err(error) => {
             error!("{error:?}");
             ExitCode::FAILURE
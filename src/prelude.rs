@@ -1,6 +1,6 @@
 pub use anyhow::{bail, ensure, Context as AnyhowContext};
 pub use fehler::{throw, throws};
-pub use log_facade::{debug, error, info, log, warn, Level};
+pub use log_facade::{debug, error, info, log, trace, warn, Level};
 
 pub use crate::{
     context::kv::IteratorExt,
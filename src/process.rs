@@ -3,21 +3,27 @@ use std::{
     collections::HashMap,
     env,
     io::{self, Cursor, Read, Write},
-    net::{IpAddr, TcpStream},
+    iter,
+    net::{IpAddr, SocketAddr, TcpStream},
+    os::unix::process::CommandExt,
+    path::PathBuf,
     process::Stdio,
+    str::FromStr,
     sync::{Arc, Mutex, MutexGuard},
     thread,
     time::Duration,
 };
 
 use crossterm::style::Stylize;
+use indexmap::IndexMap;
 use once_cell::sync::OnceCell;
 use thiserror::Error;
 
 use crate::{
     context::{
         self,
-        kv::{Item, Value},
+        key::{Key, KeyOwned},
+        kv::{Item, Kv, PutOptions, Value},
     },
     ledger::{Ledger, Transaction},
     prelude::*,
@@ -64,6 +70,274 @@ pub fn get_remote_password() -> Secret<String> {
     }
 }
 
+const MAX_PASSPHRASE_ATTEMPTS: u32 = 3;
+
+/// The libssh2 error code for failing to read the private key file, which is what a wrong
+/// passphrase results in.
+const LIBSSH2_ERROR_FILE: i32 = -16;
+
+fn is_passphrase_error(err: &ssh2::Error) -> bool {
+    err.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_FILE)
+}
+
+/// Authenticates with the passphrase, asking for a new one each time the private key cannot be
+/// decrypted with it. Other failures, like the key being rejected, are returned right away.
+#[throws(Error)]
+fn authenticate_with_passphrase<A, P>(passphrase: Secret<String>, mut auth: A, mut ask: P)
+where
+    A: FnMut(&str) -> Result<(), ssh2::Error>,
+    P: FnMut() -> Result<Secret<String>, prompt::Error>,
+{
+    let mut passphrase = passphrase;
+    let mut attempt = 1;
+    loop {
+        match auth(passphrase.as_str()) {
+            Ok(()) => break,
+            Err(err) if !is_passphrase_error(&err) || attempt >= MAX_PASSPHRASE_ATTEMPTS => {
+                throw!(err)
+            }
+            Err(err) => {
+                warn!("Failed to authenticate with SSH key: {err}");
+                passphrase = ask()?;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// A key pair to authenticate SSH sessions with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyPair {
+    public: PathBuf,
+    private: PathBuf,
+
+    /// Whether the private key is protected by a passphrase, which is asked for if needed.
+    has_passphrase: bool,
+}
+
+impl KeyPair {
+    /// The key pairs in `~/.ssh` with the default names, which exist on this machine.
+    fn user_defaults() -> Vec<Self> {
+        let ssh_dir = PathBuf::from(env::var("HOME").expect(EXPECT_HOME_ENV_VAR)).join(".ssh");
+        ["id_ed25519", "id_ecdsa", "id_rsa"]
+            .into_iter()
+            .map(|name| Self {
+                public: ssh_dir.join(format!("{name}.pub")),
+                private: ssh_dir.join(name),
+                has_passphrase: false,
+            })
+            .filter(|key_pair| key_pair.public.exists() && key_pair.private.exists())
+            .collect()
+    }
+}
+
+/// Authenticates with the key pair, asking for the passphrase again only if the key cannot be
+/// decrypted.
+#[throws(Error)]
+fn authenticate_with_key_pair<A, P>(
+    key_pair: &KeyPair,
+    passphrase: &Secret<String>,
+    mut auth: A,
+    ask: P,
+) where
+    A: FnMut(&KeyPair, Option<&str>) -> Result<(), ssh2::Error>,
+    P: FnMut() -> Result<Secret<String>, prompt::Error>,
+{
+    if !key_pair.has_passphrase {
+        auth(key_pair, None)?;
+        return;
+    }
+
+    authenticate_with_passphrase(
+        passphrase.clone(),
+        |passphrase| auth(key_pair, Some(passphrase)),
+        ask,
+    )?;
+}
+
+/// Authenticates with each key pair in turn until one is accepted, falling back to the password.
+#[throws(Error)]
+fn authenticate_with_key_pairs<K, P>(key_pairs: &[KeyPair], mut auth_key: K, auth_password: P)
+where
+    K: FnMut(&KeyPair) -> Result<(), Error>,
+    P: FnOnce() -> Result<(), ssh2::Error>,
+{
+    for key_pair in key_pairs {
+        match auth_key(key_pair) {
+            Ok(()) => {
+                debug!("Authenticated with SSH key {:?}", key_pair.private);
+                return;
+            }
+            Err(err) => debug!("SSH key {:?} was not accepted: {err}", key_pair.private),
+        }
+    }
+
+    debug!("Authenticating with password");
+    auth_password()?;
+}
+
+/// Connection parameters for SSH sessions to the nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshConfig {
+    port: u16,
+
+    /// Timeout for connecting and authenticating.
+    timeout: Option<Duration>,
+
+    /// Interval in seconds between keepalive messages.
+    keepalive_interval: Option<u32>,
+
+    host_key_policy: HostKeyPolicy,
+}
+
+impl SshConfig {
+    /// Loads the defaults in `ssh/defaults`, overridden by the node's settings in
+    /// `nodes/<node_name>/ssh`.
+    #[throws(Error)]
+    pub fn load(node_name: &str) -> Self {
+        Self::load_from(&context::Context::get_or_init().kv(), node_name)?
+    }
+
+    #[throws(Error)]
+    fn load_from(kv: &Kv, node_name: &str) -> Self {
+        let mut layers = Vec::new();
+        for result in [
+            kv.get_item("ssh/defaults/*"),
+            kv.get_item(&format!("nodes/{node_name}/ssh/*")),
+        ] {
+            match result {
+                Ok(item) => layers.push(item),
+                Err(context::Error::KeyDoesNotExist(_)) => (),
+                Err(err) => throw!(err),
+            }
+        }
+
+        Self::from_layers(layers)?
+    }
+
+    #[throws(Error)]
+    fn from_layers<I: IntoIterator<Item = Item>>(layers: I) -> Self {
+        let mut config = Self::default();
+        for layer in layers {
+            config.apply(layer.convert()?)?;
+        }
+        config
+    }
+
+    #[throws(Error)]
+    fn apply(&mut self, overrides: IndexMap<String, Item>) {
+        for (name, item) in overrides {
+            match name.as_str() {
+                "port" => self.port = item.convert()?,
+                "timeout" => {
+                    let seconds: u64 = item.convert()?;
+                    self.timeout = (seconds > 0).then(|| Duration::from_secs(seconds));
+                }
+                "keepalive_interval" => {
+                    let seconds: u32 = item.convert()?;
+                    self.keepalive_interval = (seconds > 0).then_some(seconds);
+                }
+                "host_key_policy" => self.host_key_policy = item.convert::<String>()?.parse()?,
+                _ => throw!(Error::UnknownSshOption(name)),
+            }
+        }
+    }
+
+    #[throws(Error)]
+    fn connect(&self, host: IpAddr) -> ssh2::Session {
+        let address = SocketAddr::new(host, self.port);
+        let stream = match self.timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout)?,
+            None => TcpStream::connect(address)?,
+        };
+
+        let mut session = ssh2::Session::new()?;
+        session.set_tcp_stream(stream);
+        if let Some(timeout) = self.timeout {
+            session.set_timeout(timeout.as_millis().try_into().unwrap_or(u32::MAX));
+        }
+        if let Some(interval) = self.keepalive_interval {
+            session.set_keepalive(false, interval);
+        }
+        session.handshake()?;
+
+        self.verify_host_key(&session, host)?;
+
+        session
+    }
+
+    #[throws(Error)]
+    fn verify_host_key(&self, session: &ssh2::Session, host: IpAddr) {
+        if self.host_key_policy == HostKeyPolicy::Ignore {
+            return;
+        }
+
+        let host = host.to_string();
+        let Some((key, key_type)) = session.host_key() else {
+            throw!(Error::HostKeyVerification(host));
+        };
+
+        let known_hosts_path =
+            PathBuf::from(env::var("HOME").expect(EXPECT_HOME_ENV_VAR)).join(".ssh/known_hosts");
+        let mut known_hosts = session.known_hosts()?;
+        if known_hosts_path.exists() {
+            known_hosts.read_file(&known_hosts_path, ssh2::KnownHostFileKind::OpenSSH)?;
+        }
+
+        match known_hosts.check_port(&host, self.port, key) {
+            ssh2::CheckResult::Match => (),
+            ssh2::CheckResult::NotFound if self.host_key_policy == HostKeyPolicy::AcceptNew => {
+                info!("Adding host key for {host} to {known_hosts_path:?}");
+                let known_host = if self.port == 22 {
+                    host
+                } else {
+                    format!("[{host}]:{}", self.port)
+                };
+                known_hosts.add(&known_host, key, "", key_type.into())?;
+                known_hosts.write_file(&known_hosts_path, ssh2::KnownHostFileKind::OpenSSH)?;
+            }
+            _ => throw!(Error::HostKeyVerification(host)),
+        }
+    }
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        Self {
+            port: 22,
+            timeout: Some(Duration::from_secs(30)),
+            keepalive_interval: None,
+            host_key_policy: HostKeyPolicy::Ignore,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Skip host key verification.
+    Ignore,
+
+    /// Add unknown host keys to the known hosts, but reject mismatching ones.
+    AcceptNew,
+
+    /// Reject host keys not already in the known hosts.
+    Strict,
+}
+
+impl FromStr for HostKeyPolicy {
+    type Err = Error;
+
+    #[throws(Self::Err)]
+    fn from_str(s: &str) -> Self {
+        match s {
+            "ignore" => Self::Ignore,
+            "accept-new" => Self::AcceptNew,
+            "strict" => Self::Strict,
+            _ => throw!(Error::InvalidHostKeyPolicy(s.to_owned())),
+        }
+    }
+}
+
 pub fn global_settings<'a>() -> MutexGuard<'a, Settings> {
     static SETTINGS: OnceCell<Mutex<Settings>> = OnceCell::new();
 
@@ -88,6 +362,8 @@ pub struct ProcessBuilder {
     success_codes: Vec<i32>,
     revert_process: Option<Box<Self>>,
     should_retry: bool,
+    retry_policy: Option<RetryPolicy>,
+    capture: Option<(KeyOwned, Capture)>,
 }
 
 impl ProcessBuilder {
@@ -99,6 +375,8 @@ impl ProcessBuilder {
             success_codes: vec![0],
             revert_process: None,
             should_retry: true,
+            retry_policy: None,
+            capture: None,
         }
     }
 
@@ -169,16 +447,44 @@ impl ProcessBuilder {
         self
     }
 
+    #[allow(unused)]
+    pub fn interpreter<I: Into<Cow<'static, str>>>(mut self, interpreter: I) -> Self {
+        self.settings.interpreter(interpreter);
+        self
+    }
+
     pub fn success_codes<I: IntoIterator<Item = i32>>(mut self, success_codes: I) -> Self {
         self.success_codes = success_codes.into_iter().collect();
         self
     }
 
+    /// Reruns the process with an exponential backoff if it fails with a retryable exit code.
+    #[allow(unused)]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy.replace(retry_policy);
+        self
+    }
+
+    /// Stores the standard output at `key` in the context after a successful run.
+    #[allow(unused)]
+    pub fn capture_stdout<K: Into<KeyOwned>>(mut self, key: K, capture: Capture) -> Self {
+        self.capture.replace((key.into(), capture));
+        self
+    }
+
     fn no_retry(mut self) -> Self {
         self.should_retry = false;
         self
     }
 
+    /// Leaves the revert process and the output capture to the original process, so a rerun does
+    /// not record them a second time.
+    fn no_side_effects(mut self) -> Self {
+        self.revert_process = None;
+        self.capture = None;
+        self
+    }
+
     #[throws(Error)]
     pub fn spawn(mut self) -> Process {
         self.update_settings();
@@ -196,6 +502,7 @@ impl ProcessBuilder {
     #[throws(Error)]
     fn spawn_no_settings_update(mut self, debug_desc: &str) -> Process {
         let mut password_to_cache = None;
+        let mut sudo_password = None;
         if self.settings.is_sudo() {
             let password = if let Some(temp_password) = self.settings.get_sudo_password() {
                 Secret::new(temp_password.into_owned())
@@ -216,6 +523,7 @@ impl ProcessBuilder {
                 password
             };
 
+            sudo_password.replace(password.clone());
             self.input_data = password.into_non_secret() + "\n" + &self.input_data;
         }
 
@@ -244,7 +552,9 @@ impl ProcessBuilder {
         }
 
         match self.settings.get_mode() {
-            ProcessMode::Local => self.spawn_local(password_to_cache, progress_handle)?,
+            ProcessMode::Local => {
+                self.spawn_local(password_to_cache, sudo_password, progress_handle)?
+            }
             ProcessMode::Container => {
                 const WAIT_SECONDS: u64 = 5;
                 const TIMEOUT_SECONDS: u64 = 3 * 60;
@@ -272,40 +582,69 @@ impl ProcessBuilder {
                     spin_sleep::sleep(Duration::from_secs(WAIT_SECONDS));
                 }
 
-                self.spawn_container(password_to_cache, progress_handle)?
+                self.spawn_container(password_to_cache, sudo_password, progress_handle)?
             }
             ProcessMode::Remote { node_name } => {
                 let mut current_session = current_ssh_session();
                 match &*current_session {
-                    Some((current_node, session)) if node_name == current_node => {
-                        self.spawn_remote(session, password_to_cache, progress_handle)?
-                    }
+                    Some((current_node, session)) if node_name == current_node => self
+                        .spawn_remote(session, password_to_cache, sudo_password, progress_handle)?,
                     _ => {
                         let host: IpAddr =
                             kv!("nodes/{node_name}/network/address").get()?.convert()?;
-                        let port = 22;
-                        let stream = TcpStream::connect(format!("{host}:{port}"))?;
-
-                        let mut session = ssh2::Session::new()?;
-                        session.set_tcp_stream(stream);
-                        session.handshake()?;
+                        let ssh_config = SshConfig::load(node_name)?;
+                        let session = ssh_config.connect(host)?;
 
                         let admin_username: String = kv!("admin/username").get()?.convert()?;
-                        let pub_key_file = files!("admin/ssh/pub").get()?;
-                        let priv_key_file = files!("admin/ssh/priv").get()?;
+                        let mut key_pairs = vec![KeyPair {
+                            public: files!("admin/ssh/pub").path()?,
+                            private: files!("admin/ssh/priv").path()?,
+                            has_passphrase: true,
+                        }];
+                        key_pairs.extend(KeyPair::user_defaults());
                         let password = get_remote_password()?;
                         password_to_cache.replace(password.clone());
 
-                        session.userauth_pubkey_file(
-                            &admin_username,
-                            Some(&pub_key_file.local_path),
-                            &priv_key_file.local_path,
-                            Some(&password.into_non_secret()),
+                        // The administrator key pair is protected by the administrator password,
+                        // unless another passphrase is provided.
+                        let passphrase = env::var("HOC_SSH_KEY_PASSPHRASE")
+                            .map_or_else(|_| password.clone(), Secret::new);
+                        authenticate_with_key_pairs(
+                            &key_pairs,
+                            |key_pair| {
+                                authenticate_with_key_pair(
+                                    key_pair,
+                                    &passphrase,
+                                    |key_pair, passphrase| {
+                                        session.userauth_pubkey_file(
+                                            &admin_username,
+                                            Some(&key_pair.public),
+                                            &key_pair.private,
+                                            passphrase,
+                                        )
+                                    },
+                                    || {
+                                        prompt!("[remote] SSH key passphrase")
+                                            .without_verification()
+                                            .hidden()
+                                            .get()
+                                    },
+                                )
+                            },
+                            || session.userauth_password(&admin_username, password.as_str()),
                         )?;
 
+                        // The timeout only applies to connecting, since processes may run for
+                        // a long time.
+                        session.set_timeout(0);
+
                         let node_name = node_name.clone();
-                        let process =
-                            self.spawn_remote(&session, password_to_cache, progress_handle);
+                        let process = self.spawn_remote(
+                            &session,
+                            password_to_cache,
+                            sudo_password,
+                            progress_handle,
+                        );
 
                         current_session.replace((node_name, session));
 
@@ -338,25 +677,35 @@ impl ProcessBuilder {
     fn spawn_local(
         self,
         password_to_cache: Option<Secret<String>>,
+        sudo_password: Option<Secret<String>>,
         progress_handle: ProgressHandle,
     ) -> Process {
-        let mut cmd = std::process::Command::new("sh");
-        cmd.args(["-c", &*util::get_prefixed_raw(&self)])
-            .envs(
-                self.settings
-                    .get_env_vars()
-                    .iter()
-                    .map(|(key, value)| (&**key, &**value.as_ref().unwrap_or(&Cow::Borrowed("")))),
-            )
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        let interpreter = self.settings.get_interpreter();
+        let raw = util::get_prefixed_raw(&self);
+
+        util::trace_script(
+            &format!("local command: {interpreter}"),
+            &raw,
+            sudo_password.as_ref(),
+        );
+        let mut cmd = util::interpreter_command(&interpreter, &raw)?;
+        cmd.envs(
+            self.settings
+                .get_env_vars()
+                .iter()
+                .map(|(key, value)| (&**key, &**value.as_ref().unwrap_or(&Cow::Borrowed("")))),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
 
         if let Some(current_dir) = self.settings.get_current_dir() {
             cmd.current_dir(&*current_dir);
         }
 
-        let mut child = cmd.spawn()?;
+        // In its own process group, the whole tree of processes can be suspended at once.
+        let mut child = cmd.process_group(0).spawn()?;
+        crate::log::suspend::register_child(child.id());
 
         let mut stdin = child.stdin.take().expect("stdin should not be taken");
         if !self.input_data.is_empty() {
@@ -382,6 +731,7 @@ impl ProcessBuilder {
     fn spawn_container(
         self,
         password_to_cache: Option<Secret<String>>,
+        sudo_password: Option<Secret<String>>,
         progress_handle: ProgressHandle,
     ) -> Process {
         let raw: Cow<_> = if let Some(current_dir) = &self.settings.get_current_dir() {
@@ -390,6 +740,8 @@ impl ProcessBuilder {
             util::get_prefixed_raw(&self)
         };
 
+        util::trace_script("container command: sh -c", &raw, sudo_password.as_ref());
+
         let mut cmd = std::process::Command::new("docker");
         cmd.args([
             "run",
@@ -432,7 +784,9 @@ impl ProcessBuilder {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .process_group(0)
             .spawn()?;
+        crate::log::suspend::register_child(child.id());
 
         let mut stdin = child.stdin.take().expect("stdin should not be taken");
         if !self.input_data.is_empty() {
@@ -459,6 +813,7 @@ impl ProcessBuilder {
         self,
         session: &ssh2::Session,
         password_to_cache: Option<Secret<String>>,
+        sudo_password: Option<Secret<String>>,
         progress_handle: ProgressHandle,
     ) -> Process {
         let mut channel = session.channel_session()?;
@@ -492,6 +847,10 @@ impl ProcessBuilder {
             raw
         };
 
+        trace!(
+            "Executing remote command: {}",
+            util::redact(&raw, sudo_password.as_ref())
+        );
         channel.exec(&raw)?;
 
         let stdout = Arc::new(Mutex::new(channel.stream(0)));
@@ -605,6 +964,26 @@ impl Process {
 
         self.progress_handle.finish();
 
+        if let Some(retry_policy) = self.builder.retry_policy.take() {
+            let builder = &self.builder;
+            output = retry_policy.retry(
+                output,
+                &builder.success_codes,
+                || {
+                    let process = builder
+                        .clone()
+                        .no_retry()
+                        .no_side_effects()
+                        .spawn_no_settings_update("Retrying process")?;
+                    match process.join() {
+                        Err(Error::Failed(output)) => Ok(output),
+                        result => result,
+                    }
+                },
+                thread::sleep,
+            )?;
+        }
+
         if self.builder.success_codes.contains(&output.code) {
             if let Some(password) = self.password_to_cache {
                 match self.builder.settings.get_mode() {
@@ -622,6 +1001,13 @@ impl Process {
                 .put(password)?;
             }
 
+            if let Some((key, capture)) = &self.builder.capture {
+                for (key, value) in capture.items(key, &output.stdout) {
+                    debug!("Capture output: {key:?}");
+                    kv!("{}", key.as_str()).put(value)?;
+                }
+            }
+
             if let Some(revert_process) = self.builder.revert_process {
                 let transaction = ledger::RevertibleProcess::new(
                     self.builder.raw,
@@ -785,7 +1171,9 @@ impl Handle {
 
         match self {
             Self::Cmd(mut child) => {
-                let status = child.wait()?;
+                let status = child.wait();
+                crate::log::suspend::unregister_child(child.id());
+                let status = status?;
                 let Some(code) = status.code() else {
                     throw!(Error::Terminated)
                 };
@@ -796,6 +1184,7 @@ impl Handle {
                 channel.close()?;
                 channel.wait_close()?;
                 output.code = channel.exit_status()?;
+                trace!("Remote command exited with status {}", output.code);
             }
             _ => (),
         }
@@ -986,6 +1375,146 @@ impl Shell<Running> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capture {
+    /// The trimmed output as a single string.
+    String,
+    /// An array with one string per non-empty line.
+    Lines,
+}
+
+impl Capture {
+    fn items(self, key: &Key, stdout: &str) -> Vec<(KeyOwned, String)> {
+        match self {
+            Self::String => vec![(key.to_owned(), stdout.trim().to_owned())],
+            Self::Lines => stdout
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .enumerate()
+                .map(|(index, line)| (key.join(&index.to_string()), line.to_owned()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    retryable_codes: Vec<i32>,
+}
+
+impl RetryPolicy {
+    #[allow(unused)]
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            retryable_codes: Vec::new(),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn retryable_codes<I: IntoIterator<Item = i32>>(mut self, retryable_codes: I) -> Self {
+        self.retryable_codes.extend(retryable_codes);
+        self
+    }
+
+    /// Returns the delay before the next attempt, or `None` if the failure should not be retried.
+    fn backoff(&self, attempt: u32, code: i32) -> Option<Duration> {
+        (attempt < self.max_attempts && self.retryable_codes.contains(&code))
+            .then(|| self.base_delay * 2u32.saturating_pow(attempt - 1))
+    }
+
+    #[throws(Error)]
+    fn retry<R, S>(
+        &self,
+        mut output: Output,
+        success_codes: &[i32],
+        mut rerun: R,
+        mut sleep: S,
+    ) -> Output
+    where
+        R: FnMut() -> Result<Output, Error>,
+        S: FnMut(Duration),
+    {
+        let mut attempt = 1;
+        while !success_codes.contains(&output.code) {
+            let Some(delay) = self.backoff(attempt, output.code) else {
+                break;
+            };
+
+            warn!(
+                "Process failed with exit code {}, retrying in {:.1}s (attempt {}/{})",
+                output.code,
+                delay.as_secs_f64(),
+                attempt + 1,
+                self.max_attempts,
+            );
+            sleep(delay);
+
+            attempt += 1;
+            output = rerun()?;
+        }
+
+        output
+    }
+}
+
+/// Runs a local program directly, without a shell, progress or retries. The output is streamed to
+/// the logger and captured, and errors carry the command line.
+#[allow(unused)]
+#[throws(Error)]
+pub fn run_local<I, S>(program: &str, args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let args: Vec<_> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_owned())
+        .collect();
+    let command = iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    debug!("Running local command: {command}");
+
+    let mut child = std::process::Command::new(program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::LocalCommandIo(command.clone(), err))?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let stderr_thread =
+        thread::spawn(move || util::read_lines(stderr, |line| debug!("[stderr] {line}")));
+    let stdout = util::read_lines(stdout, |line| debug!("[stdout] {line}"));
+    let stderr = stderr_thread.join().expect(EXPECT_THREAD_NOT_POSIONED);
+
+    let status = child
+        .wait()
+        .map_err(|err| Error::LocalCommandIo(command.clone(), err))?;
+    let Some(code) = status.code() else {
+        throw!(Error::Terminated);
+    };
+
+    let output = Output {
+        code,
+        stdout: stdout?,
+        stderr: stderr?,
+    };
+    if code != 0 {
+        throw!(Error::LocalCommandFailed(command, output));
+    }
+
+    output
+}
+
 #[derive(Debug)]
 pub struct Output {
     pub code: i32,
@@ -1013,6 +1542,7 @@ pub struct Settings {
     mode: Option<ProcessMode>,
     env_vars: EnvVarMap,
     prefix_env_vars: Option<bool>,
+    interpreter: Option<Cow<'static, str>>,
 }
 
 impl Settings {
@@ -1021,6 +1551,7 @@ impl Settings {
     const DEFAULT_CURRENT_DIR: Option<Cow<'static, str>> = None;
     const DEFAULT_MODE: ProcessMode = ProcessMode::Container;
     const DEFAULT_PREFIX_ENV_VARS: bool = false;
+    const DEFAULT_INTERPRETER: &'static str = "sh -c";
 
     fn new() -> Self {
         Self {
@@ -1030,6 +1561,7 @@ impl Settings {
             mode: None,
             env_vars: EnvVarMap::new(),
             prefix_env_vars: None,
+            interpreter: None,
         }
     }
 
@@ -1055,6 +1587,10 @@ impl Settings {
         if let Some(prefix_env_vars) = other.prefix_env_vars {
             self.prefix_env_vars.replace(prefix_env_vars);
         }
+
+        if let Some(interpreter) = &other.interpreter {
+            self.interpreter.replace(interpreter.clone());
+        }
     }
 
     pub fn sudo(&mut self) -> &mut Self {
@@ -1122,6 +1658,14 @@ impl Settings {
         self
     }
 
+    /// Sets the program and flags used to run local commands, e.g. `bash -eu -c`. The script is
+    /// passed as the last argument.
+    #[allow(unused)]
+    pub fn interpreter<I: Into<Cow<'static, str>>>(&mut self, interpreter: I) -> &mut Self {
+        self.interpreter.replace(interpreter.into());
+        self
+    }
+
     fn is_sudo(&self) -> bool {
         !matches!(self.get_mode(), ProcessMode::Container)
             && self.sudo.unwrap_or(Self::DEFAULT_SUDO)
@@ -1153,6 +1697,12 @@ impl Settings {
         self.prefix_env_vars
             .unwrap_or(Self::DEFAULT_PREFIX_ENV_VARS)
     }
+
+    fn get_interpreter(&self) -> Cow<'static, str> {
+        self.interpreter
+            .clone()
+            .unwrap_or(Cow::Borrowed(Self::DEFAULT_INTERPRETER))
+    }
 }
 
 #[derive(Default, Clone)]
@@ -1182,6 +1732,15 @@ pub enum Error {
     #[error("Unexpected end of input")]
     EndOfInput,
 
+    #[error("Command `{0}` failed with exit code {}: {}", _1.code, _1.stderr.trim())]
+    LocalCommandFailed(String, Output),
+
+    #[error("Failed to run command `{0}`: {1}")]
+    LocalCommandIo(String, io::Error),
+
+    #[error("Interpreter '{0}' was not found in PATH")]
+    InterpreterNotFound(String),
+
     #[error(transparent)]
     Prompt(#[from] prompt::Error),
 
@@ -1194,6 +1753,15 @@ pub enum Error {
     #[error(transparent)]
     Transaction(anyhow::Error),
 
+    #[error("Unknown SSH option: {0}")]
+    UnknownSshOption(String),
+
+    #[error("Invalid host key policy: {0:?}")]
+    InvalidHostKeyPolicy(String),
+
+    #[error("Host key verification failed for {0}")]
+    HostKeyVerification(String),
+
     #[error(transparent)]
     Ssh(#[from] ssh2::Error),
 }
@@ -1211,6 +1779,33 @@ mod util {
         }
     }
 
+    #[throws(Error)]
+    pub fn interpreter_command(interpreter: &str, script: &str) -> std::process::Command {
+        let mut words = interpreter.split_whitespace();
+        let program = words.next().unwrap_or("sh");
+
+        if find_in_path(program).is_none() {
+            throw!(Error::InterpreterNotFound(program.to_owned()));
+        }
+
+        let mut cmd = std::process::Command::new(program);
+        cmd.args(words).arg(script);
+        cmd
+    }
+
+    fn find_in_path(program: &str) -> Option<PathBuf> {
+        if program.contains('/') {
+            let path = PathBuf::from(program);
+            return path.is_file().then_some(path);
+        }
+
+        env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|path| path.is_file())
+        })
+    }
+
     pub fn get_prefixed_raw(process: &ProcessBuilder) -> Cow<'static, str> {
         let maybe_sudo = if process.settings.is_sudo() {
             "sudo -kSp '' "
@@ -1235,6 +1830,19 @@ mod util {
         format!("{maybe_sudo}{env_vars}{}", process.raw).into()
     }
 
+    /// Replaces every occurrence of the secret in `raw` with a placeholder.
+    pub fn redact(raw: &str, secret: Option<&Secret<String>>) -> String {
+        match secret.filter(|secret| !secret.is_empty()) {
+            Some(secret) => raw.replace(secret.as_str(), &secret.to_string()),
+            None => raw.to_owned(),
+        }
+    }
+
+    /// Logs the full script about to be executed, with the secret redacted.
+    pub fn trace_script(description: &str, raw: &str, secret: Option<&Secret<String>>) {
+        trace!("Executing {description} {}", redact(raw, secret));
+    }
+
     #[throws(Error)]
     pub fn read_lines(reader: impl Read, print_line: impl Fn(&str)) -> String {
         let mut buf_reader = BufReader::new(reader);
@@ -1364,3 +1972,407 @@ mod ledger {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(code: i32) -> Output {
+        Output {
+            code,
+            ..Output::new()
+        }
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn run_local_captures_output() {
+        let output = run_local("echo", ["hello", "world"])?;
+        assert_eq!(output.code, 0);
+        assert_eq!(output.stdout, "hello world\n");
+    }
+
+    #[test]
+    fn run_local_errors_carry_command_line() {
+        let err = run_local("sh", ["-c", "echo oops >&2; exit 3"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command `sh -c echo oops >&2; exit 3` failed with exit code 3: oops",
+        );
+
+        let err = run_local("hoc-nonexistent-program", ["--flag"]).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::LocalCommandIo(command, _) if command == "hoc-nonexistent-program --flag",
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn ssh_config_node_overrides_defaults() {
+        let defaults = Item::Map(IndexMap::from([
+            ("port".to_owned(), Item::from(2222u16)),
+            ("keepalive_interval".to_owned(), Item::from(15u32)),
+            ("host_key_policy".to_owned(), Item::from("strict")),
+        ]));
+        let node = Item::Map(IndexMap::from([
+            ("port".to_owned(), Item::from(22u16)),
+            ("timeout".to_owned(), Item::from(0u64)),
+        ]));
+
+        let config = SshConfig::from_layers([defaults.clone()])?;
+        assert_eq!(config.port, 2222);
+        assert_eq!(config.timeout, SshConfig::default().timeout);
+
+        let config = SshConfig::from_layers([defaults, node])?;
+        assert_eq!(
+            config,
+            SshConfig {
+                port: 22,
+                timeout: None,
+                keepalive_interval: Some(15),
+                host_key_policy: HostKeyPolicy::Strict,
+            }
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn ssh_config_is_loaded_from_kv() {
+        let mut kv = Kv::new();
+        let options = PutOptions::default();
+        kv.put_value("ssh/defaults/port", 2222u16, options)?;
+        kv.put_value("ssh/defaults/keepalive_interval", 15u32, options)?;
+        kv.put_value("nodes/node-1/ssh/port", 22u16, options)?;
+        kv.put_value("nodes/node-2/ssh/timeout", 0u64, options)?;
+
+        let config = SshConfig::load_from(&kv, "node-1")?;
+        assert_eq!(config.port, 22);
+        assert_eq!(config.timeout, SshConfig::default().timeout);
+        assert_eq!(config.keepalive_interval, Some(15));
+
+        let config = SshConfig::load_from(&kv, "node-3")?;
+        assert_eq!(config.port, 2222);
+    }
+
+    #[test]
+    fn ssh_config_rejects_unknown_options() {
+        let node = Item::Map(IndexMap::from([("prot".to_owned(), Item::from(22u16))]));
+        assert!(matches!(
+            SshConfig::from_layers([node]),
+            Err(Error::UnknownSshOption(name)) if name == "prot",
+        ));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn retry_with_backoff() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100)).retryable_codes([100]);
+
+        let mut codes = vec![0, 100].into_iter();
+        let mut delays = Vec::new();
+        let result = policy.retry(
+            output(100),
+            &[0],
+            || Ok(output(codes.next_back().unwrap())),
+            |delay| delays.push(delay),
+        )?;
+        assert_eq!(result.code, 0);
+        assert_eq!(
+            delays,
+            [Duration::from_millis(100), Duration::from_millis(200)]
+        );
+
+        let mut reruns = 0;
+        let result = policy.retry(
+            output(1),
+            &[0],
+            || {
+                reruns += 1;
+                Ok(output(0))
+            },
+            |_| (),
+        )?;
+        assert_eq!(result.code, 1);
+        assert_eq!(reruns, 0);
+
+        let result = policy.retry(output(100), &[0], || Ok(output(100)), |_| ())?;
+        assert_eq!(result.code, 100);
+    }
+
+    #[test]
+    #[throws(anyhow::Error)]
+    fn successful_retry_records_one_transaction() {
+        let test_dir = util::TempDir::new()?;
+        let marker = test_dir.path().join("failed-once");
+        let marker = marker.to_string_lossy();
+
+        process!("test -e {marker} || {{ touch {marker}; exit 100; }}")
+            .local_mode()
+            .retry_policy(RetryPolicy::new(2, Duration::from_millis(1)).retryable_codes([100]))
+            .revertible(process!("rm {marker}").local_mode())
+            .run()?;
+
+        let recorded = Ledger::get_or_init()
+            .details()
+            .into_iter()
+            .filter(|detail| detail.contains(&*marker))
+            .count();
+        assert_eq!(recorded, 1);
+    }
+
+    #[test]
+    fn captured_output_keys() {
+        let key = Key::new("nodes/betsy/addresses");
+        let stdout = "10.0.0.1\n\n10.0.0.2\n";
+
+        let items: Vec<_> = Capture::String
+            .items(key, stdout)
+            .into_iter()
+            .map(|(key, value)| (key.as_str().to_owned(), value))
+            .collect();
+        assert_eq!(
+            items,
+            [(
+                "nodes/betsy/addresses".to_owned(),
+                "10.0.0.1\n\n10.0.0.2".to_owned()
+            )]
+        );
+
+        let items: Vec<_> = Capture::Lines
+            .items(key, stdout)
+            .into_iter()
+            .map(|(key, value)| (key.as_str().to_owned(), value))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                ("nodes/betsy/addresses/0".to_owned(), "10.0.0.1".to_owned()),
+                ("nodes/betsy/addresses/1".to_owned(), "10.0.0.2".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn captured_output_is_stored() {
+        let key = format!(
+            "test/{}/addresses",
+            util::random_string(util::RAND_CHARS, 10)
+        );
+
+        process!("echo 10.0.0.1; echo; echo 10.0.0.2")
+            .local_mode()
+            .capture_stdout(key.clone(), Capture::Lines)
+            .run()?;
+
+        let first: String = kv!("{key}/0").get()?.convert()?;
+        let second: String = kv!("{key}/1").get()?.convert()?;
+        assert_eq!(first, "10.0.0.1");
+        assert_eq!(second, "10.0.0.2");
+        assert!(!kv!("{key}/2").exists());
+    }
+
+    #[test]
+    fn passphrase_is_asked_again_after_failure() {
+        let auth_failed = || {
+            ssh2::Error::new(
+                ssh2::ErrorCode::Session(LIBSSH2_ERROR_FILE),
+                "wrong passphrase",
+            )
+        };
+
+        let mut tried = Vec::new();
+        let mut answers = vec!["correct", "wrong"];
+        authenticate_with_passphrase(
+            Secret::new("initial".to_owned()),
+            |passphrase| {
+                tried.push(passphrase.to_owned());
+                (passphrase == "correct")
+                    .then_some(())
+                    .ok_or_else(auth_failed)
+            },
+            || Ok(Secret::new(answers.pop().unwrap().to_owned())),
+        )
+        .unwrap();
+        assert_eq!(tried, ["initial", "wrong", "correct"]);
+
+        let mut asked = 0;
+        let res = authenticate_with_passphrase(
+            Secret::new("initial".to_owned()),
+            |_| Err(auth_failed()),
+            || {
+                asked += 1;
+                Ok(Secret::new("wrong".to_owned()))
+            },
+        );
+        assert!(matches!(res, Err(Error::Ssh(_))));
+        assert_eq!(asked, MAX_PASSPHRASE_ATTEMPTS - 1);
+
+        let mut asked = 0;
+        let res = authenticate_with_passphrase(
+            Secret::new("initial".to_owned()),
+            |_| {
+                Err(ssh2::Error::new(
+                    ssh2::ErrorCode::Session(-18),
+                    "auth failed",
+                ))
+            },
+            || {
+                asked += 1;
+                Ok(Secret::new("wrong".to_owned()))
+            },
+        );
+        assert!(matches!(res, Err(Error::Ssh(_))));
+        assert_eq!(asked, 0);
+    }
+
+    #[test]
+    fn key_pairs_are_tried_in_order() {
+        let auth_failed = || ssh2::Error::new(ssh2::ErrorCode::Session(-18), "auth failed");
+        let key_pair = |name: &str| KeyPair {
+            public: PathBuf::from(format!("{name}.pub")),
+            private: PathBuf::from(name),
+            has_passphrase: false,
+        };
+        let key_pairs = [key_pair("first"), key_pair("second"), key_pair("third")];
+
+        let mut tried = Vec::new();
+        authenticate_with_key_pairs(
+            &key_pairs,
+            |key_pair| {
+                tried.push(key_pair.private.clone());
+                (key_pair.private == PathBuf::from("second"))
+                    .then_some(())
+                    .ok_or_else(|| Error::Ssh(auth_failed()))
+            },
+            || panic!("password should not be tried"),
+        )
+        .unwrap();
+        assert_eq!(tried, [PathBuf::from("first"), PathBuf::from("second")]);
+
+        let mut password_tried = false;
+        authenticate_with_key_pairs(
+            &key_pairs,
+            |_| Err(Error::Ssh(auth_failed())),
+            || {
+                password_tried = true;
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert!(password_tried);
+
+        let res = authenticate_with_key_pairs(
+            &key_pairs,
+            |_| Err(Error::Ssh(auth_failed())),
+            || Err(auth_failed()),
+        );
+        assert!(matches!(res, Err(Error::Ssh(_))));
+    }
+
+    #[test]
+    fn rejected_key_pair_is_skipped_without_asking() {
+        let key_pairs = [
+            KeyPair {
+                public: PathBuf::from("admin.pub"),
+                private: PathBuf::from("admin"),
+                has_passphrase: true,
+            },
+            KeyPair {
+                public: PathBuf::from("id_ed25519.pub"),
+                private: PathBuf::from("id_ed25519"),
+                has_passphrase: false,
+            },
+        ];
+        let passphrase = Secret::new("passphrase".to_owned());
+
+        let mut tried = Vec::new();
+        authenticate_with_key_pairs(
+            &key_pairs,
+            |key_pair| {
+                authenticate_with_key_pair(
+                    key_pair,
+                    &passphrase,
+                    |key_pair, passphrase| {
+                        tried.push((key_pair.private.clone(), passphrase.map(str::to_owned)));
+                        if key_pair.has_passphrase {
+                            Err(ssh2::Error::new(
+                                ssh2::ErrorCode::Session(-18),
+                                "auth failed",
+                            ))
+                        } else {
+                            Ok(())
+                        }
+                    },
+                    || panic!("passphrase should not be asked for"),
+                )
+            },
+            || panic!("password should not be tried"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            tried,
+            [
+                (PathBuf::from("admin"), Some("passphrase".to_owned())),
+                (PathBuf::from("id_ed25519"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn redact_sudo_password() {
+        let password = Secret::new("hunter2".to_owned());
+        let raw = "sudo -kSp '' reboot <<'EOT-abc'\nhunter2\nEOT-abc";
+
+        let redacted = util::redact(raw, Some(&password));
+        assert!(!redacted.contains("hunter2"));
+        assert_eq!(
+            redacted,
+            "sudo -kSp '' reboot <<'EOT-abc'\n********\nEOT-abc"
+        );
+
+        assert_eq!(util::redact(raw, None), raw);
+    }
+
+    #[test]
+    fn prefixed_script_is_complete() {
+        let process = ProcessBuilder::new("reboot")
+            .sudo()
+            .env_var("DELAY", Some("10"))
+            .prefix_env_vars();
+
+        let raw = util::get_prefixed_raw(&process);
+        assert_eq!(raw, "sudo -kSp '' DELAY=10 reboot");
+
+        let ((), logs) =
+            crate::log::capture(|| util::trace_script("local command: sh -c", &raw, None));
+        assert_eq!(
+            logs,
+            [(
+                Level::Trace,
+                "Executing local command: sh -c sudo -kSp '' DELAY=10 reboot".to_owned(),
+            )]
+        );
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn custom_interpreter_runs_script() {
+        let mut cmd = util::interpreter_command("sh -e -c", "echo custom")?;
+        assert_eq!(cmd.get_program(), "sh");
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            ["-e", "-c", "echo custom"]
+        );
+
+        let output = cmd.output()?;
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "custom\n");
+
+        assert!(matches!(
+            util::interpreter_command("hoc-missing-shell -c", "true"),
+            Err(Error::InterpreterNotFound(program)) if program == "hoc-missing-shell",
+        ));
+    }
+}
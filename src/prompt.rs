@@ -1,10 +1,13 @@
 use std::{
     borrow::Cow,
     fmt::{Debug, Display},
+    io,
     marker::PhantomData,
     str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+use crossterm::tty::IsTty;
 use inquire::{
     error::CustomUserError,
     ui::{Color, RenderConfig, StyleSheet},
@@ -13,7 +16,18 @@ use inquire::{
 };
 use thiserror::Error;
 
-use crate::{log, prelude::*};
+use crate::{log, prelude::*, util::Opt};
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Makes prompts with a default value and yes/no selections answer themselves.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::SeqCst);
+}
+
+fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::SeqCst)
+}
 
 fn postpad(lines: u16) {
     for _ in 0..lines {
@@ -28,6 +42,7 @@ pub struct PromptBuilder<'a, T, S> {
     help_message: Option<&'a str>,
     verification: bool,
     hidden: bool,
+    validator: Option<fn(&str) -> Result<(), String>>,
     _output_type: PhantomData<T>,
     _state: PhantomData<S>,
 }
@@ -44,6 +59,7 @@ impl<'a, T> PromptBuilder<'a, T, NonSecret> {
             help_message: None,
             verification: true,
             hidden: false,
+            validator: None,
             _output_type: Default::default(),
             _state: Default::default(),
         }
@@ -63,6 +79,7 @@ impl<'a, T, S> PromptBuilder<'a, T, S> {
             help_message: self.help_message,
             verification: self.verification,
             hidden: self.hidden,
+            validator: self.validator,
             _output_type: self._output_type,
             _state: Default::default(),
         }
@@ -83,6 +100,13 @@ impl<'a, T, S> PromptBuilder<'a, T, S> {
         self
     }
 
+    /// Rejects input, including the default value, for which the validator returns an error.
+    #[allow(unused)]
+    pub fn with_validator(mut self, validator: fn(&str) -> Result<(), String>) -> Self {
+        self.validator.replace(validator);
+        self
+    }
+
     pub fn without_verification(mut self) -> PromptBuilder<'a, T, AsSecret> {
         self.verification = false;
         self.convert()
@@ -101,54 +125,77 @@ where
 {
     #[throws(Error)]
     pub fn get(self) -> T {
+        let assume_yes = assume_yes();
+        if self.default.is_some() && assume_yes || !io::stdin().is_tty() {
+            return self.get_non_interactive(assume_yes)?;
+        }
+
         let prompt = format!("{}:", self.message);
 
         let pause_height = 2 + self.help_message.map_or(0, |_| 1);
+        log::emit_event(log::Event::WaitingForInput {
+            message: &self.message,
+        });
         let pause_lock = log::pause_rendering(pause_height)?;
 
-        let default_clone = self.default.clone();
-        let validator = move |s: &str| {
-            let s = s.trim();
-            if s.is_empty() {
-                return if let Some(default) = &default_clone {
-                    match T::from_str(default) {
-                        Ok(_) => Ok(Validation::Valid),
-                        Err(_) => Err(Box::new(InvalidDefaultError(default.clone().into_owned()))
-                            as CustomUserError),
+        // A function pointer does not capture `T`, so it can be sent to the prompt thread.
+        let parse: fn(&str) -> Option<String> = |s| T::from_str(s).err().map(|e| e.to_string());
+        let validator = self.validator;
+        let check = move |s: &str| parse(s).or_else(|| validator?(s).err());
+
+        let default = self.default.clone();
+        let initial_input = self.initial_input.map(str::to_owned);
+        let help_message = self.help_message.map(str::to_owned);
+        let indentation = pause_lock.indentation() as u16;
+
+        let res = pause_lock
+            .wait_for(move || {
+                let default_clone = default.clone();
+                let validator = move |s: &str| {
+                    let s = s.trim();
+                    if s.is_empty() {
+                        return if let Some(default) = &default_clone {
+                            match check(default) {
+                                None => Ok(Validation::Valid),
+                                Some(_) => {
+                                    Err(Box::new(InvalidDefaultError(default.clone().into_owned()))
+                                        as CustomUserError)
+                                }
+                            }
+                        } else {
+                            Ok(Validation::Invalid(ErrorMessage::Custom(
+                                "input must not be empty".to_owned(),
+                            )))
+                        };
                     }
-                } else {
-                    Ok(Validation::Invalid(ErrorMessage::Custom(
-                        "input must not be empty".to_owned(),
-                    )))
-                };
-            }
 
-            match T::from_str(s) {
-                Ok(_) => Ok(Validation::Valid),
-                Err(err) => Ok(Validation::Invalid(ErrorMessage::Custom(err.to_string()))),
-            }
-        };
+                    match check(s) {
+                        None => Ok(Validation::Valid),
+                        Some(err) => Ok(Validation::Invalid(ErrorMessage::Custom(err))),
+                    }
+                };
 
-        let render_config =
-            RenderConfig::default().with_global_indentation(pause_lock.indentation() as u16);
+                let render_config = RenderConfig::default().with_global_indentation(indentation);
 
-        let mut text = Text::new(&prompt)
-            .with_render_config(render_config)
-            .with_validator(validator);
+                let mut text = Text::new(&prompt)
+                    .with_render_config(render_config)
+                    .with_validator(validator);
 
-        if let Some(default) = &self.default {
-            text = text.with_default(default);
-        }
+                if let Some(default) = &default {
+                    text = text.with_default(default);
+                }
 
-        if let Some(initial_input) = self.initial_input {
-            text = text.with_initial_value(initial_input);
-        }
+                if let Some(initial_input) = &initial_input {
+                    text = text.with_initial_value(initial_input);
+                }
 
-        if let Some(help_message) = self.help_message {
-            text = text.with_help_message(help_message);
-        }
+                if let Some(help_message) = &help_message {
+                    text = text.with_help_message(help_message);
+                }
 
-        let res = text.prompt();
+                text.prompt()
+            })
+            .ok_or(Error::Canceled)?;
 
         match res {
             Ok(resp) => {
@@ -173,6 +220,30 @@ where
             }
         }
     }
+
+    /// Answers with the default value without prompting.
+    #[throws(Error)]
+    fn get_non_interactive(self, assume_yes: bool) -> T {
+        let Some(default) = self.default else {
+            throw!(Error::InputRequired(self.message.into_owned()));
+        };
+
+        if let Some(err) = self
+            .validator
+            .and_then(|validator| validator(&default).err())
+        {
+            throw!(InvalidDefaultError(format!("{default}: {err}")));
+        }
+        let value = T::from_str(&default)
+            .map_err(|err| InvalidDefaultError(format!("{default}: {err}")))?;
+
+        if assume_yes {
+            info!("{}: {default} (assumed)", self.message);
+        } else {
+            info!("{}: {default} (default)", self.message);
+        }
+        value
+    }
 }
 
 impl<'a, T> PromptBuilder<'a, T, AsSecret>
@@ -184,46 +255,61 @@ where
     pub fn get(self) -> Secret<T> {
         let extra_pause_height = self.verification as usize + !self.hidden as usize;
         let pause_height = 2 + extra_pause_height;
+        log::emit_event(log::Event::WaitingForInput {
+            message: &self.message,
+        });
         let pause_lock = log::pause_rendering(pause_height)?;
 
         let prompt = format!("{}:", self.message);
         let prompt_confirm = format!("{} (confirm):", self.message);
-        let validator = move |s: &str| {
-            let s = s.trim();
-            if s.is_empty() {
-                return Ok(Validation::Invalid(ErrorMessage::Custom(
-                    "input must not be empty".to_owned(),
-                )));
-            }
 
-            match T::from_str(s) {
-                Ok(_) => Ok(Validation::Valid),
-                Err(err) => Ok(Validation::Invalid(ErrorMessage::Custom(err.to_string()))),
-            }
-        };
+        // A function pointer does not capture `T`, so it can be sent to the prompt thread.
+        let check: fn(&str) -> Option<String> = |s| T::from_str(s).err().map(|e| e.to_string());
+
+        let verification = self.verification;
+        let hidden = self.hidden;
+        let indentation = pause_lock.indentation() as u16;
+
+        let res = pause_lock
+            .wait_for(move || {
+                let validator = move |s: &str| {
+                    let s = s.trim();
+                    if s.is_empty() {
+                        return Ok(Validation::Invalid(ErrorMessage::Custom(
+                            "input must not be empty".to_owned(),
+                        )));
+                    }
 
-        let render_config = RenderConfig::default()
-            .with_global_indentation(pause_lock.indentation() as u16)
-            .with_help_message(StyleSheet::default().with_fg(Color::DarkBlue));
+                    match check(s) {
+                        None => Ok(Validation::Valid),
+                        Some(err) => Ok(Validation::Invalid(ErrorMessage::Custom(err))),
+                    }
+                };
 
-        let mut text = Password::new(&prompt)
-            .with_render_config(render_config)
-            .with_custom_confirmation_message(&prompt_confirm)
-            .with_validator(validator);
+                let render_config = RenderConfig::default()
+                    .with_global_indentation(indentation)
+                    .with_help_message(StyleSheet::default().with_fg(Color::DarkBlue));
 
-        if !self.verification {
-            text = text.without_confirmation();
-        }
+                let mut text = Password::new(&prompt)
+                    .with_render_config(render_config)
+                    .with_custom_confirmation_message(&prompt_confirm)
+                    .with_validator(validator);
 
-        text = if self.hidden {
-            text.with_display_mode(PasswordDisplayMode::Hidden)
-        } else {
-            text.with_display_mode(PasswordDisplayMode::Masked)
-                .with_help_message("Ctrl-R to reveal/hide")
-                .with_display_toggle_enabled()
-        };
+                if !verification {
+                    text = text.without_confirmation();
+                }
 
-        let res = text.prompt();
+                text = if hidden {
+                    text.with_display_mode(PasswordDisplayMode::Hidden)
+                } else {
+                    text.with_display_mode(PasswordDisplayMode::Masked)
+                        .with_help_message("Ctrl-R to reveal/hide")
+                        .with_display_toggle_enabled()
+                };
+
+                text.prompt()
+            })
+            .ok_or(Error::Canceled)?;
         postpad(extra_pause_height as u16);
 
         let secret = Secret::new(T::from_str(res?.trim()).unwrap_or_else(|_| unreachable!()));
@@ -236,6 +322,7 @@ where
 pub struct SelectBuilder<T> {
     message: Cow<'static, str>,
     options: Vec<T>,
+    default: Option<usize>,
 }
 
 impl<T> SelectBuilder<T> {
@@ -243,6 +330,7 @@ impl<T> SelectBuilder<T> {
         Self {
             message: message.into(),
             options: Vec::with_capacity(1),
+            default: None,
         }
     }
 
@@ -256,6 +344,15 @@ impl<T> SelectBuilder<T> {
         self
     }
 
+    /// Adds an option which is selected initially, and chosen without prompting when not running
+    /// interactively.
+    #[allow(unused)]
+    pub fn with_default(mut self, option: T) -> Self {
+        self.default.replace(self.options.len());
+        self.options.push(option);
+        self
+    }
+
     pub fn option_count(&self) -> usize {
         self.options.len()
     }
@@ -270,21 +367,79 @@ impl<T: Display> SelectBuilder<T> {
             return self.options.remove(0);
         }
 
-        let pause_lock = log::pause_rendering(2 + num_options)?;
+        let assume_yes = assume_yes();
+        if assume_yes || !io::stdin().is_tty() {
+            return self.get_non_interactive(assume_yes)?;
+        }
 
-        let render_config =
-            RenderConfig::default().with_global_indentation(pause_lock.indentation() as u16);
+        log::emit_event(log::Event::WaitingForInput {
+            message: &self.message,
+        });
+        let pause_lock = log::pause_rendering(2 + num_options)?;
 
-        let option = Select::new(&self.message, self.options)
-            .with_render_config(render_config)
-            .prompt();
+        let message = self.message.clone();
+        let displayed: Vec<_> = self.options.iter().map(ToString::to_string).collect();
+        let starting_cursor = self.default.unwrap_or(0);
+        let indentation = pause_lock.indentation() as u16;
+
+        let index = pause_lock
+            .wait_for(move || {
+                let render_config = RenderConfig::default().with_global_indentation(indentation);
+
+                Select::new(&message, displayed)
+                    .with_render_config(render_config)
+                    .with_starting_cursor(starting_cursor)
+                    .raw_prompt()
+                    .map(|option| option.index)
+            })
+            .ok_or(Error::Canceled)?;
         postpad(num_options as u16);
 
-        let option = option?;
+        let option = self.options.remove(index?);
         pause_lock.finish_with_message(Level::Info, format!("{} {}", self.message, option));
 
         option
     }
+
+    /// Chooses an option without prompting: "Yes" if answers are assumed, otherwise the default.
+    #[throws(Error)]
+    fn get_non_interactive(mut self, assume_yes: bool) -> T {
+        if assume_yes {
+            let yes = Opt::Yes.to_string();
+            if let Some(index) = self.options.iter().position(|o| o.to_string() == yes) {
+                let option = self.options.remove(index);
+                info!("{} {option} (assumed)", self.message);
+                return option;
+            }
+        }
+
+        let Some(index) = self.default else {
+            throw!(Error::SelectionRequired(self.message.into_owned()));
+        };
+
+        let option = self.options.remove(index);
+        info!("{} {option} (default)", self.message);
+        option
+    }
+}
+
+/// Asks the user to type `expected` in order to confirm an irreversible operation.
+#[throws(Error)]
+pub fn confirm_destructive(message: impl Into<Cow<'static, str>>, expected: &str) -> bool {
+    let help_message = format!("Type '{expected}' to confirm");
+    check_confirmation(
+        || {
+            PromptBuilder::new(message)
+                .with_help_message(&help_message)
+                .get()
+        },
+        expected,
+    )?
+}
+
+#[throws(Error)]
+fn check_confirmation(get_input: impl FnOnce() -> Result<String, Error>, expected: &str) -> bool {
+    get_input()?.trim() == expected
 }
 
 #[derive(Debug, Error)]
@@ -297,7 +452,31 @@ pub enum Error {
     Log(#[from] log::Error),
 
     #[error(transparent)]
-    Inquire(#[from] inquire::InquireError),
+    Inquire(inquire::InquireError),
+
+    #[error("The prompt was canceled")]
+    Canceled,
+
+    #[error("Selection required but not running interactively: {0}")]
+    SelectionRequired(String),
+
+    #[error("Input required but not running interactively: {0}")]
+    InputRequired(String),
+
+    #[error(transparent)]
+    InvalidDefault(#[from] InvalidDefaultError),
+}
+
+impl From<inquire::InquireError> for Error {
+    /// Canceling and interrupting a prompt both become [`Error::Canceled`], so callers only have
+    /// to check for one kind of cancellation.
+    fn from(err: inquire::InquireError) -> Self {
+        match err {
+            inquire::InquireError::OperationCanceled
+            | inquire::InquireError::OperationInterrupted => Self::Canceled,
+            err => Self::Inquire(err),
+        }
+    }
 }
 
 impl<T> private::Sealed for Option<T> {}
@@ -305,3 +484,103 @@ impl<T> private::Sealed for Option<T> {}
 mod private {
     pub trait Sealed {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancellation_is_an_error() {
+        let res = check_confirmation(
+            || Err(inquire::InquireError::OperationInterrupted.into()),
+            "disk2",
+        );
+        assert!(matches!(res, Err(Error::Canceled)));
+
+        let err = Error::from(inquire::InquireError::OperationCanceled);
+        assert!(matches!(err, Error::Canceled));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn select_returns_item() {
+        let item = SelectBuilder::new("Which item?")
+            .with_option(String::from("only"))
+            .get()?;
+        assert_eq!(item, "only");
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn assume_yes_confirms() {
+        let opt = SelectBuilder::new("Do you want to proceed?")
+            .with_options([Opt::Yes, Opt::No])
+            .get_non_interactive(true)?;
+        let name: String = PromptBuilder::new("Name")
+            .with_default("hoc")
+            .get_non_interactive(true)?;
+
+        assert!(opt == Opt::Yes);
+        assert_eq!(name, "hoc");
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn non_interactive_prompt_uses_default() {
+        let port: u16 = PromptBuilder::new("Port")
+            .with_default("8080")
+            .get_non_interactive(false)?;
+        assert_eq!(port, 8080);
+    }
+
+    #[test]
+    fn non_interactive_prompt_without_default_fails() {
+        let res: Result<String, _> = PromptBuilder::new("Name").get_non_interactive(false);
+        assert!(matches!(res, Err(Error::InputRequired(_))));
+    }
+
+    #[test]
+    fn non_interactive_prompt_validates_default() {
+        let res: Result<String, _> = PromptBuilder::new("Name")
+            .with_default("Hoc")
+            .with_validator(|s| match s.chars().all(|c| c.is_ascii_lowercase()) {
+                true => Ok(()),
+                false => Err("must be lowercase".to_owned()),
+            })
+            .get_non_interactive(false);
+        assert!(matches!(res, Err(Error::InvalidDefault(_))));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn non_interactive_select_uses_default() {
+        let opt = SelectBuilder::new("How do you want to proceed?")
+            .with_option(Opt::Skip)
+            .with_default(Opt::Retry)
+            .get_non_interactive(false)?;
+        assert!(opt == Opt::Retry);
+    }
+
+    #[test]
+    fn non_interactive_select_without_default_fails() {
+        let res = SelectBuilder::new("Which disk is your SD card?")
+            .with_options(["sda", "sdb"])
+            .get_non_interactive(false);
+        assert!(matches!(res, Err(Error::SelectionRequired(_))));
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn confirmation_matches_token() {
+        assert!(check_confirmation(|| Ok("sdb".to_owned()), "sdb")?);
+        assert!(check_confirmation(|| Ok(" sdb ".to_owned()), "sdb")?);
+    }
+
+    #[test]
+    #[throws(Error)]
+    fn confirmation_mismatches_token() {
+        assert!(!check_confirmation(|| Ok("sda".to_owned()), "sdb")?);
+        assert!(!check_confirmation(|| Ok("SDB".to_owned()), "sdb")?);
+        assert!(!check_confirmation(|| Ok("y".to_owned()), "sdb")?);
+    }
+}
@@ -313,6 +313,68 @@ pub fn random_string(source: &str, len: usize) -> String {
     sample.choose_multiple(&mut rng, len).collect()
 }
 
+/// A uniquely named directory in the system temp directory, which is removed when dropped.
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    #[throws(std::io::Error)]
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("hoc-test-{}", random_string(RAND_CHARS, 10)));
+        fs::create_dir_all(&path)?;
+        Self(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Creates a line-by-line diff between two texts. Removed lines are prefixed with `-`, added lines
+/// with `+`, and unchanged lines with a space.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old_lines: Vec<_> = old.lines().collect();
+    let new_lines: Vec<_> = new.lines().collect();
+
+    // Lengths of the longest common subsequences of the line suffixes.
+    let mut lcs = vec![vec![0; new_lines.len() + 1]; old_lines.len() + 1];
+    for i in (0..old_lines.len()).rev() {
+        for j in (0..new_lines.len()).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old_lines.len() || j < new_lines.len() {
+        if i < old_lines.len() && j < new_lines.len() && old_lines[i] == new_lines[j] {
+            diff += &format!(" {}\n", old_lines[i]);
+            i += 1;
+            j += 1;
+        } else if i < old_lines.len() && (j == new_lines.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff += &format!("-{}\n", old_lines[i]);
+            i += 1;
+        } else {
+            diff += &format!("+{}\n", new_lines[j]);
+            j += 1;
+        }
+    }
+
+    diff
+}
+
 #[throws(anyhow::Error)]
 pub fn get_attached_disks() -> Vec<DiskInfo> {
     match process!("uname").run()?.stdout.trim() {
@@ -656,4 +718,12 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn line_diffs() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nb\nc\n"), " a\n b\n c\n");
+        assert_eq!(line_diff("a\nb\nc\n", "a\nB\nc\n"), " a\n-b\n+B\n c\n");
+        assert_eq!(line_diff("", "a\n"), "+a\n");
+        assert_eq!(line_diff("a\nb\n", "b\n"), "-a\n b\n");
+    }
 }